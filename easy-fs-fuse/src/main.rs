@@ -8,6 +8,8 @@ use std::sync::Mutex;
 /// Use a block size of 512 bytes
 const BLOCK_SZ: usize = 512;
 const BLOCK_NUM: usize = 131072; //64*2048
+/// blocks left after the file system for the kernel's swap area, 4096 pages
+const SWAP_BLOCKS: usize = 32768;
 
 /// Wrapper for turning a File into a BlockDevice
 struct BlockFile(Mutex<File>);
@@ -60,7 +62,7 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f.set_len(((BLOCK_NUM + SWAP_BLOCKS) * BLOCK_SZ) as u64).unwrap();
         f
     })));
     let efs = EasyFileSystem::create(block_file.clone(), BLOCK_NUM as u32, 1);
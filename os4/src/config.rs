@@ -1,10 +1,81 @@
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// the user stack starts with `USER_STACK_SIZE` mapped and grows on faults up to this
+pub const USER_STACK_MAX_SIZE: usize = 4096 * 16;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 20;
+/// each hart starts on a boot stack of this size, `entry.asm` reserves them
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
+/// harts the kernel brings up, the others stay stopped; `entry.asm` reserves
+/// a boot stack for each
+pub const MAX_HARTS: usize = 4;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
 pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
+// sys_getpagesize, the mmap alignment checks and the paging code must agree
+const _: () = assert!(PAGE_SIZE == 1 << PAGE_SIZE_BITS);
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// distinct syscall ids a task keeps a count for, the calls of any further
+/// id only add to its overflow count
+pub const MAX_SYSCALL_IDS_COUNTED: usize = 64;
+pub const BIG_STRIDE: usize = 0x10_0000;
+pub const DEFAULT_PRIORITY: usize = 16;
+/// warn when a task yields more often than this within one timer tick, 0 disables the check
+pub const YIELD_LIVELOCK_THRESHOLD: usize = 64;
+/// kernel time a task may spend in syscalls within one time slice before it is preempted, in us
+pub const KERNEL_CHURN_LIMIT_US: usize = 5_000;
+/// cpu time a task may use after entering batch mode before it is killed, in us
+pub const BATCH_CPU_LIMIT_US: usize = 2_000_000;
+/// bytes a task may have mmapped at once, mmap past it fails with -ENOMEM
+pub const MMAP_QUOTA_BYTES: usize = 64 * 1024 * 1024;
+/// longest app name sys_spawn accepts, in bytes
+pub const MAX_APP_NAME_LEN: usize = 64;
+/// room sys_exec gives the argument and environment strings on the new user
+/// stack, in bytes, counting each string's nul and its pointer slot
+pub const MAX_ARG_BYTES: usize = 4096;
+/// longest path sys_open accepts, in bytes
+pub const MAX_PATH_LEN: usize = 256;
+/// events the kernel event log keeps before overwriting the oldest
+pub const EVENT_LOG_LEN: usize = 64;
+/// trace records each hart keeps before overwriting the oldest
+pub const TRACE_LEN: usize = 256;
+/// mmap at 0 without MAP_FIXED places the mapping in the first hole from here on,
+/// with `ASLR` from a random page of the `ASLR_MMAP_PAGES` above it
+pub const MMAP_AUTO_BASE: usize = 0x4000_0000;
+/// randomize where the user stack, the heap, auto-placed mmaps and
+/// position-independent executables go
+pub const ASLR: bool = true;
+/// position-independent executables are loaded at a random page of the
+/// `ASLR_PIE_PAGES` from here on, or right here without `ASLR`
+pub const PIE_BASE: usize = 0x2000_0000;
+/// pages a position-independent executable may be moved up by
+pub const ASLR_PIE_PAGES: usize = 0x1000;
+/// pages the user stack may be moved up by, away from the program below it
+pub const ASLR_STACK_PAGES: usize = 0x100;
+/// pages the mmap base may be moved up by
+pub const ASLR_MMAP_PAGES: usize = 0x1000;
+/// map never-written lazy pages to a shared zero frame instead of allocating a zeroed frame on first access
+pub const LAZY_ZERO_PAGE: bool = true;
+/// a fault on a page of an elf segment also loads the other pages of the segment in the
+/// same aligned block of this many pages, so a small program is in after a fault or two
+pub const ELF_FAULT_AROUND_PAGES: usize = 16;
+/// the swap area starts on the disk right after the easy-fs image, in blocks
+pub const SWAP_START_BLOCK: usize = 131072;
+/// page-sized slots in the swap area
+pub const SWAP_PAGES: usize = 4096;
+/// a page fault first swaps pages of the faulting task out until this many frames are free,
+/// enough for the page, the tables to map it and a copy on write
+pub const SWAP_LOW_FRAMES: usize = 8;
+/// with the `sched_audit` feature, look for starved tasks every this many timer ticks
+pub const SCHED_AUDIT_TICKS: usize = 100;
+/// a Ready task that has not run for this long counts as starved, in us
+pub const STARVATION_THRESHOLD_US: usize = 1_000_000;
+/// priority levels of the MLFQ scheduler, level 0 runs first
+pub const MLFQ_LEVELS: usize = 3;
+/// timer ticks a task gets at MLFQ level 0 before it is demoted, the slice doubles with each level
+pub const MLFQ_BASE_SLICE_TICKS: usize = 1;
+/// every this many timer ticks the MLFQ scheduler lifts every task back to level 0
+pub const MLFQ_BOOST_TICKS: usize = 100;
+pub const MIN_PRIORITY: usize = 2;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
@@ -16,3 +87,90 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
 }
 
 pub const CLOCK_FREQ: usize = 12500000;
+
+/// (start, len) of the device registers the kernel maps: the first virtio-mmio
+/// slot of qemu virt, the PLIC up to the claim registers of the last hart,
+/// the UART, then the sifive_test finisher
+#[cfg(not(feature = "gdbstub"))]
+pub const MMIO: &[(usize, usize)] = &[
+    (0x1000_1000, 0x1000),
+    (0x0C00_0000, 0x40_0000),
+    (0x1000_0000, 0x1000),
+    (0x0010_0000, 0x1000),
+];
+
+/// the same, and the PCI windows the UART of the gdb stub sits behind last
+#[cfg(feature = "gdbstub")]
+pub const MMIO: &[(usize, usize)] = &[
+    (0x1000_1000, 0x1000),
+    (0x0C00_0000, 0x40_0000),
+    (0x1000_0000, 0x1000),
+    (0x0010_0000, 0x1000),
+    PCI_ECAM,
+    PCI_IO,
+];
+
+/// (start, len) of the ECAM config space of PCIe bus 0 on qemu virt
+#[cfg(feature = "gdbstub")]
+pub const PCI_ECAM: (usize, usize) = (0x3000_0000, 0x10_0000);
+
+/// (start, len) of the PCI I/O port window on qemu virt
+#[cfg(feature = "gdbstub")]
+pub const PCI_IO: (usize, usize) = (0x0300_0000, 0x1_0000);
+
+/// (start, len) of the ns16550a of the gdb stub. qemu virt has only the
+/// console UART on its own, so the stub uses a `pci-serial` device, see the
+/// Makefile, and puts its I/O BAR here
+#[cfg(feature = "gdbstub")]
+pub const GDB_UART: (usize, usize) = (PCI_IO.0 + 0x1000, 8);
+
+/// What the kernel does once every application has exited
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CompletionPolicy {
+    /// end qemu with exit status 0, so CI sees it exit and pass
+    Shutdown,
+    /// stay up waiting for interrupts, for poking at the machine in a debugger
+    Idle,
+}
+
+/// the policy picked with `make run COMPLETION=IDLE`, shutting down by default
+pub fn completion_policy() -> CompletionPolicy {
+    parse_completion_policy(option_env!("COMPLETION"))
+}
+
+fn parse_completion_policy(name: Option<&str>) -> CompletionPolicy {
+    match name {
+        Some("IDLE") => CompletionPolicy::Idle,
+        _ => CompletionPolicy::Shutdown,
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// completing runs the shutdown hooks, then ends qemu with status 0 unless
+/// the policy says idle, anything unknown must still shut down
+pub fn completion_policy_test() {
+    use crate::shutdown::{self, ShutdownHook};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static HOOK_RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn count() {
+        HOOK_RUNS.fetch_add(1, Ordering::Relaxed);
+    }
+    let hook = ShutdownHook {
+        name: "completion_policy_test",
+        priority: 0,
+        func: count,
+    };
+    assert!(shutdown::register(hook));
+    assert_eq!(shutdown::complete(parse_completion_policy(None)), Some(0));
+    assert_eq!(HOOK_RUNS.load(Ordering::Relaxed), 1);
+    for name in ["SHUTDOWN", "SHELL"] {
+        assert_eq!(shutdown::complete(parse_completion_policy(Some(name))), Some(0));
+    }
+    // each hook runs once, idling runs the ones registered since
+    assert_eq!(HOOK_RUNS.load(Ordering::Relaxed), 1);
+    assert!(shutdown::register(hook));
+    assert_eq!(shutdown::complete(parse_completion_policy(Some("IDLE"))), None);
+    assert_eq!(HOOK_RUNS.load(Ordering::Relaxed), 2);
+    info!("completion_policy_test passed!");
+}
@@ -2,15 +2,15 @@
     本模块实现了 print 和 println 宏
 */
 
-use crate::sbi::console_putchar;
+use crate::drivers::uart;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+        for byte in s.bytes() {
+            uart::putchar(byte);
         }
         Ok(())
     }
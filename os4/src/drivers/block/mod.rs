@@ -0,0 +1,36 @@
+//! Block devices
+
+mod virtio_blk;
+
+pub use virtio_blk::VirtIOBlock;
+
+use alloc::sync::Arc;
+// the file system and the drivers share the trait, a block is a virtio-blk sector
+pub use easy_fs::{BlockDevice, BLOCK_SZ};
+use lazy_static::*;
+
+lazy_static! {
+    /// the disk qemu attaches as `virtio-blk-device`, probed on first use
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new());
+}
+
+#[allow(unused)]
+#[test_case]
+/// write a few blocks, read them back and restore what was there before
+pub fn block_device_test() {
+    let block_device = BLOCK_DEVICE.clone();
+    let mut saved = [0u8; BLOCK_SZ];
+    let mut write_buffer = [0u8; BLOCK_SZ];
+    let mut read_buffer = [0u8; BLOCK_SZ];
+    for i in 0..4 {
+        block_device.read_block(i, &mut saved);
+        for (j, byte) in write_buffer.iter_mut().enumerate() {
+            *byte = (i + j) as u8;
+        }
+        block_device.write_block(i, &write_buffer);
+        block_device.read_block(i, &mut read_buffer);
+        assert_eq!(write_buffer, read_buffer);
+        block_device.write_block(i, &saved);
+    }
+    info!("block_device_test passed!");
+}
@@ -0,0 +1,235 @@
+//! virtio-blk over the legacy virtio-mmio interface of qemu virt
+//!
+//! One request is in flight at a time and its completion is polled. The
+//! queue, the request header, the status byte and a bounce buffer for the
+//! data all live in frames from `frame_alloc_contiguous`. Physical memory is
+//! identity mapped in kernel space, so the addresses handed to the device
+//! are also the ones the driver dereferences.
+
+use super::{BlockDevice, BLOCK_SZ};
+use crate::config::{MMIO, PAGE_SIZE};
+use crate::mm::{frame_alloc_contiguous, FrameTracker, PhysAddr};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+// legacy (version 1) virtio-mmio registers
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const GUEST_FEATURES: usize = 0x020;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+
+/// "virt" read as a little endian u32
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+const DESC_F_NEXT: u16 = 1;
+/// the device writes the buffer instead of reading it
+const DESC_F_WRITE: u16 = 2;
+
+const REQ_IN: u32 = 0;
+const REQ_OUT: u32 = 1;
+const REQ_STATUS_OK: u8 = 0;
+
+/// descriptors in the queue, a request only ever uses the first three
+const QUEUE_SIZE: usize = 16;
+/// descriptors and available ring on the first page, used ring on the second
+const QUEUE_PAGES: usize = 2;
+// offsets into the page right after the queue
+const HEADER_OFFSET: usize = 0;
+const STATUS_OFFSET: usize = 16;
+const DATA_OFFSET: usize = BLOCK_SZ;
+
+// the device reads and writes these, the driver mostly never looks at the fields
+#[repr(C)]
+#[allow(dead_code)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct RequestHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+// with QueueAlign = PAGE_SIZE the used ring starts on the second page
+const _: () = assert!(
+    QUEUE_SIZE * size_of::<Descriptor>() + size_of::<AvailRing>() <= PAGE_SIZE
+        && size_of::<UsedRing>() <= PAGE_SIZE
+        && size_of::<RequestHeader>() <= STATUS_OFFSET
+);
+
+struct VirtIOBlockInner {
+    /// base address of the device registers
+    base: usize,
+    /// the queue pages followed by the request page, physically contiguous
+    frames: Vec<FrameTracker>,
+    /// value of the used ring index after the last completed request
+    last_used: u16,
+}
+
+/// The virtio-blk device in the first virtio-mmio slot.
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlockInner>);
+
+impl VirtIOBlock {
+    /// Reset the device and hand it a freshly allocated queue, panics if
+    /// there is no legacy virtio-blk device at `MMIO[0]`.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let frames =
+            frame_alloc_contiguous(QUEUE_PAGES + 1).expect("no frames left for the virtio queue");
+        let inner = VirtIOBlockInner {
+            base: MMIO[0].0,
+            frames,
+            last_used: 0,
+        };
+        assert!(
+            inner.read_reg(MAGIC_VALUE) == VIRTIO_MAGIC
+                && inner.read_reg(DEVICE_ID) == VIRTIO_DEVICE_BLOCK,
+            "no virtio-blk device at {:#x}",
+            inner.base
+        );
+        assert_eq!(inner.read_reg(VERSION), 1, "only legacy virtio-mmio is supported");
+        inner.write_reg(STATUS, 0);
+        inner.write_reg(STATUS, STATUS_ACKNOWLEDGE);
+        inner.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        // whole-block reads and writes need no optional feature
+        inner.write_reg(GUEST_FEATURES, 0);
+        inner.write_reg(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        inner.write_reg(QUEUE_SEL, 0);
+        assert!(inner.read_reg(QUEUE_NUM_MAX) as usize >= QUEUE_SIZE);
+        inner.write_reg(QUEUE_NUM, QUEUE_SIZE as u32);
+        inner.write_reg(QUEUE_ALIGN, PAGE_SIZE as u32);
+        inner.write_reg(QUEUE_PFN, inner.frames[0].ppn.0 as u32);
+        inner.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+        Self(unsafe { UPSafeCell::new(inner) })
+    }
+}
+
+impl VirtIOBlockInner {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+    /// Address of the byte `offset` bytes into the frames.
+    fn dma(&self, offset: usize) -> usize {
+        PhysAddr::from(self.frames[0].ppn).0 + offset
+    }
+    fn request_page(&self, offset: usize) -> usize {
+        self.dma(QUEUE_PAGES * PAGE_SIZE + offset)
+    }
+    /// The bounce buffer the block data goes through.
+    fn data(&self) -> &'static mut [u8] {
+        &mut self.frames[QUEUE_PAGES].ppn.get_bytes_array()[DATA_OFFSET..DATA_OFFSET + BLOCK_SZ]
+    }
+    /// Hand the device a request for `block_id` and spin until it is done.
+    fn request(&mut self, req_type: u32, block_id: usize) {
+        let header = RequestHeader {
+            req_type,
+            reserved: 0,
+            sector: block_id as u64,
+        };
+        let data_flags = match req_type {
+            REQ_IN => DESC_F_NEXT | DESC_F_WRITE,
+            _ => DESC_F_NEXT,
+        };
+        // header, data and status, chained through descriptors 0, 1 and 2
+        let chain = [
+            (HEADER_OFFSET, size_of::<RequestHeader>(), DESC_F_NEXT),
+            (DATA_OFFSET, BLOCK_SZ, data_flags),
+            (STATUS_OFFSET, 1, DESC_F_WRITE),
+        ];
+        let descriptors = self.dma(0) as *mut Descriptor;
+        let avail = self.dma(QUEUE_SIZE * size_of::<Descriptor>()) as *mut AvailRing;
+        let used = self.dma(PAGE_SIZE) as *const UsedRing;
+        unsafe {
+            write_volatile(self.request_page(HEADER_OFFSET) as *mut RequestHeader, header);
+            write_volatile(self.request_page(STATUS_OFFSET) as *mut u8, 0xff);
+            for (i, &(offset, len, flags)) in chain.iter().enumerate() {
+                let descriptor = Descriptor {
+                    addr: self.request_page(offset) as u64,
+                    len: len as u32,
+                    flags,
+                    next: if flags & DESC_F_NEXT != 0 { i as u16 + 1 } else { 0 },
+                };
+                write_volatile(descriptors.add(i), descriptor);
+            }
+            let idx = read_volatile(addr_of!((*avail).idx));
+            write_volatile(addr_of_mut!((*avail).ring[idx as usize % QUEUE_SIZE]), 0);
+            // the device must see the chain before the index that publishes it
+            fence(Ordering::SeqCst);
+            write_volatile(addr_of_mut!((*avail).idx), idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+            self.write_reg(QUEUE_NOTIFY, 0);
+            while read_volatile(addr_of!((*used).idx)) == self.last_used {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+        }
+        self.last_used = self.last_used.wrapping_add(1);
+        // nothing listens to the interrupt yet, acknowledge it all the same
+        self.write_reg(INTERRUPT_ACK, self.read_reg(INTERRUPT_STATUS));
+        let status = unsafe { read_volatile(self.request_page(STATUS_OFFSET) as *const u8) };
+        assert_eq!(status, REQ_STATUS_OK, "virtio-blk request on block {} failed", block_id);
+    }
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut inner = self.0.exclusive_access();
+        inner.request(REQ_IN, block_id);
+        buf.copy_from_slice(inner.data());
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut inner = self.0.exclusive_access();
+        inner.data().copy_from_slice(buf);
+        inner.request(REQ_OUT, block_id);
+    }
+}
@@ -0,0 +1,12 @@
+//! Device drivers
+//!
+//! Devices sit behind the memory-mapped registers listed in
+//! [`crate::config::MMIO`], which the kernel space maps identically, and
+//! raise their interrupts through the [`plic`].
+
+pub mod block;
+pub mod plic;
+pub mod qemu_exit;
+pub mod uart;
+
+pub use block::{BlockDevice, BLOCK_DEVICE};
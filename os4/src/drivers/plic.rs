@@ -0,0 +1,78 @@
+//! The platform-level interrupt controller of qemu virt
+//!
+//! Every device interrupt source goes through the PLIC, which hands it to
+//! one of the harts whose supervisor context enabled the source as a
+//! SupervisorExternal interrupt. The hart claims the source, runs the handler
+//! registered for it and completes it, after which the source may fire again.
+
+use crate::config::{MAX_HARTS, MMIO};
+use crate::smp::hart_id;
+use crate::sync::UPSafeCell;
+use core::ptr::{read_volatile, write_volatile};
+use lazy_static::*;
+
+/// sources handled, qemu virt puts its devices below this
+pub const MAX_IRQ: usize = 64;
+
+const PRIORITY: usize = 0x0;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const THRESHOLD: usize = 0x20_0000;
+const CLAIM_COMPLETE: usize = 0x20_0004;
+const CONTEXT_STRIDE: usize = 0x1000;
+
+lazy_static! {
+    /// what each source runs once it is claimed, by irq number
+    static ref HANDLERS: UPSafeCell<[Option<fn()>; MAX_IRQ]> =
+        unsafe { UPSafeCell::new([None; MAX_IRQ]) };
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (MMIO[1].0 + offset) as *mut u32
+}
+
+/// The supervisor context of `hart`, the machine one comes first.
+fn context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+/// Let every source through on this hart, what each source fires is left
+/// to its priority and enable bits.
+pub fn init_hart() {
+    unsafe {
+        write_volatile(reg(THRESHOLD + context(hart_id()) * CONTEXT_STRIDE), 0);
+    }
+}
+
+/// Run `handler` whenever `irq` fires, on whichever hart claims it first.
+pub fn register(irq: usize, handler: fn()) {
+    assert!(irq != 0 && irq < MAX_IRQ, "no such irq {}", irq);
+    HANDLERS.exclusive_access()[irq] = Some(handler);
+    unsafe {
+        write_volatile(reg(PRIORITY + irq * 4), 1);
+        for hart in 0..MAX_HARTS {
+            let enable = reg(ENABLE + context(hart) * ENABLE_STRIDE + irq / 32 * 4);
+            write_volatile(enable, read_volatile(enable) | 1 << (irq % 32));
+        }
+    }
+}
+
+/// Serve every source pending for this hart, on a SupervisorExternal trap.
+pub fn handle_interrupt() {
+    let claim = reg(CLAIM_COMPLETE + context(hart_id()) * CONTEXT_STRIDE);
+    loop {
+        // 0 once another hart took what was pending
+        let irq = unsafe { read_volatile(claim) } as usize;
+        if irq == 0 {
+            break;
+        }
+        let handler = HANDLERS.exclusive_access().get(irq).copied().flatten();
+        match handler {
+            Some(handler) => handler(),
+            None => warn!("[kernel] irq {} without a handler", irq),
+        }
+        unsafe {
+            write_volatile(claim, irq as u32);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! The sifive_test device of qemu virt, which ends qemu with an exit status
+//!
+//! Whoever runs qemu, a grader or `make test`, can then tell a passing run
+//! from a failing one by the status alone.
+
+use crate::config::MMIO;
+use core::ptr::write_volatile;
+
+/// qemu exits with status 0
+const FINISHER_PASS: u32 = 0x5555;
+/// qemu exits with the status in the upper 16 bits
+const FINISHER_FAIL: u32 = 0x3333;
+
+/// End qemu with exit status `code`, 0 for success. On a machine without
+/// the device this falls back to an SBI shutdown.
+pub fn exit_qemu(code: u16) -> ! {
+    let value = match code {
+        0 => FINISHER_PASS,
+        code => FINISHER_FAIL | (code as u32) << 16,
+    };
+    unsafe {
+        write_volatile(MMIO[3].0 as *mut u32, value);
+    }
+    crate::sbi::shutdown(code != 0)
+}
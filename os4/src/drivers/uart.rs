@@ -0,0 +1,185 @@
+//! The console, on the ns16550a UART of qemu virt
+//!
+//! Output goes straight to the transmit FIFO, without an SBI call per byte.
+//! Input is interrupt driven: the UART raises irq `UART_IRQ` through the
+//! PLIC whenever bytes arrive, and the handler moves them into a ring buffer
+//! and wakes the tasks waiting for input, so a reader blocks instead of
+//! polling the console.
+
+use super::plic;
+use crate::config::MMIO;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_pid, wakeup_task};
+use alloc::collections::VecDeque;
+use core::ptr::{read_volatile, write_volatile};
+use lazy_static::*;
+
+/// the UART's source number at the PLIC of qemu virt
+const UART_IRQ: usize = 10;
+/// bytes kept until someone reads them, later ones are dropped
+const RX_BUFFER_SIZE: usize = 256;
+
+// ns16550a registers, one byte each; with LCR_DLAB set the first two hold
+// the baud rate divisor instead
+const RBR: usize = 0;
+const THR: usize = 0;
+const IER: usize = 1;
+const DLL: usize = 0;
+const DLM: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1;
+/// turn the FIFOs on and empty both
+const FCR_FIFO_RESET: u8 = 0x07;
+const LCR_8N1: u8 = 0x03;
+const LCR_DLAB: u8 = 0x80;
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_THR_EMPTY: u8 = 0x20;
+/// 38400 baud from the 1.8432 MHz clock, qemu ignores it anyway
+const BAUD_DIVISOR: u16 = 3;
+
+/// Bytes received and not read yet, oldest first.
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    /// pids blocked in `wait_for_input`
+    waiters: VecDeque<usize>,
+}
+
+impl RxBuffer {
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == RX_BUFFER_SIZE {
+            return false;
+        }
+        self.bytes[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+lazy_static! {
+    static ref RX_BUFFER: UPSafeCell<RxBuffer> = unsafe {
+        UPSafeCell::new(RxBuffer {
+            bytes: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            waiters: VecDeque::new(),
+        })
+    };
+}
+
+/// An ns16550a at `base`, driven by polling.
+pub struct Ns16550a {
+    base: usize,
+}
+
+impl Ns16550a {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+    fn reg(&self, offset: usize) -> *mut u8 {
+        (self.base + offset) as *mut u8
+    }
+    /// Set the port up for 8N1 with FIFOs and no interrupts.
+    pub fn init(&self) {
+        unsafe {
+            write_volatile(self.reg(IER), 0);
+            write_volatile(self.reg(LCR), LCR_DLAB);
+            write_volatile(self.reg(DLL), BAUD_DIVISOR as u8);
+            write_volatile(self.reg(DLM), (BAUD_DIVISOR >> 8) as u8);
+            write_volatile(self.reg(LCR), LCR_8N1);
+            write_volatile(self.reg(FCR), FCR_FIFO_RESET);
+        }
+    }
+    /// Send `byte`, waiting for room in the transmit FIFO.
+    pub fn putchar(&self, byte: u8) {
+        unsafe {
+            while read_volatile(self.reg(LSR)) & LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            write_volatile(self.reg(THR), byte);
+        }
+    }
+    /// A byte straight from the receive FIFO, `None` if it is empty.
+    pub fn poll_getchar(&self) -> Option<u8> {
+        unsafe {
+            if read_volatile(self.reg(LSR)) & LSR_DATA_READY != 0 {
+                Some(read_volatile(self.reg(RBR)))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// the port the console is on
+const CONSOLE: Ns16550a = Ns16550a::new(MMIO[2].0);
+
+/// Set the console up. Nothing else is needed for output, so this runs
+/// before the first `println!`.
+pub fn init() {
+    CONSOLE.init();
+}
+
+/// Have the console interrupt on received bytes and take them in
+/// `handle_irq`, once the PLIC is mapped.
+pub fn enable_rx_interrupt() {
+    plic::register(UART_IRQ, handle_irq);
+    unsafe {
+        write_volatile(CONSOLE.reg(IER), IER_RX_AVAILABLE);
+    }
+}
+
+/// Send `byte` to the console.
+pub fn putchar(byte: u8) {
+    CONSOLE.putchar(byte);
+}
+
+/// Move what the UART received into the buffer and wake the readers.
+fn handle_irq() {
+    let mut rx = RX_BUFFER.exclusive_access();
+    let mut dropped = 0;
+    while let Some(byte) = CONSOLE.poll_getchar() {
+        if !rx.push(byte) {
+            dropped += 1;
+        }
+    }
+    let waiters: VecDeque<usize> = rx.waiters.drain(..).collect();
+    drop(rx);
+    if dropped > 0 {
+        warn!("[kernel] console input full, {} bytes dropped", dropped);
+    }
+    for pid in waiters {
+        wakeup_task(pid);
+    }
+}
+
+/// The oldest byte received and not read yet.
+pub fn getchar() -> Option<u8> {
+    RX_BUFFER.exclusive_access().pop()
+}
+
+/// Block the current task until some input arrives, or it is woken for
+/// another reason; check `getchar` again after.
+pub fn wait_for_input() {
+    let pid = current_pid();
+    RX_BUFFER.exclusive_access().waiters.push_back(pid);
+    block_current_and_run_next();
+    // still queued only if a signal woke us
+    RX_BUFFER
+        .exclusive_access()
+        .waiters
+        .retain(|waiter| *waiter != pid);
+}
@@ -1,12 +1,30 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # the hart id comes in a0, the kernel keeps it in tp
+    mv tp, a0
+    call set_boot_stack
     call rust_main
 
+    # where sbi hart_start sends the other harts
+    .globl _start_secondary
+_start_secondary:
+    mv tp, a0
+    call set_boot_stack
+    call rust_main_secondary
+
+    # sp = boot_stack_top - hart id * BOOT_STACK_SIZE
+set_boot_stack:
+    la sp, boot_stack_top
+    li t0, 4096 * 16
+    mul t0, t0, tp
+    sub sp, sp, t0
+    ret
+
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # BOOT_STACK_SIZE * MAX_HARTS, see config.rs
+    .space 4096 * 16 * 4
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
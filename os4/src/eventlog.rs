@@ -0,0 +1,152 @@
+//! A bounded log of notable kernel events that user space can drain
+//!
+//! Events go into a fixed-size ring. Once it is full the oldest event is
+//! overwritten and counted as dropped; the next drain starts with an
+//! [`EventKind::Overflow`] record carrying that count.
+
+use crate::config::EVENT_LOG_LEN;
+use crate::sync::UPSafeCell;
+use crate::timer;
+use lazy_static::*;
+
+/// what an [`Event`] records, `arg` depends on it
+#[repr(usize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    /// `arg` events were overwritten before anyone read them
+    Overflow = 0,
+    TaskCreate = 1,
+    /// `arg` is the exit code
+    TaskExit = 2,
+    /// `arg` is the start address
+    Mmap = 3,
+    /// `arg` is the start address
+    Munmap = 4,
+    /// the task was killed by a fault, `arg` is the faulting address
+    Fault = 5,
+}
+
+/// one log record, the layout `sys_read_eventlog` copies out
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Event {
+    /// in microseconds
+    pub time: usize,
+    pub task: usize,
+    pub kind: EventKind,
+    pub arg: usize,
+}
+
+impl Event {
+    pub const fn empty() -> Self {
+        Self {
+            time: 0,
+            task: 0,
+            kind: EventKind::Overflow,
+            arg: 0,
+        }
+    }
+}
+
+/// ring of the last `N` events
+pub struct EventRing<const N: usize> {
+    events: [Event; N],
+    /// index of the oldest event
+    head: usize,
+    len: usize,
+    /// events overwritten since the last drain
+    dropped: usize,
+}
+
+impl<const N: usize> EventRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            events: [Event::empty(); N],
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+    pub fn push(&mut self, event: Event) {
+        if self.len == N {
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+            self.dropped += 1;
+        }
+        self.events[(self.head + self.len) % N] = event;
+        self.len += 1;
+    }
+    /// Move the oldest events into `out`, an overflow record first if any
+    /// were dropped. Returns how many records were written.
+    pub fn drain(&mut self, out: &mut [Event]) -> usize {
+        let mut written = 0;
+        if self.dropped != 0 && !out.is_empty() {
+            out[0] = Event {
+                time: timer::get_time_us(),
+                task: 0,
+                kind: EventKind::Overflow,
+                arg: self.dropped,
+            };
+            self.dropped = 0;
+            written = 1;
+        }
+        while written < out.len() && self.len != 0 {
+            out[written] = self.events[self.head];
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+            written += 1;
+        }
+        written
+    }
+}
+
+lazy_static! {
+    static ref EVENT_LOG: UPSafeCell<EventRing<EVENT_LOG_LEN>> =
+        unsafe { UPSafeCell::new(EventRing::new()) };
+}
+
+/// Log that `kind` happened to `task`.
+pub fn record(kind: EventKind, task: usize, arg: usize) {
+    EVENT_LOG.exclusive_access().push(Event {
+        time: timer::get_time_us(),
+        task,
+        kind,
+        arg,
+    });
+}
+
+/// Drain the oldest events into `out`, see [`EventRing::drain`].
+pub fn drain(out: &mut [Event]) -> usize {
+    EVENT_LOG.exclusive_access().drain(out)
+}
+
+#[allow(unused)]
+#[test_case]
+/// events come out oldest first, and a wrapped ring reports what it lost
+pub fn event_log_test() {
+    let event = |arg| Event {
+        time: arg,
+        task: 1,
+        kind: EventKind::Mmap,
+        arg,
+    };
+    let mut ring: EventRing<4> = EventRing::new();
+    let mut out = [Event::empty(); 8];
+    for arg in 0..3 {
+        ring.push(event(arg));
+    }
+    assert_eq!(ring.drain(&mut out[..2]), 2);
+    assert_eq!((out[0].arg, out[1].arg), (0, 1));
+    // 6 more on top of the one left: the three oldest are overwritten
+    for arg in 3..9 {
+        ring.push(event(arg));
+    }
+    assert_eq!(ring.drain(&mut out), 5);
+    assert_eq!(out[0].kind, EventKind::Overflow);
+    assert_eq!(out[0].arg, 3);
+    let args: [usize; 4] = [out[1].arg, out[2].arg, out[3].arg, out[4].arg];
+    assert_eq!(args, [5, 6, 7, 8]);
+    // the overflow count starts over after a drain
+    assert_eq!(ring.drain(&mut out), 0);
+    info!("event_log_test passed!");
+}
@@ -0,0 +1,87 @@
+//! Files a task can hold in its fd table
+//!
+//! Two layers of trait objects: a [`File`] is what a descriptor refers to,
+//! an open file with its offset, the console or anything else that can be
+//! read or written; an [`Inode`] is a file or directory of some file system.
+//! Regular files are [`OSInode`]s over an inode of the root directory, which
+//! is easy-fs on the virtio-blk disk for now. A [`Pipe`] end is a [`File`]
+//! too. `sys_read` and `sys_write` only ever see [`File`]s.
+
+mod inode;
+mod pipe;
+mod stdio;
+
+use crate::mm::{MapPermission, UserBuffer};
+use crate::task::populate_user_buffer;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Anything that can sit behind a file descriptor.
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    /// Read into `buf`, returns how many bytes were read, 0 at the end.
+    fn read(&self, buf: UserBuffer) -> usize;
+    /// Write all of `buf`, returns how many bytes were written.
+    fn write(&self, buf: UserBuffer) -> usize;
+    /// The inode behind the file, for mmap; `None` for anything that is not
+    /// a regular file.
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        None
+    }
+}
+
+/// A file or directory of a file system, addressed by byte offsets.
+pub trait Inode: Send + Sync {
+    /// Read from `offset` into `buf`, returns how many bytes were read.
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// Write `buf` at `offset`, growing the file, returns how many bytes were written.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// Drop the contents of a file.
+    fn clear(&self);
+    /// The length of a file in bytes.
+    fn size(&self) -> usize;
+    /// The entry `name` of a directory.
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    /// Make an empty file `name` in a directory, `None` if it exists.
+    fn create(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    /// The names in a directory.
+    fn ls(&self) -> Vec<String>;
+}
+
+impl Inode for easy_fs::Inode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        easy_fs::Inode::read_at(self, offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        easy_fs::Inode::write_at(self, offset, buf)
+    }
+    fn clear(&self) {
+        easy_fs::Inode::clear(self)
+    }
+    fn size(&self) -> usize {
+        easy_fs::Inode::size(self)
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        easy_fs::Inode::find(self, name).map(|inode| inode as Arc<dyn Inode>)
+    }
+    fn create(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        easy_fs::Inode::create(self, name).map(|inode| inode as Arc<dyn Inode>)
+    }
+    fn ls(&self) -> Vec<String> {
+        easy_fs::Inode::ls(self)
+    }
+}
+
+/// Make `buf` usable again after the task blocked, backing its pages first
+/// like `sys_read` and `sys_write` do. False if part of it is gone.
+fn reload_user_buffer(buf: &mut UserBuffer) -> bool {
+    let access = if buf.writable() { MapPermission::W } else { MapPermission::R };
+    populate_user_buffer(buf.ptr(), buf.len(), access);
+    buf.translate_again()
+}
+
+pub use inode::{open_file, OSInode, OpenFlags, ROOT_INODE};
+pub use pipe::{make_pipe, Pipe};
+pub use stdio::{Stdin, Stdout};
@@ -0,0 +1,215 @@
+//! Pipes, a ring buffer shared by a read end and a write end
+
+use super::{reload_user_buffer, File};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, current_pid, wakeup_task};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// One end of a pipe.
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+}
+
+const RING_BUFFER_SIZE: usize = 32;
+
+/// The bytes in flight and the tasks blocked on them.
+struct PipeRingBuffer {
+    arr: [u8; RING_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    read_end: Option<Weak<Pipe>>,
+    write_end: Option<Weak<Pipe>>,
+    /// pids of the tasks blocked on an empty buffer
+    readers: Vec<usize>,
+    /// pids of the tasks blocked on a full buffer
+    writers: Vec<usize>,
+}
+
+impl PipeRingBuffer {
+    fn new() -> Self {
+        Self {
+            arr: [0; RING_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            read_end: None,
+            write_end: None,
+            readers: Vec::new(),
+            writers: Vec::new(),
+        }
+    }
+    fn read_byte(&mut self) -> u8 {
+        let c = self.arr[self.head];
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.len -= 1;
+        c
+    }
+    fn write_byte(&mut self, c: u8) {
+        self.arr[(self.head + self.len) % RING_BUFFER_SIZE] = c;
+        self.len += 1;
+    }
+    fn available_read(&self) -> usize {
+        self.len
+    }
+    fn available_write(&self) -> usize {
+        RING_BUFFER_SIZE - self.len
+    }
+    fn all_read_ends_closed(&self) -> bool {
+        self.read_end.as_ref().unwrap().upgrade().is_none()
+    }
+    fn all_write_ends_closed(&self) -> bool {
+        self.write_end.as_ref().unwrap().upgrade().is_none()
+    }
+}
+
+/// Wait on `queue`, at most once per task, and remember to wake it.
+fn enqueue(queue: &mut Vec<usize>) {
+    let pid = current_pid();
+    if !queue.contains(&pid) {
+        queue.push(pid);
+    }
+}
+
+fn wake_all(queue: &mut Vec<usize>) {
+    for pid in queue.drain(..) {
+        wakeup_task(pid);
+    }
+}
+
+/// A new pipe as (read end, write end).
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
+    let read_end = Arc::new(Pipe {
+        readable: true,
+        writable: false,
+        buffer: buffer.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        readable: false,
+        writable: true,
+        buffer: buffer.clone(),
+    });
+    let mut ring = buffer.exclusive_access();
+    ring.read_end = Some(Arc::downgrade(&read_end));
+    ring.write_end = Some(Arc::downgrade(&write_end));
+    drop(ring);
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    /// Block until there is something to read, then take what is there.
+    /// Returns 0 once the buffer is empty and every write end is closed.
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.available_read() == 0 {
+                // a killed task never comes back to user mode to see the 0
+                if ring.all_write_ends_closed() || current_killed() {
+                    return 0;
+                }
+                enqueue(&mut ring.readers);
+                drop(ring);
+                block_current_and_run_next();
+                // the frames behind `buf` may have changed while we slept
+                if !reload_user_buffer(&mut buf) {
+                    return 0;
+                }
+                continue;
+            }
+            let mut count = 0;
+            'copy: for buffer in buf.iter_mut() {
+                for byte in buffer.iter_mut() {
+                    if ring.available_read() == 0 {
+                        break 'copy;
+                    }
+                    *byte = ring.read_byte();
+                    count += 1;
+                }
+            }
+            wake_all(&mut ring.writers);
+            return count;
+        }
+    }
+    /// Write all of `buf`, blocking while the buffer is full. Stops early
+    /// once every read end is closed, nobody would ever read the rest.
+    fn write(&self, mut buf: UserBuffer) -> usize {
+        let mut count = 0;
+        while count < buf.len() {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.all_read_ends_closed() || current_killed() {
+                return count;
+            }
+            if ring.available_write() == 0 {
+                enqueue(&mut ring.writers);
+                drop(ring);
+                block_current_and_run_next();
+                // the frames behind `buf` may have changed while we slept
+                if !reload_user_buffer(&mut buf) {
+                    return count;
+                }
+                continue;
+            }
+            let n = (buf.len() - count).min(ring.available_write());
+            for &c in buf.bytes(count).take(n) {
+                ring.write_byte(c);
+            }
+            count += n;
+            wake_all(&mut ring.readers);
+        }
+        count
+    }
+}
+
+impl Drop for Pipe {
+    /// The last reference to one end is gone, whoever waits on the other
+    /// end gets to see that.
+    fn drop(&mut self) {
+        let mut ring = self.buffer.exclusive_access();
+        if self.writable {
+            wake_all(&mut ring.readers);
+        }
+        if self.readable {
+            wake_all(&mut ring.writers);
+        }
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// Bytes come out in order across the wrap of the ring, and each end
+/// notices when the other one is gone.
+pub fn pipe_ring_test() {
+    let (read_end, write_end) = make_pipe();
+    let mut ring = read_end.buffer.exclusive_access();
+    for round in 0..3 {
+        for i in 0..RING_BUFFER_SIZE - 5 {
+            ring.write_byte((round + i) as u8);
+        }
+        assert_eq!(ring.available_write(), 5);
+        for i in 0..RING_BUFFER_SIZE - 5 {
+            assert_eq!(ring.read_byte(), (round + i) as u8);
+        }
+        assert_eq!(ring.available_read(), 0);
+    }
+    assert!(!ring.all_read_ends_closed());
+    assert!(!ring.all_write_ends_closed());
+    drop(ring);
+    drop(write_end);
+    let ring = read_end.buffer.exclusive_access();
+    assert!(ring.all_write_ends_closed());
+    assert!(!ring.all_read_ends_closed());
+    drop(ring);
+    info!("pipe_ring_test passed!");
+}
@@ -0,0 +1,73 @@
+//! The console as fd 0, 1 and 2
+
+use super::{reload_user_buffer, File};
+use crate::drivers::uart::{getchar, wait_for_input};
+use crate::mm::UserBuffer;
+use crate::task::current_killed;
+
+/// Standard input, what the UART received.
+pub struct Stdin;
+/// Standard output and standard error, written to the UART.
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Block until the first byte arrives, then take only the bytes that are
+    /// already waiting.
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let mut next = loop {
+            match getchar() {
+                Some(c) => break Some(c),
+                None if current_killed() => return 0,
+                None => wait_for_input(),
+            }
+            // another thread may have unmapped or forked the pages meanwhile
+            if !reload_user_buffer(&mut buf) {
+                return 0;
+            }
+        };
+        let mut count = 0;
+        for buffer in buf.iter_mut() {
+            for byte in buffer.iter_mut() {
+                match next {
+                    Some(c) => *byte = c,
+                    None => return count,
+                }
+                count += 1;
+                next = getchar();
+            }
+        }
+        count
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        for buffer in buf.iter() {
+            #[cfg(feature = "integration")]
+            crate::integration::capture(buffer);
+            print!("{}", core::str::from_utf8(buffer).unwrap());
+        }
+        buf.len()
+    }
+}
@@ -0,0 +1,606 @@
+//! A GDB remote stub for user tasks, on its own UART, with the `gdbstub` feature
+//!
+//! qemu virt has a single UART of its own, the console, so the stub talks
+//! over a `pci-serial` device: `make run GDBSTUB=on` builds the stub and
+//! hands qemu the device on localhost:1235, then in gdb
+//! `target remote localhost:1235`. Without the device the stub stays off.
+//!
+//! `target remote` on the port at `GDB_UART` attaches to whatever task runs
+//! when the first byte from gdb is noticed on a timer tick. From then on a
+//! breakpoint, a finished single step or a ^C from gdb stops the task that
+//! runs, and the kernel serves gdb's packets with that task's trap context
+//! and address space until gdb resumes it. The other harts wait on the big
+//! kernel lock meanwhile.
+//!
+//! Breakpoints are `ebreak`s written into the task's pages through its page
+//! table, read-only text included. There is no hardware single step in
+//! supervisor mode, so a step puts breakpoints on every instruction that
+//! can run next. The kernel itself is not debugged here, `make debug` has
+//! qemu's own gdbserver for that.
+
+use crate::config::{GDB_UART, PCI_ECAM, PCI_IO};
+use crate::drivers::uart::Ns16550a;
+use crate::mm::{nofault_copy_from, VirtAddr};
+use crate::sync::UPSafeCell;
+use crate::task::{current_private_frame, current_user_token};
+use crate::trap::TrapContext;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+const PORT: Ns16550a = Ns16550a::new(GDB_UART.0);
+/// vendor and device id of qemu's pci-serial, an ns16550a behind an I/O BAR
+const PCI_SERIAL_ID: u32 = 0x0002_1b36;
+const PCI_COMMAND: usize = 0x04;
+/// command register bit that makes the device decode its I/O BARs
+const PCI_COMMAND_IO: u16 = 0x1;
+const PCI_BAR0: usize = 0x10;
+
+/// longest packet taken from gdb, announced in qSupported
+const PACKET_SIZE: usize = 4096;
+/// what gdb sends to interrupt a running task
+const INTERRUPT: u8 = 0x03;
+const EBREAK: u32 = 0x0010_0073;
+const C_EBREAK: u16 = 0x9002;
+/// stop reply for SIGTRAP
+const STOPPED: &str = "S05";
+
+/// An `ebreak` written over the instruction at `va` of one address space.
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    token: usize,
+    va: usize,
+    saved: [u8; 4],
+    len: usize,
+}
+
+struct GdbState {
+    /// gdb talked to us and has not detached yet
+    attached: bool,
+    /// breakpoints gdb set
+    breakpoints: Vec<Breakpoint>,
+    /// breakpoints of a single step in progress
+    step: Vec<Breakpoint>,
+}
+
+lazy_static! {
+    static ref GDB: UPSafeCell<GdbState> = unsafe {
+        UPSafeCell::new(GdbState {
+            attached: false,
+            breakpoints: Vec::new(),
+            step: Vec::new(),
+        })
+    };
+}
+
+/// whether `init` found the pci-serial, the port is never touched otherwise
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// Find the pci-serial and get the port ready, it is only ever polled.
+pub fn init() {
+    if !enable_pci_serial() {
+        warn!("[kernel] gdbstub: no pci-serial device, the stub is off");
+        return;
+    }
+    PORT.init();
+    PRESENT.store(true, Ordering::Relaxed);
+}
+
+/// Look for qemu's pci-serial on bus 0 and put its registers at `GDB_UART`,
+/// false if there is none. Nothing else assigns BARs on qemu virt.
+fn enable_pci_serial() -> bool {
+    for device in 0..32 {
+        let config = PCI_ECAM.0 + (device << 15);
+        unsafe {
+            // an empty slot reads all ones
+            if read_volatile(config as *const u32) != PCI_SERIAL_ID {
+                continue;
+            }
+            write_volatile((config + PCI_BAR0) as *mut u32, (GDB_UART.0 - PCI_IO.0) as u32);
+            let command = (config + PCI_COMMAND) as *mut u16;
+            write_volatile(command, read_volatile(command) | PCI_COMMAND_IO);
+        }
+        return true;
+    }
+    false
+}
+
+/// Stop the current task for gdb if gdb sent something since the last
+/// tick, a ^C or the first packets of `target remote`.
+pub fn poll(cx: &mut TrapContext) {
+    if !PRESENT.load(Ordering::Relaxed) {
+        return;
+    }
+    let byte = match PORT.poll_getchar() {
+        Some(byte) => byte,
+        None => return,
+    };
+    let attached = core::mem::replace(&mut GDB.exclusive_access().attached, true);
+    if attached {
+        // whatever it was, gdb waits for the task to stop
+        put_packet(STOPPED);
+        serve(cx, None);
+    } else {
+        // the packet `byte` starts, if it is one, is answered right away
+        serve(cx, Some(byte).filter(|byte| *byte != INTERRUPT));
+    }
+}
+
+/// Handle an `ebreak` of the current task, false if gdb has nothing to do
+/// with it and the task should die of it as without the stub.
+pub fn handle_breakpoint(cx: &mut TrapContext) -> bool {
+    let mut gdb = GDB.exclusive_access();
+    // breakpoints live only while gdb is attached, so this is the app's own ebreak
+    if !gdb.attached {
+        return false;
+    }
+    // the step is over whichever of its breakpoints was hit; an ebreak of the
+    // app itself stops it for gdb like one of gdb's
+    let step = core::mem::take(&mut gdb.step);
+    drop(gdb);
+    for breakpoint in step.iter() {
+        remove(breakpoint);
+    }
+    put_packet(STOPPED);
+    serve(cx, None);
+    true
+}
+
+/// Answer gdb's packets until it resumes the task, `first` being the first
+/// byte of a packet already taken from the port.
+fn serve(cx: &mut TrapContext, mut first: Option<u8>) {
+    loop {
+        let packet = get_packet(first.take());
+        let (command, args) = match packet.split_first() {
+            Some((command, args)) => (*command, args),
+            None => {
+                put_packet("");
+                continue;
+            }
+        };
+        let reply = match command {
+            b'?' => Reply::Text(STOPPED),
+            b'g' => Reply::Hex(read_registers(cx)),
+            b'G' => Reply::status(write_registers(cx, args)),
+            b'p' => match parse_hex(args).and_then(|n| register(cx, n)) {
+                Some(value) => Reply::Hex(value.to_le_bytes().to_vec()),
+                None => Reply::Text("E01"),
+            },
+            b'P' => Reply::status(write_register(cx, args)),
+            b'm' => match read_memory(args) {
+                Some(bytes) => Reply::Hex(bytes),
+                None => Reply::Text("E14"),
+            },
+            b'M' => Reply::status(write_memory(args)),
+            b'Z' => Reply::status(set_breakpoint(args)),
+            b'z' => Reply::status(clear_breakpoint(args)),
+            b'c' => {
+                if let Some(pc) = parse_hex(args) {
+                    cx.sepc = pc;
+                }
+                return;
+            }
+            b's' => {
+                if let Some(pc) = parse_hex(args) {
+                    cx.sepc = pc;
+                }
+                if step(cx) {
+                    return;
+                }
+                Reply::Text("E01")
+            }
+            b'D' | b'k' => {
+                detach();
+                // gdb is gone after a kill, it only waits for the reply to detach
+                if command == b'D' {
+                    put_packet("OK");
+                }
+                return;
+            }
+            b'H' => Reply::Text("OK"),
+            b'q' if args.starts_with(b"Supported") => Reply::Text("PacketSize=1000"),
+            b'q' if args.starts_with(b"Attached") => Reply::Text("1"),
+            _ => Reply::Text(""),
+        };
+        match reply {
+            Reply::Text(text) => put_packet(text),
+            Reply::Hex(bytes) => put_packet(&to_hex(&bytes)),
+        }
+    }
+}
+
+enum Reply {
+    Text(&'static str),
+    Hex(Vec<u8>),
+}
+
+impl Reply {
+    fn status(ok: bool) -> Self {
+        Reply::Text(if ok { "OK" } else { "E01" })
+    }
+}
+
+/// Take out every breakpoint and forget gdb, the task runs on.
+fn detach() {
+    let mut gdb = GDB.exclusive_access();
+    gdb.attached = false;
+    let mut breakpoints = core::mem::take(&mut gdb.breakpoints);
+    breakpoints.append(&mut gdb.step);
+    drop(gdb);
+    for breakpoint in breakpoints.iter() {
+        remove(breakpoint);
+    }
+}
+
+// registers
+
+/// x0-x31 and pc, the order gdb numbers them in
+fn register(cx: &TrapContext, n: usize) -> Option<usize> {
+    match n {
+        0 => Some(0),
+        1..=31 => Some(cx.x[n]),
+        32 => Some(cx.sepc),
+        _ => None,
+    }
+}
+
+fn set_register(cx: &mut TrapContext, n: usize, value: usize) -> bool {
+    match n {
+        // x0 stays zero
+        0 => {}
+        1..=31 => cx.x[n] = value,
+        32 => cx.sepc = value,
+        _ => return false,
+    }
+    true
+}
+
+fn read_registers(cx: &TrapContext) -> Vec<u8> {
+    (0..33)
+        .flat_map(|n| register(cx, n).unwrap().to_le_bytes())
+        .collect()
+}
+
+fn write_registers(cx: &mut TrapContext, args: &[u8]) -> bool {
+    let bytes = match from_hex(args) {
+        Some(bytes) if bytes.len() >= 33 * 8 => bytes,
+        _ => return false,
+    };
+    for (n, value) in bytes.chunks(8).take(33).enumerate() {
+        set_register(cx, n, usize::from_le_bytes(value.try_into().unwrap()));
+    }
+    true
+}
+
+/// `P n=value`
+fn write_register(cx: &mut TrapContext, args: &[u8]) -> bool {
+    let mut parts = args.splitn(2, |byte| *byte == b'=');
+    let n = parts.next().and_then(parse_hex);
+    let value = parts.next().and_then(from_hex);
+    match (n, value) {
+        (Some(n), Some(value)) if value.len() == 8 => {
+            set_register(cx, n, usize::from_le_bytes(value.try_into().unwrap()))
+        }
+        _ => false,
+    }
+}
+
+// memory
+
+/// `m addr,len`, from the stopped task's address space
+fn read_memory(args: &[u8]) -> Option<Vec<u8>> {
+    let (va, len) = parse_pair(args)?;
+    let mut bytes = alloc::vec![0u8; len.min(PACKET_SIZE / 2)];
+    let copied = nofault_copy_from(current_user_token(), va, &mut bytes);
+    if copied == 0 && !bytes.is_empty() {
+        return None;
+    }
+    bytes.truncate(copied);
+    Some(bytes)
+}
+
+/// `M addr,len:data`
+fn write_memory(args: &[u8]) -> bool {
+    let mut parts = args.splitn(2, |byte| *byte == b':');
+    let range = parts.next().and_then(parse_pair);
+    let data = parts.next().and_then(from_hex);
+    match (range, data) {
+        (Some((va, len)), Some(data)) if data.len() == len => poke(va, &data),
+        _ => false,
+    }
+}
+
+/// Write `bytes` at `va` of the current task, whatever the page's
+/// permissions. A page still shared with another process after fork gets a
+/// copy of its own first, and a page reading the zero frame is refused, so
+/// the write is only ever seen by this process.
+fn poke(va: usize, bytes: &[u8]) -> bool {
+    for (i, byte) in bytes.iter().enumerate() {
+        let va = va + i;
+        let frame = match current_private_frame(va) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        frame.ppn.get_bytes_array()[VirtAddr::from(va).page_offset()] = *byte;
+    }
+    true
+}
+
+// breakpoints
+
+/// Put an `ebreak` of `len` bytes at `va`, what was there is kept.
+fn insert(va: usize, len: usize) -> Option<Breakpoint> {
+    let mut saved = [0u8; 4];
+    if nofault_copy_from(current_user_token(), va, &mut saved[..len]) != len {
+        return None;
+    }
+    let ebreak = EBREAK.to_le_bytes();
+    let c_ebreak = C_EBREAK.to_le_bytes();
+    let code = if len == 2 { &c_ebreak[..] } else { &ebreak[..] };
+    if !poke(va, code) {
+        return None;
+    }
+    Some(Breakpoint {
+        token: current_user_token(),
+        va,
+        saved,
+        len,
+    })
+}
+
+fn remove(breakpoint: &Breakpoint) {
+    // the task may have exited, or this is some other task's address space
+    if breakpoint.token == current_user_token() {
+        poke(breakpoint.va, &breakpoint.saved[..breakpoint.len]);
+    }
+}
+
+/// `Z0,addr,kind`, only software breakpoints
+fn set_breakpoint(args: &[u8]) -> bool {
+    let (va, len) = match parse_breakpoint(args) {
+        Some(breakpoint) => breakpoint,
+        None => return false,
+    };
+    let token = current_user_token();
+    let mut gdb = GDB.exclusive_access();
+    if gdb
+        .breakpoints
+        .iter()
+        .any(|b| b.token == token && b.va == va)
+    {
+        return true;
+    }
+    match insert(va, len) {
+        Some(breakpoint) => {
+            gdb.breakpoints.push(breakpoint);
+            true
+        }
+        None => false,
+    }
+}
+
+/// `z0,addr,kind`
+fn clear_breakpoint(args: &[u8]) -> bool {
+    let va = match parse_breakpoint(args) {
+        Some((va, _)) => va,
+        None => return false,
+    };
+    let token = current_user_token();
+    let mut gdb = GDB.exclusive_access();
+    match gdb
+        .breakpoints
+        .iter()
+        .position(|b| b.token == token && b.va == va)
+    {
+        Some(i) => {
+            let breakpoint = gdb.breakpoints.remove(i);
+            remove(&breakpoint);
+            true
+        }
+        None => false,
+    }
+}
+
+/// (addr, length) of `0,addr,kind`, kind 2 for a compressed ebreak
+fn parse_breakpoint(args: &[u8]) -> Option<(usize, usize)> {
+    let mut parts = args.split(|byte| *byte == b',');
+    if parts.next()? != b"0" {
+        return None;
+    }
+    let va = parse_hex(parts.next()?)?;
+    let len = if parse_hex(parts.next()?)? == 2 { 2 } else { 4 };
+    Some((va, len))
+}
+
+// single step
+
+/// Break on every instruction that can follow the one at `cx.sepc`.
+fn step(cx: &TrapContext) -> bool {
+    let token = current_user_token();
+    let mut code = [0u8; 4];
+    nofault_copy_from(token, cx.sepc, &mut code);
+    let insn = u32::from_le_bytes(code);
+    let mut gdb = GDB.exclusive_access();
+    for pc in next_pcs(cx, insn).iter().flatten() {
+        // gdb's own breakpoint there already stops the task
+        if gdb
+            .breakpoints
+            .iter()
+            .chain(gdb.step.iter())
+            .any(|b| b.token == token && b.va == *pc)
+        {
+            continue;
+        }
+        let mut code = [0u8; 2];
+        nofault_copy_from(token, *pc, &mut code);
+        let len = if code[0] & 0b11 == 0b11 { 4 } else { 2 };
+        match insert(*pc, len) {
+            Some(breakpoint) => gdb.step.push(breakpoint),
+            None => return false,
+        }
+    }
+    true
+}
+
+fn sign_extend(value: u32, bits: u32) -> usize {
+    (((value << (32 - bits)) as i32) >> (32 - bits)) as isize as usize
+}
+
+fn bits(insn: u32, high: u32, low: u32) -> u32 {
+    (insn >> low) & ((1 << (high - low + 1)) - 1)
+}
+
+/// Where execution can go after `insn` at `cx.sepc`: the next instruction
+/// and, for jumps and branches, the target.
+fn next_pcs(cx: &TrapContext, insn: u32) -> [Option<usize>; 2] {
+    let pc = cx.sepc;
+    let reg = |n: u32| if n == 0 { 0 } else { cx.x[n as usize] };
+    if insn & 0b11 == 0b11 {
+        let next = pc + 4;
+        match insn & 0x7f {
+            // jal
+            0x6f => {
+                let imm = bits(insn, 31, 31) << 20
+                    | bits(insn, 19, 12) << 12
+                    | bits(insn, 20, 20) << 11
+                    | bits(insn, 30, 21) << 1;
+                [Some(pc.wrapping_add(sign_extend(imm, 21))), None]
+            }
+            // jalr
+            0x67 => {
+                let target = reg(bits(insn, 19, 15)).wrapping_add(sign_extend(insn >> 20, 12));
+                [Some(target & !1), None]
+            }
+            // branches
+            0x63 => {
+                let imm = bits(insn, 31, 31) << 12
+                    | bits(insn, 7, 7) << 11
+                    | bits(insn, 30, 25) << 5
+                    | bits(insn, 11, 8) << 1;
+                [Some(next), Some(pc.wrapping_add(sign_extend(imm, 13)))]
+            }
+            _ => [Some(next), None],
+        }
+    } else {
+        let next = pc + 2;
+        match (insn & 0b11, bits(insn, 15, 13)) {
+            // c.j
+            (0b01, 0b101) => {
+                let imm = bits(insn, 12, 12) << 11
+                    | bits(insn, 8, 8) << 10
+                    | bits(insn, 10, 9) << 8
+                    | bits(insn, 6, 6) << 7
+                    | bits(insn, 7, 7) << 6
+                    | bits(insn, 2, 2) << 5
+                    | bits(insn, 11, 11) << 4
+                    | bits(insn, 5, 3) << 1;
+                [Some(pc.wrapping_add(sign_extend(imm, 12))), None]
+            }
+            // c.beqz, c.bnez
+            (0b01, 0b110) | (0b01, 0b111) => {
+                let imm = bits(insn, 12, 12) << 8
+                    | bits(insn, 6, 5) << 6
+                    | bits(insn, 2, 2) << 5
+                    | bits(insn, 11, 10) << 3
+                    | bits(insn, 4, 3) << 1;
+                [Some(next), Some(pc.wrapping_add(sign_extend(imm, 9)))]
+            }
+            // c.jr, c.jalr
+            (0b10, 0b100) if bits(insn, 6, 2) == 0 && bits(insn, 11, 7) != 0 => {
+                [Some(reg(bits(insn, 11, 7)) & !1), None]
+            }
+            _ => [Some(next), None],
+        }
+    }
+}
+
+// packets
+
+fn get_packet(mut first: Option<u8>) -> Vec<u8> {
+    let mut getchar = move || {
+        first.take().unwrap_or_else(|| loop {
+            if let Some(byte) = PORT.poll_getchar() {
+                break byte;
+            }
+        })
+    };
+    loop {
+        // skip acks and interrupts up to the start of the packet
+        while getchar() != b'$' {}
+        let mut packet = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            match getchar() {
+                b'#' => break,
+                byte => {
+                    sum = sum.wrapping_add(byte);
+                    if packet.len() < PACKET_SIZE {
+                        packet.push(byte);
+                    }
+                }
+            }
+        }
+        let checksum = [getchar(), getchar()];
+        if parse_hex(&checksum) == Some(sum as usize) {
+            PORT.putchar(b'+');
+            return packet;
+        }
+        PORT.putchar(b'-');
+    }
+}
+
+fn put_packet(data: &str) {
+    let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    loop {
+        PORT.putchar(b'$');
+        data.bytes().for_each(|byte| PORT.putchar(byte));
+        PORT.putchar(b'#');
+        to_hex(&[sum]).bytes().for_each(|byte| PORT.putchar(byte));
+        // gdb asks again with '-'; anything else, a ^C included, counts as taken
+        let ack = loop {
+            if let Some(byte) = PORT.poll_getchar() {
+                break byte;
+            }
+        };
+        if ack != b'-' {
+            return;
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> alloc::string::String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    bytes
+        .iter()
+        .flat_map(|byte| [DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0xf) as usize]])
+        .map(char::from)
+        .collect()
+}
+
+fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| parse_hex(pair).map(|byte| byte as u8))
+        .collect()
+}
+
+/// A big-endian hex number, as gdb writes addresses and lengths.
+fn parse_hex(hex: &[u8]) -> Option<usize> {
+    if hex.is_empty() {
+        return None;
+    }
+    hex.iter().try_fold(0usize, |value, digit| {
+        let digit = (*digit as char).to_digit(16)? as usize;
+        value.checked_mul(16)?.checked_add(digit)
+    })
+}
+
+/// `addr,len`
+fn parse_pair(args: &[u8]) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, |byte| *byte == b',');
+    Some((parse_hex(parts.next()?)?, parse_hex(parts.next()?)?))
+}
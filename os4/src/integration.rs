@@ -0,0 +1,92 @@
+//! Running a fixed set of user apps and checking what they print, with the
+//! `integration` feature
+//!
+//! Only the apps in `CASES` are loaded at boot. Everything they write to
+//! stdout is kept in a buffer besides going to the console, and once the
+//! last of them exited each app's expected lines are looked for in it. qemu
+//! then exits through the test finisher with status 0 if every line was
+//! found and 1 otherwise, so `make run` can be scripted without reading the
+//! console.
+
+use crate::drivers::qemu_exit::exit_qemu;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// stdout kept for the checks, a run printing more is failed
+const CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// each app run and the lines it must print
+const CASES: &[(&str, &[&str])] = &[
+    ("ch4_mmap0", &["Test 04_1 OK!"]),
+    ("ch4_mmap3", &["Test 04_4 test OK!"]),
+    ("ch4_unmap", &["Test 04_5 ummap OK!"]),
+    ("ch4_unmap2", &["Test 04_6 ummap2 OK!"]),
+    ("ch4_mmap_errno", &["Test mmap errno OK!"]),
+    ("ch4_mmap_lazy", &["Test mmap lazy OK!"]),
+    ("ch4_mmap_shared", &["Test mmap shared OK!"]),
+    ("ch4_mmap_file", &["Test mmap file OK!"]),
+    ("ch4_task_info", &["Test task info snapshot OK!"]),
+    ("ch4_task_info_mem", &["Test task info memory OK!"]),
+    ("ch4_thread", &["Test thread OK!"]),
+];
+
+struct Capture {
+    output: Vec<u8>,
+    overflowed: bool,
+}
+
+lazy_static! {
+    static ref CAPTURE: UPSafeCell<Capture> = unsafe {
+        UPSafeCell::new(Capture {
+            output: Vec::new(),
+            overflowed: false,
+        })
+    };
+}
+
+/// Whether the app `name` is part of the run.
+pub fn selected(name: &str) -> bool {
+    CASES.iter().any(|(app, _)| *app == name)
+}
+
+/// Keep `bytes` some app wrote to stdout.
+pub fn capture(bytes: &[u8]) {
+    let mut capture = CAPTURE.exclusive_access();
+    if capture.output.len() + bytes.len() > CAPTURE_LIMIT {
+        capture.overflowed = true;
+        return;
+    }
+    capture.output.extend_from_slice(bytes);
+}
+
+/// Report each app's result and end qemu with the verdict, once every app
+/// exited.
+pub fn report_and_exit() -> ! {
+    let capture = CAPTURE.exclusive_access();
+    let mut failed = 0;
+    for (app, lines) in CASES {
+        let missing = lines
+            .iter()
+            .find(|line| !contains(&capture.output, line.as_bytes()));
+        match missing {
+            None => println!("[integration] {} passed", app),
+            Some(line) => {
+                println!("[integration] {} FAILED, never printed {:?}", app, line);
+                failed += 1;
+            }
+        }
+    }
+    if capture.overflowed {
+        println!("[integration] more than {} bytes of output", CAPTURE_LIMIT);
+        failed += 1;
+    }
+    println!("[integration] {} of {} apps failed", failed, CASES.len());
+    exit_qemu(if failed == 0 { 0 } else { 1 })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -1,6 +1,13 @@
-use crate::sbi::shutdown;
+use crate::config::{kernel_stack_position, KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
+use crate::drivers::qemu_exit::exit_qemu;
+use crate::shutdown as shutdown_hooks;
 use core::panic::PanicInfo;
 
+/// frames printed at most, in case the chain loops
+const MAX_FRAMES: usize = 32;
+/// trace records of each hart printed on a panic
+const PANIC_TRACE_RECORDS: usize = 16;
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     if let Some(location) = info.location() {
@@ -13,5 +20,61 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         println!("[kernel] Panicked: {}", info.message().unwrap());
     }
-    shutdown()
+    backtrace();
+    crate::trace::dump(PANIC_TRACE_RECORDS);
+    // a panicking shutdown hook is skipped, the remaining ones still run
+    if let Some(name) = shutdown_hooks::interrupted_hook() {
+        println!("[kernel] shutdown hook {} panicked, skipped", name);
+        shutdown_hooks::run_hooks();
+    }
+    exit_qemu(1)
+}
+
+/// Print the return address of every frame from here up, following the
+/// frame pointers the kernel is built to keep: a frame keeps the return
+/// address at `fp - 8` and the caller's `fp` at `fp - 16`. The walk stops at
+/// the end of the stack the panic happened on, so it never reads an
+/// unmapped page; the first frame on a kernel stack points at a user `fp`.
+/// `addr2line -e` on the kernel ELF turns the addresses into lines.
+fn backtrace() {
+    let mut fp: usize;
+    unsafe {
+        core::arch::asm!("mv {}, fp", out(reg) fp);
+    }
+    let (bottom, top) = match stack_bounds(fp) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    println!("[kernel] backtrace:");
+    for _ in 0..MAX_FRAMES {
+        if fp % 8 != 0 || fp - 16 < bottom || fp > top {
+            break;
+        }
+        let (ra, caller_fp) =
+            unsafe { (*((fp - 8) as *const usize), *((fp - 16) as *const usize)) };
+        println!("  {:#x}", ra);
+        // callers are further up the stack
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+}
+
+/// (bottom, top) of the boot or kernel stack `fp` is on.
+fn stack_bounds(fp: usize) -> Option<(usize, usize)> {
+    extern "C" {
+        fn sbss_with_stack();
+        fn sbss();
+    }
+    let boot_stacks = sbss_with_stack as usize..sbss as usize;
+    if boot_stacks.contains(&fp) {
+        return Some((boot_stacks.start, boot_stacks.end));
+    }
+    if fp > TRAMPOLINE {
+        return None;
+    }
+    let (bottom, top) = kernel_stack_position((TRAMPOLINE - fp) / (KERNEL_STACK_SIZE + PAGE_SIZE));
+    // below `bottom` is the guard page
+    (bottom..=top).contains(&fp).then(|| (bottom, top))
 }
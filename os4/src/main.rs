@@ -10,6 +10,9 @@
 #![no_main]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test_runner::run_tests)]
+#![reexport_test_harness_main = "test_main"]
 
 #[macro_use]
 extern crate bitflags;
@@ -21,15 +24,27 @@ extern crate alloc;
 #[macro_use]
 mod console;
 mod config;
+mod drivers;
+mod eventlog;
+mod fs;
+#[cfg(feature = "gdbstub")]
+mod gdbstub;
+#[cfg(feature = "integration")]
+mod integration;
 mod lang_items;
 mod loader;
 mod logging;
 mod mm;
+mod random;
 mod sbi;
+mod shutdown;
+mod smp;
 mod sync;
 mod syscall;
 mod task;
+mod test_runner;
 mod timer;
+mod trace;
 mod trap;
 
 core::arch::global_asm!(include_str!("entry.asm"));
@@ -49,15 +64,42 @@ fn clear_bss() {
 #[no_mangle]
 pub fn rust_main() -> ! {
     clear_bss();
+    smp::lock_kernel();
+    drivers::uart::init();
     logging::init();
     println!("[kernel] Hello, world!");
     mm::init();
     println!("[kernel] back to world!");
     mm::remap_test();
     trap::init();
+    #[cfg(test)]
+    test_main();
     //trap::enable_interrupt();
     trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
+    drivers::uart::enable_rx_interrupt();
+    #[cfg(feature = "gdbstub")]
+    gdbstub::init();
     timer::set_next_trigger();
+    task::init();
+    smp::mark_online();
+    smp::start_other_harts();
     task::run_first_task();
     panic!("Unreachable in rust_main!");
 }
+
+/// Where the harts started by `smp::start_other_harts` enter the kernel,
+/// once the boot hart set everything up.
+#[no_mangle]
+pub fn rust_main_secondary() -> ! {
+    smp::lock_kernel();
+    mm::init_other_hart();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
+    timer::set_next_trigger();
+    smp::mark_online();
+    info!("[kernel] hart {} online", smp::hart_id());
+    task::run_first_task();
+    panic!("Unreachable in rust_main_secondary!");
+}
@@ -89,6 +89,12 @@ impl From<VirtPageNum> for usize {
     }
 }
 
+/// end of the lower (user) canonical half of SV39
+pub const SV39_LOWER_END: usize = 1 << 38;
+/// start of the upper (kernel) canonical half, the addresses in between are
+/// the non-canonical hole
+pub const SV39_UPPER_START: usize = !(SV39_LOWER_END - 1);
+
 //虚拟地址和虚拟页号之间的转换
 //PAGE_SIZE 为4096 ， PAGE_SIZE_BITS 为12 ，分别表示每个页面的大小和页内偏移的位宽
 impl VirtAddr {
@@ -104,6 +110,10 @@ impl VirtAddr {
     pub fn aligned(&self) -> bool {
         self.page_offset() == 0
     }
+    /// Whether the address lies in one of the two canonical halves of SV39.
+    pub fn is_canonical(&self) -> bool {
+        self.0 < SV39_LOWER_END || self.0 >= SV39_UPPER_START
+    }
 }
 impl From<VirtAddr> for VirtPageNum {
     fn from(v: VirtAddr) -> Self {
@@ -255,4 +265,40 @@ where
 
 /// a simple range structure for virtual page number
 //一种简单的虚拟页码范围结构
-pub type VPNRange = SimpleRange<VirtPageNum>;
\ No newline at end of file
+pub type VPNRange = SimpleRange<VirtPageNum>;
+
+impl VPNRange {
+    /// The pages covering `[start, end)`, or `None` if the range runs
+    /// backwards or touches the non-canonical hole between the halves.
+    pub fn checked(start: VirtAddr, end: VirtAddr) -> Option<Self> {
+        if start == end {
+            return Some(Self::new(start.floor(), start.floor()));
+        }
+        let last = VirtAddr(end.0.checked_sub(1)?);
+        let same_half = (start.0 < SV39_LOWER_END) == (last.0 < SV39_LOWER_END);
+        if start > end || !start.is_canonical() || !last.is_canonical() || !same_half {
+            return None;
+        }
+        Some(Self::new(start.floor(), end.ceil()))
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// ranges that reach into or across the SV39 hole must be refused
+pub fn canonical_range_test() {
+    let range = |start: usize, end: usize| VPNRange::checked(VirtAddr(start), VirtAddr(end));
+    // ordinary user ranges, up to the very end of the lower half
+    assert!(range(0x1000_0000, 0x1000_2000).is_some());
+    assert!(range(SV39_LOWER_END - PAGE_SIZE, SV39_LOWER_END).is_some());
+    assert!(range(0x1000, 0x1000).is_some());
+    // starts in user space and ends above the gap
+    assert!(range(SV39_LOWER_END - PAGE_SIZE, SV39_UPPER_START + PAGE_SIZE).is_none());
+    // one page past the lower half, or entirely inside the hole
+    assert!(range(SV39_LOWER_END - PAGE_SIZE, SV39_LOWER_END + PAGE_SIZE).is_none());
+    assert!(range(SV39_LOWER_END, SV39_LOWER_END + PAGE_SIZE).is_none());
+    // the kernel half on its own is fine, a backwards range is not
+    assert!(range(SV39_UPPER_START, SV39_UPPER_START + PAGE_SIZE).is_some());
+    assert!(range(0x2000, 0x1000).is_none());
+    info!("canonical_range_test passed!");
+}
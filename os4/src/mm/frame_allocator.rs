@@ -1,11 +1,17 @@
 //! Implementation of [`FrameAllocator`] which 
 //! controls all the frames in the operating system.
 
+use super::heap_allocator::assert_heap_ready;
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinNoIrqLock;
+use crate::trace::{self, TracePoint};
+#[cfg(feature = "frame_trace")]
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
+#[cfg(feature = "frame_trace")]
+use core::panic::Location;
 use lazy_static::*;
 
 /// manage a frame which has the same lifecycle as the tracker
@@ -42,17 +48,144 @@ trait FrameAllocator {
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
+/// number of power-of-two buckets in the free-run histogram
+pub const FREE_RUN_BUCKETS: usize = 16;
+
+/// fragmentation snapshot of the free frames
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentationInfo {
+    /// length of the longest run of contiguous free frames
+    pub largest_run: usize,
+    /// `histogram[i]` counts free runs with a length in `[2^i, 2^(i+1))`,
+    /// the last bucket also takes every longer run
+    pub histogram: [usize; FREE_RUN_BUCKETS],
+}
+
+impl FragmentationInfo {
+    fn empty() -> Self {
+        Self {
+            largest_run: 0,
+            histogram: [0; FREE_RUN_BUCKETS],
+        }
+    }
+    fn record_run(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.largest_run = self.largest_run.max(len);
+        let bucket = (usize::BITS - 1 - len.leading_zeros()) as usize;
+        self.histogram[bucket.min(FREE_RUN_BUCKETS - 1)] += 1;
+    }
+}
+
+/// frame counts of the allocator
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameStats {
+    /// frames managed by the allocator
+    pub total: usize,
+    /// frames currently handed out
+    pub allocated: usize,
+    /// frames that can still be handed out
+    pub free: usize,
+}
+
 /// an implementation for frame allocator
 pub struct StackFrameAllocator {
     current: usize,
     end: usize,
     recycled: Vec<usize>,
+    /// frames currently handed out
+    allocated: usize,
+    /// frames managed by the allocator
+    total: usize,
+    /// cached result of `scan_free_runs`, dropped whenever the free set changes
+    free_runs: Option<FragmentationInfo>,
+    /// where each frame that is still handed out was allocated, by ppn
+    #[cfg(feature = "frame_trace")]
+    sites: BTreeMap<usize, &'static Location<'static>>,
 }
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
         self.current = l.0;
         self.end = r.0;
+        self.allocated = 0;
+        self.total = r.0 - l.0;
+        self.free_runs = None;
+    }
+    /// Total, allocated and free frame counts.
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            allocated: self.allocated,
+            free: self.total - self.allocated,
+        }
+    }
+    /// Allocate a frame and remember that `site` asked for it.
+    #[cfg(feature = "frame_trace")]
+    fn alloc_at(&mut self, site: &'static Location<'static>) -> Option<PhysPageNum> {
+        let ppn = self.alloc()?;
+        self.sites.insert(ppn.0, site);
+        Some(ppn)
+    }
+    /// Frames still handed out, counted per allocation site, most frames first.
+    #[cfg(feature = "frame_trace")]
+    pub fn outstanding_sites(&self) -> Vec<(&'static Location<'static>, usize)> {
+        let mut counts: BTreeMap<&'static Location<'static>, usize> = BTreeMap::new();
+        for site in self.sites.values() {
+            *counts.entry(*site).or_insert(0) += 1;
+        }
+        let mut sites: Vec<_> = counts.into_iter().collect();
+        sites.sort_by(|a, b| b.1.cmp(&a.1));
+        sites
+    }
+    /// Allocate `pages` physically contiguous frames and return the first,
+    /// for devices that DMA into memory. They are cut from the part never
+    /// handed out, recycled frames are not searched for a long enough run.
+    pub fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 0 || self.end - self.current < pages {
+            return None;
+        }
+        self.free_runs = None;
+        let first = self.current;
+        self.current += pages;
+        self.allocated += pages;
+        Some(first.into())
+    }
+    /// Largest free run and free-run histogram, rescanned only after the free set changed.
+    pub fn fragmentation(&mut self) -> FragmentationInfo {
+        if self.free_runs.is_none() {
+            self.free_runs = Some(self.scan_free_runs());
+        }
+        self.free_runs.unwrap()
+    }
+    fn scan_free_runs(&self) -> FragmentationInfo {
+        let mut info = FragmentationInfo::empty();
+        let mut free = self.recycled.clone();
+        free.sort_unstable();
+        let mut run: Option<(usize, usize)> = None;
+        for ppn in free {
+            run = match run {
+                Some((start, end)) if ppn == end => Some((start, end + 1)),
+                Some((start, end)) => {
+                    info.record_run(end - start);
+                    Some((ppn, ppn + 1))
+                }
+                None => Some((ppn, ppn + 1)),
+            };
+        }
+        // recycled frames all lie below `current`, so only the last recycled run
+        // can touch the never-allocated tail `[current, end)`
+        let tail = self.end - self.current;
+        match run {
+            Some((start, end)) if end == self.current => info.record_run(end - start + tail),
+            Some((start, end)) => {
+                info.record_run(end - start);
+                info.record_run(tail);
+            }
+            None => info.record_run(tail),
+        }
+        info
     }
 }
 impl FrameAllocator for StackFrameAllocator {
@@ -61,17 +194,25 @@ impl FrameAllocator for StackFrameAllocator {
             current: 0,
             end: 0,
             recycled: Vec::new(),
+            allocated: 0,
+            total: 0,
+            free_runs: None,
+            #[cfg(feature = "frame_trace")]
+            sites: BTreeMap::new(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
+        self.free_runs = None;
+        let ppn = if let Some(ppn) = self.recycled.pop() {
+            ppn
         } else if self.current == self.end {
-            None
+            return None;
         } else {
             self.current += 1;
-            Some((self.current - 1).into())
-        }
+            self.current - 1
+        };
+        self.allocated += 1;
+        Some(ppn.into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
@@ -81,6 +222,10 @@ impl FrameAllocator for StackFrameAllocator {
         }
         // recycle
         self.recycled.push(ppn);
+        self.allocated -= 1;
+        self.free_runs = None;
+        #[cfg(feature = "frame_trace")]
+        self.sites.remove(&ppn);
     }
 }
 
@@ -88,8 +233,8 @@ type FrameAllocatorImpl = StackFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinNoIrqLock<FrameAllocatorImpl> =
+        SpinNoIrqLock::new(FrameAllocatorImpl::new());
 }
 
 /// initiate the frame allocator using `ekernel` and `MEMORY_END`
@@ -97,26 +242,73 @@ pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
+    assert_heap_ready("init_frame_allocator");
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr::from(ekernel as usize).ceil(),
         PhysAddr::from(MEMORY_END).floor(),
     );
 }
 
-/// allocate a frame
+/// allocate a frame, with `frame_trace` the caller is recorded as its allocation site
+#[track_caller]
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    #[cfg(feature = "frame_trace")]
+    let ppn = allocator.alloc_at(Location::caller());
+    #[cfg(not(feature = "frame_trace"))]
+    let ppn = allocator.alloc();
+    drop(allocator);
+    if let Some(ppn) = ppn {
+        trace::record(TracePoint::FrameAlloc, trace::NO_TASK, [ppn.0, 0]);
+    }
+    ppn.map(FrameTracker::new)
+}
+
+/// allocate `pages` physically contiguous, zeroed frames that a device can DMA
+/// into, in address order; freed one by one like any other frame
+#[track_caller]
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let first = allocator.alloc_contiguous(pages)?;
+    #[cfg(feature = "frame_trace")]
+    for ppn in first.0..first.0 + pages {
+        allocator.sites.insert(ppn, Location::caller());
+    }
+    drop(allocator);
+    for ppn in first.0..first.0 + pages {
+        trace::record(TracePoint::FrameAlloc, trace::NO_TASK, [ppn, 0]);
+    }
+    Some(
+        (first.0..first.0 + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// frames still allocated, counted per allocation site
+#[cfg(feature = "frame_trace")]
+pub fn frame_leak_report() -> Vec<(&'static Location<'static>, usize)> {
+    FRAME_ALLOCATOR.lock().outstanding_sites()
+}
+
+/// fragmentation of the free physical frames
+pub fn frame_fragmentation() -> FragmentationInfo {
+    FRAME_ALLOCATOR.lock().fragmentation()
+}
+
+/// total, allocated and free frame counts
+pub fn frame_stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().stats()
 }
 
 /// deallocate a frame
 fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
+    trace::record(TracePoint::FrameFree, trace::NO_TASK, [ppn.0, 0]);
 }
 
 #[allow(unused)]
+#[test_case]
 /// a simple test for frame allocator
 pub fn frame_allocator_test() {
     let mut v: Vec<FrameTracker> = Vec::new();
@@ -133,4 +325,99 @@ pub fn frame_allocator_test() {
     }
     drop(v);
     info!("frame_allocator_test passed!");
-}
\ No newline at end of file
+}
+
+#[allow(unused)]
+#[test_case]
+/// a test for the fragmentation metrics on a private allocator instance
+pub fn frame_fragmentation_test() {
+    let mut allocator = StackFrameAllocator::new();
+    allocator.init(PhysPageNum(0x100), PhysPageNum(0x120));
+    let info = allocator.fragmentation();
+    assert_eq!(info.largest_run, 0x20);
+    assert_eq!(info.histogram[5], 1);
+    let frames: Vec<PhysPageNum> = (0..0x18).map(|_| allocator.alloc().unwrap()).collect();
+    // free every other frame of the first 16, then frames 0x110..0x114
+    for ppn in frames.iter().take(16).step_by(2) {
+        allocator.dealloc(*ppn);
+    }
+    for ppn in &frames[0x10..0x14] {
+        allocator.dealloc(*ppn);
+    }
+    // free runs: 8 single frames, [0x110, 0x114) and the untouched tail [0x118, 0x120)
+    let info = allocator.fragmentation();
+    assert_eq!(info.largest_run, 8);
+    assert_eq!(info.histogram[0], 8);
+    assert_eq!(info.histogram[2], 1);
+    assert_eq!(info.histogram[3], 1);
+    // freeing [0x114, 0x118) joins the middle run with the tail
+    for ppn in &frames[0x14..0x18] {
+        allocator.dealloc(*ppn);
+    }
+    let info = allocator.fragmentation();
+    assert_eq!(info.largest_run, 0x10);
+    assert_eq!(info.histogram[4], 1);
+    info!("frame_fragmentation_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// contiguous runs come from the untouched tail, never from recycled frames
+pub fn frame_contiguous_test() {
+    let mut allocator = StackFrameAllocator::new();
+    allocator.init(PhysPageNum(0x100), PhysPageNum(0x108));
+    let single = allocator.alloc().unwrap();
+    allocator.dealloc(single);
+    assert_eq!(allocator.alloc_contiguous(3), Some(PhysPageNum(0x101)));
+    assert_eq!(allocator.stats().allocated, 3);
+    assert!(allocator.alloc_contiguous(5).is_none());
+    assert!(allocator.alloc_contiguous(0).is_none());
+    assert_eq!(allocator.alloc_contiguous(4), Some(PhysPageNum(0x104)));
+    // the recycled frame is still there for single allocations
+    assert_eq!(allocator.alloc(), Some(single));
+    assert_eq!(allocator.stats().free, 0);
+    info!("frame_contiguous_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a test for the used/total frame counters on a private allocator instance
+pub fn frame_stats_test() {
+    let mut allocator = StackFrameAllocator::new();
+    allocator.init(PhysPageNum(0x100), PhysPageNum(0x108));
+    assert_eq!(allocator.stats().total, 8);
+    assert_eq!(allocator.stats().free, 8);
+    let frames: Vec<PhysPageNum> = (0..8).map(|_| allocator.alloc().unwrap()).collect();
+    assert!(allocator.alloc().is_none());
+    assert_eq!(allocator.stats().allocated, 8);
+    assert_eq!(allocator.stats().free, 0);
+    allocator.dealloc(frames[3]);
+    allocator.dealloc(frames[5]);
+    assert_eq!(allocator.stats().allocated, 6);
+    assert_eq!(allocator.stats().free, 2);
+    // recycled frames are counted again when handed out
+    allocator.alloc().unwrap();
+    assert_eq!(allocator.stats().allocated, 7);
+    assert_eq!(allocator.stats().free, 1);
+    info!("frame_stats_test passed!");
+}
+
+#[allow(unused)]
+#[cfg(feature = "frame_trace")]
+#[test_case]
+/// a forgotten frame must show up in the leak report under the line that allocated it
+pub fn frame_trace_test() {
+    let line = line!() + 1;
+    let frame = frame_alloc().unwrap();
+    let ppn = frame.ppn;
+    core::mem::forget(frame);
+    let report = frame_leak_report();
+    assert!(report
+        .iter()
+        .any(|(site, count)| site.file() == file!() && site.line() == line && *count == 1));
+    frame_dealloc(ppn);
+    assert!(!frame_leak_report()
+        .iter()
+        .any(|(site, _)| site.file() == file!() && site.line() == line));
+    info!("frame_trace_test passed!");
+}
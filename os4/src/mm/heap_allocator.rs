@@ -2,6 +2,7 @@
 
 use crate::config::KERNEL_HEAP_SIZE;
 use buddy_system_allocator::LockedHeap;
+use core::fmt;
 
 #[global_allocator]
 /// heap allocator instance
@@ -25,7 +26,49 @@ pub fn init_heap() {
     }
 }
 
+/// whether `heap` has been given memory to hand out
+fn heap_is_ready(heap: &LockedHeap) -> bool {
+    heap.lock().stats_total_bytes() != 0
+}
+
+/// the diagnostic for a routine that ran before [`init_heap`]
+struct HeapNotReady<'a>(&'a str);
+
+impl fmt::Display for HeapNotReady<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} allocates before the kernel heap is ready, call mm::init() first",
+            self.0
+        )
+    }
+}
+
+/// Panic with a clear message if `routine` runs before [`init_heap`],
+/// instead of failing somewhere inside the allocator.
+#[track_caller]
+pub fn assert_heap_ready(routine: &str) {
+    if !heap_is_ready(&HEAP_ALLOCATOR) {
+        panic!("{}", HeapNotReady(routine));
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// an uninitialized heap must be caught and named in the diagnostic
+pub fn heap_ready_test() {
+    use alloc::string::ToString;
+    let heap = LockedHeap::empty();
+    assert!(!heap_is_ready(&heap));
+    assert!(heap_is_ready(&HEAP_ALLOCATOR));
+    let message = HeapNotReady("remap_test").to_string();
+    assert!(message.starts_with("remap_test allocates before the kernel heap is ready"));
+    assert!(message.ends_with("call mm::init() first"));
+    info!("heap_ready_test passed!");
+}
+
 #[allow(unused)]
+#[test_case]
 pub fn heap_test() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
@@ -1,10 +1,20 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
+use super::address::SV39_LOWER_END;
+use super::heap_allocator::assert_heap_ready;
+use super::shm::shm_is_attachment;
+use super::swap::{swap_alloc, swap_duplicate, swap_free, swap_read, swap_write};
+use super::{flush_tlb, frame_alloc, frame_stats, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{
+    ASLR, ASLR_MMAP_PAGES, ASLR_PIE_PAGES, ASLR_STACK_PAGES, ELF_FAULT_AROUND_PAGES,
+    LAZY_ZERO_PAGE, MEMORY_END, MMAP_AUTO_BASE, MMIO, PAGE_SIZE, PIE_BASE, SWAP_LOW_FRAMES,
+    TRAMPOLINE, TRAP_CONTEXT, USER_STACK_MAX_SIZE, USER_STACK_SIZE,
+};
+use crate::fs::Inode;
+use crate::random::random_below;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -29,12 +39,47 @@ lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
     pub static ref KERNEL_SPACE: Arc<Mutex<MemorySet>> =
         Arc::new(Mutex::new(MemorySet::new_kernel()));
+    /// a frame that stays zero, mapped read-only for lazy pages that were only read
+    static ref ZERO_FRAME: FrameTracker = frame_alloc().unwrap();
+}
+
+/// A random number of pages below `max` with `ASLR`, none without.
+fn aslr_pages(max: usize) -> usize {
+    if ASLR {
+        random_below(max)
+    } else {
+        0
+    }
 }
 
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// lowest address the program break may shrink to
+    heap_bottom: usize,
+    /// current program break
+    program_brk: usize,
+    /// lowest address the user stack may grow down to, 0 without a user stack
+    stack_limit: usize,
+    /// the address right above the user stack
+    stack_top: usize,
+    /// writes that had to copy a shared page (the zero frame, or a frame
+    /// shared by fork) into a private frame
+    cow_copies: usize,
+    /// the most pages `resident_pages` ever counted at once
+    peak_resident_pages: usize,
+    /// where mmap without an address starts looking for a hole
+    mmap_base: usize,
+    /// where the clock of `swap_out` goes on from
+    clock_hand: VirtPageNum,
+    /// pages written out to swap so far
+    swap_outs: usize,
+    /// pages read back from swap so far
+    swap_ins: usize,
+    /// some page table entry may be a swap entry, so dropping or forking the
+    /// set has to look for them
+    swap_used: bool,
 }
 
 impl MemorySet {
@@ -42,11 +87,27 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            heap_bottom: 0,
+            program_brk: 0,
+            stack_limit: 0,
+            stack_top: 0,
+            cow_copies: 0,
+            peak_resident_pages: 0,
+            mmap_base: MMAP_AUTO_BASE,
+            clock_hand: VirtPageNum(0),
+            swap_outs: 0,
+            swap_ins: 0,
+            swap_used: false,
         }
     }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// See [`PageTable::replace_root`].
+    #[allow(unused)]
+    pub fn replace_root(&mut self, root: PhysPageNum) -> PhysPageNum {
+        self.page_table.replace_root(root)
+    }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
         &mut self,
@@ -59,16 +120,468 @@ impl MemorySet {
             None,
         );
     }
+    /// Reserve `[start_va, end_va)` without allocating frames, pages are backed
+    /// one by one in `handle_lazy_fault` on first access.
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        self.push(
+            MapArea::new(start_va, end_va, MapType::Lazy, permission),
+            None,
+        );
+    }
+    /// Reserve `[start_va, end_va)` for mmap, lazily unless `shared`: then
+    /// the frames are allocated right away and a forked memory set keeps
+    /// sharing them instead of copying on write, a lazy page touched after
+    /// fork would end up private. The pages count against the mmap quota
+    /// until `unmap_range` takes them.
+    pub fn insert_mmap_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        shared: bool,
+    ) {
+        let map_type = if shared { MapType::Framed } else { MapType::Lazy };
+        let mut map_area = MapArea::new(start_va, end_va, map_type, permission);
+        map_area.shared = shared;
+        map_area.mmap = true;
+        self.push(map_area, None);
+    }
+    /// Map `inode` from `offset` on for mmap, lazily like an anonymous
+    /// private area: each page is read from the file on its first access,
+    /// the part past the end of the file reads as zeros. With `shared`,
+    /// fork hands the child the same frames and unmapping the pages writes
+    /// them back to the file.
+    pub fn insert_file_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        shared: bool,
+        file: (Arc<dyn Inode>, usize),
+    ) {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, permission);
+        map_area.shared = shared;
+        map_area.mmap = true;
+        map_area.file = Some(file);
+        self.push(map_area, None);
+    }
+    /// Map the frames of a shared memory segment from `start_va` on, shared
+    /// with fork like a MAP_SHARED area.
+    pub fn insert_shm_area(
+        &mut self,
+        start_va: VirtAddr,
+        frames: Vec<Arc<FrameTracker>>,
+        permission: MapPermission,
+    ) {
+        let start: VirtPageNum = start_va.floor();
+        let end = VirtPageNum(start.0 + frames.len());
+        let mut map_area = MapArea::new(start.into(), end.into(), MapType::Framed, permission);
+        map_area.shared = true;
+        let flags = PTEFlags::from_bits(permission.bits).unwrap();
+        for (vpn, frame) in map_area.vpn_range.into_iter().zip(frames) {
+            self.page_table.map(vpn, frame.ppn, flags);
+            map_area.data_frames.insert(vpn, frame);
+        }
+        self.areas.push(map_area);
+        self.note_resident();
+    }
+    /// Unmap the shared memory attachment starting at `start_vpn`, false if
+    /// no area there starts with the first page of a segment.
+    pub fn remove_shm_area(&mut self, start_vpn: VirtPageNum) -> bool {
+        let attached = self.areas.iter().any(|area| {
+            area.shared
+                && area.vpn_range.get_start() == start_vpn
+                && area.data_frames.get(&start_vpn).map_or(false, shm_is_attachment)
+        });
+        if attached {
+            self.remove_area_with_start_vpn(start_vpn);
+        }
+        attached
+    }
+    /// Unmap and drop the area starting at `start_vpn`, if there is one.
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some((idx, area)) = self
+            .areas
+            .iter_mut()
+            .enumerate()
+            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+        {
+            area.unmap(&mut self.page_table);
+            self.areas.remove(idx);
+        }
+    }
     fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
         map_area.map(&mut self.page_table);
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        self.note_resident();
     }
 
+    /// Whether `vpn` lies in an area that user code may access.
+    pub fn is_user_page(&self, vpn: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .any(|area| area.contains(vpn) && area.map_perm.contains(MapPermission::U))
+    }
+
+    /// Unmap one page, freeing its frame if it ever got one, and cut the page
+    /// out of its area so it is no longer reserved.
     pub fn munmap(&mut self, vpn: VirtPageNum) {
-        self.areas[0].unmap_one(&mut self.page_table, vpn);
+        let mut end = vpn;
+        end.step();
+        self.unmap_range(VPNRange::new(vpn, end));
+    }
+
+    /// Unmap every page of `range`, freeing the frames that are backed, and
+    /// cut the range out of the areas it overlaps. An area keeps the pieces
+    /// before and after the range, so it may end up split in two. Returns
+    /// how many of the pages came from mmap areas.
+    pub fn unmap_range(&mut self, range: VPNRange) -> usize {
+        let (start, end) = (range.get_start(), range.get_end());
+        let mut kept = Vec::with_capacity(self.areas.len() + 1);
+        let mut mmap_pages = 0;
+        for mut area in core::mem::take(&mut self.areas) {
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if area_end <= start || end <= area_start {
+                kept.push(area);
+                continue;
+            }
+            let tail = area.split_off(area_end.min(end));
+            let mut middle = area.split_off(area_start.max(start));
+            middle.unmap(&mut self.page_table);
+            if middle.mmap {
+                mmap_pages += middle.vpn_range.get_end().0 - middle.vpn_range.get_start().0;
+            }
+            for piece in [area, tail] {
+                if !piece.is_empty() {
+                    kept.push(piece);
+                }
+            }
+        }
+        self.areas = kept;
+        mmap_pages
+    }
+
+    /// Give every page of `range` the permission `perm`, splitting the areas
+    /// the range overlaps at its edges. Backed pages get their ptes rewritten
+    /// right away, pages still shared copy-on-write or mapping the zero frame
+    /// stay read-only until written. The caller flushes the TLB.
+    pub fn mprotect(&mut self, range: VPNRange, perm: MapPermission) {
+        let (start, end) = (range.get_start(), range.get_end());
+        let mut kept = Vec::with_capacity(self.areas.len() + 2);
+        for mut area in core::mem::take(&mut self.areas) {
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if area_end <= start || end <= area_start {
+                kept.push(area);
+                continue;
+            }
+            let tail = area.split_off(area_end.min(end));
+            let mut middle = area.split_off(area_start.max(start));
+            middle.set_perm(&mut self.page_table, perm);
+            for piece in [area, middle, tail] {
+                if !piece.is_empty() {
+                    kept.push(piece);
+                }
+            }
+        }
+        self.areas = kept;
+    }
+
+    /// Whether `vpn` lies in any area, including lazy pages that have no pte yet.
+    pub fn is_reserved(&self, vpn: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| area.contains(vpn))
+    }
+
+    /// Resolve a fault of an `access` to `vpn`: read a page back from swap,
+    /// back a page of a lazy area on its first access (see
+    /// `LAZY_ZERO_PAGE`), give a page shared after fork or with the zero
+    /// frame its own copy on a write, or grow the user stack down to `vpn`.
+    /// Pages of this set go to swap first if frames run low, see
+    /// `SWAP_LOW_FRAMES`.
+    /// `PageFault::Invalid` if `vpn` is outside every such area, already
+    /// backed, or the area does not grant `access`, which makes the fault a
+    /// genuine access violation, and also if no frame is left for the page,
+    /// the task is killed either way.
+    pub fn resolve_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> PageFault {
+        self.resolve_fault_pinned(vpn, access, None)
+    }
+    /// `resolve_fault`, but no page in `pinned` goes to swap to make room.
+    fn resolve_fault_pinned(
+        &mut self,
+        vpn: VirtPageNum,
+        access: MapPermission,
+        pinned: Option<VPNRange>,
+    ) -> PageFault {
+        let free = frame_stats().free;
+        if free < SWAP_LOW_FRAMES {
+            self.swap_out_except(SWAP_LOW_FRAMES - free, pinned);
+        }
+        let copies = self.cow_copies;
+        let fault = if let Some(swapped_in) = self.swap_in(vpn, access) {
+            if swapped_in {
+                PageFault::SwapIn
+            } else {
+                PageFault::Invalid
+            }
+        } else if access.contains(MapPermission::W) && self.handle_cow_fault(vpn) {
+            PageFault::CopyOnWrite
+        } else if self.grow_stack(vpn, access) {
+            PageFault::StackGrowth
+        } else if !self.handle_lazy_fault_with(vpn, access, LAZY_ZERO_PAGE) {
+            PageFault::Invalid
+        } else if self.cow_copies != copies {
+            // a write to a page that was reading the zero frame
+            PageFault::CopyOnWrite
+        } else {
+            PageFault::Lazy
+        };
+        self.note_resident();
+        fault
+    }
+    /// Whether `resolve_fault` made the access possible.
+    pub fn handle_lazy_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> bool {
+        self.resolve_fault(vpn, access) != PageFault::Invalid
+    }
+    /// Back every page of `range` the `access` cannot be made to yet, as
+    /// `resolve_fault` would. The pages of `range` are pinned meanwhile, so
+    /// making room for one never sends another to swap. Stops at the first
+    /// page that cannot be backed; returns whether any page changed.
+    pub fn populate(&mut self, range: VPNRange, access: MapPermission) -> bool {
+        let mut populated = false;
+        for vpn in range {
+            let backed = self.translate(vpn).map_or(false, |pte| {
+                pte.is_valid() && (!access.contains(MapPermission::W) || pte.writable())
+            });
+            if backed {
+                continue;
+            }
+            if self.resolve_fault_pinned(vpn, access, Some(range)) == PageFault::Invalid {
+                break;
+            }
+            populated = true;
+        }
+        populated
+    }
+    /// Read the swapped-out page `vpn` back into a new frame if its area
+    /// grants `access`. `None` for any other page, `Some(false)` if no frame
+    /// is left, the page then stays in swap.
+    fn swap_in(&mut self, vpn: VirtPageNum, access: MapPermission) -> Option<bool> {
+        let page_table = &mut self.page_table;
+        let slot = page_table.translate(vpn).and_then(|pte| pte.swap_slot())?;
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.contains(vpn) && area.map_perm.contains(access))?;
+        let frame = match frame_alloc() {
+            Some(frame) => frame,
+            None => return Some(false),
+        };
+        swap_read(slot, frame.ppn.get_bytes_array());
+        let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        if !page_table.try_map(vpn, frame.ppn, flags) {
+            return Some(false);
+        }
+        swap_free(slot);
+        area.data_frames.insert(vpn, Arc::new(frame));
+        self.swap_ins += 1;
+        Some(true)
+    }
+    /// Write up to `pages` pages to swap with the clock algorithm and free
+    /// their frames, returns how many went out.
+    ///
+    /// The hand sweeps the private pages of user areas in address order and
+    /// goes on where it stopped last time: a page with the A bit set loses
+    /// it and is passed over, the first one found without it goes out.
+    /// Replacement is local, only this set's pages are candidates, and
+    /// frames still shared after fork are skipped since swapping them out
+    /// frees nothing. Stops early once the swap area is full.
+    pub fn swap_out(&mut self, pages: usize) -> usize {
+        self.swap_out_except(pages, None)
+    }
+    /// `swap_out`, passing over the pages in `pinned`.
+    fn swap_out_except(&mut self, pages: usize, pinned: Option<VPNRange>) -> usize {
+        let is_pinned = |vpn: &VirtPageNum| {
+            pinned.map_or(false, |range| range.get_start() <= *vpn && *vpn < range.get_end())
+        };
+        let mut candidates: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .filter(|area| area.swappable())
+            .flat_map(|area| {
+                area.data_frames
+                    .iter()
+                    .filter(|(_, frame)| Arc::strong_count(frame) == 1)
+                    .map(|(vpn, _)| *vpn)
+            })
+            .filter(|vpn| !is_pinned(vpn))
+            .collect();
+        candidates.sort_unstable();
+        let mut i = candidates.partition_point(|vpn| *vpn < self.clock_hand);
+        // two rounds: the first one may only be clearing A bits
+        let mut steps = 2 * candidates.len();
+        let mut swapped = 0;
+        // freed only once no hart can still reach them through a stale entry
+        let mut written = Vec::new();
+        while swapped < pages && steps > 0 && !candidates.is_empty() {
+            steps -= 1;
+            if i == candidates.len() {
+                i = 0;
+            }
+            let vpn = candidates[i];
+            if self.page_table.test_and_clear_accessed(vpn) {
+                i += 1;
+                continue;
+            }
+            let slot = match swap_alloc() {
+                Some(slot) => slot,
+                None => break,
+            };
+            let area = self.areas.iter_mut().find(|area| area.contains(vpn)).unwrap();
+            let frame = area.data_frames.remove(&vpn).unwrap();
+            swap_write(slot, frame.ppn.get_bytes_array());
+            self.page_table.swap_out(vpn, slot);
+            written.push(frame);
+            candidates.remove(i);
+            swapped += 1;
+        }
+        self.clock_hand = candidates.get(i).copied().unwrap_or(VirtPageNum(0));
+        self.swap_outs += swapped;
+        self.swap_used |= swapped > 0;
+        // A bits were cleared and entries dropped, whatever space is active,
+        // and threads of this set may be running on other harts
+        flush_tlb();
+        drop(written);
+        swapped
+    }
+    /// Make the write-protected page `vpn` writable again if its area allows
+    /// writes: a frame still shared with another memory set is copied first,
+    /// the last one left keeps its frame. Returns false for any other page,
+    /// and if no frame is left for the copy; the page stays read-only then.
+    fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let page_table = &mut self.page_table;
+        let area = match self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            Some(area) if area.map_perm.contains(MapPermission::W) => area,
+            _ => return false,
+        };
+        let frame = match area.data_frames.get_mut(&vpn) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        if page_table.translate(vpn).map_or(true, |pte| !pte.is_valid() || pte.writable()) {
+            return false;
+        }
+        if Arc::strong_count(frame) > 1 {
+            let copy = match frame_alloc() {
+                Some(copy) => copy,
+                None => return false,
+            };
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(copy);
+            self.cow_copies += 1;
+        }
+        let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        page_table.remap(vpn, frame.ppn, flags);
+        true
+    }
+    /// With `lazy_zero`, reads of pages not loaded from an elf image map the
+    /// shared zero frame and only a write gets the page a private frame;
+    /// otherwise any access allocates one.
+    fn handle_lazy_fault_with(
+        &mut self,
+        vpn: VirtPageNum,
+        access: MapPermission,
+        lazy_zero: bool,
+    ) -> bool {
+        let page_table = &mut self.page_table;
+        match self
+            .areas
+            .iter_mut()
+            .find(|area| area.map_type == MapType::Lazy && area.contains(vpn))
+        {
+            Some(area)
+                if area.map_perm.contains(access) && !area.data_frames.contains_key(&vpn) =>
+            {
+                let zero_mapped = page_table.translate(vpn).map_or(false, |pte| pte.is_valid());
+                // a page loaded from an image or a file needs its own frame
+                // even for a read
+                let zero_ok = lazy_zero && area.image_of(vpn).is_none() && area.file.is_none();
+                if zero_ok && !access.contains(MapPermission::W) {
+                    if zero_mapped {
+                        return false;
+                    }
+                    if !area.map_zero(page_table, vpn) {
+                        return false;
+                    }
+                } else {
+                    if zero_mapped {
+                        // copy on write of the shared zero frame
+                        page_table.unmap(vpn);
+                    }
+                    if !area.try_map_one(page_table, vpn) {
+                        return false;
+                    }
+                    if zero_mapped {
+                        self.cow_copies += 1;
+                    }
+                    if area.image_of(vpn).is_some() {
+                        area.load_around(page_table, vpn);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the program break by `increment` bytes and return the old break.
+    ///
+    /// The heap is one lazy R|W|U area from the heap bottom up to the break:
+    /// growing extends it and its pages get frames on first touch like any
+    /// lazy page, shrinking unmaps the pages above the new break and frees
+    /// their frames. The area only shows up once the heap first grows, and
+    /// a new one is started if the old one was unmapped or had its
+    /// permission changed. Returns `None` without changing anything if
+    /// the break would drop below the heap bottom or the new pages are taken.
+    pub fn sbrk(&mut self, increment: isize) -> Option<usize> {
+        let old_brk = self.program_brk;
+        let new_brk = (old_brk as isize).checked_add(increment)?;
+        if new_brk < self.heap_bottom as isize || new_brk as usize > TRAP_CONTEXT {
+            return None;
+        }
+        let new_brk = new_brk as usize;
+        let old_end = VirtAddr::from(old_brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        if new_end > old_end {
+            let new_pages = VPNRange::new(old_end, new_end);
+            if new_pages.into_iter().any(|vpn| self.is_reserved(vpn)) {
+                return None;
+            }
+            let heap_perm = MapPermission::R | MapPermission::W | MapPermission::U;
+            let heap_start = VirtAddr::from(self.heap_bottom).floor();
+            match self.areas.iter_mut().find(|area| {
+                area.map_type == MapType::Lazy
+                    && area.map_perm == heap_perm
+                    && !area.shared
+                    && area.vpn_range.get_start() >= heap_start
+                    && area.vpn_range.get_end() == old_end
+            }) {
+                Some(heap) => heap.vpn_range = VPNRange::new(heap.vpn_range.get_start(), new_end),
+                None => self.insert_lazy_area(old_end.into(), new_end.into(), heap_perm),
+            }
+        } else {
+            self.unmap_range(VPNRange::new(new_end, old_end));
+        }
+        self.program_brk = new_brk;
+        Some(old_brk)
     }
 
     /// Mention that trampoline is not collected by areas.
@@ -81,6 +594,7 @@ impl MemorySet {
     }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
+        assert_heap_ready("MemorySet::new_kernel");
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
@@ -142,11 +656,33 @@ impl MemorySet {
             ),
             None,
         );
+        info!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    (start + len).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    ///
+    /// Segments are not copied here: their pages are lazy and filled from
+    /// `elf_data` on the first fault, together with the neighbours in the
+    /// same `ELF_FAULT_AROUND_PAGES` block, so pages never touched cost no
+    /// frame.
+    ///
+    /// A position-independent (`ET_DYN`) elf is loaded at a random page above
+    /// `PIE_BASE` and relocated there, others go where they were linked. With
+    /// `ASLR` the stack, and the heap right above it, and the mmap base move
+    /// by a random number of pages on every load.
+    pub fn from_elf(elf_data: &'static [u8]) -> (Self, usize, usize) {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
@@ -155,13 +691,20 @@ impl MemorySet {
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+        // a position-independent executable goes wherever we like
+        let base = match elf_header.pt2.type_().as_type() {
+            xmas_elf::header::Type::SharedObject => {
+                PIE_BASE + aslr_pages(ASLR_PIE_PAGES) * PAGE_SIZE
+            }
+            _ => 0,
+        };
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                let start_va: VirtAddr = (base + ph.virtual_addr() as usize).into();
+                let end_va: VirtAddr = (base + (ph.virtual_addr() + ph.mem_size()) as usize).into();
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -173,29 +716,24 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                let mut map_area = MapArea::new(start_va, end_va, MapType::Lazy, map_perm);
+                map_area.image =
+                    Some(&elf_data[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]);
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                memory_set.push(map_area, None);
             }
         }
-        // map user stack with U flags
+        if base != 0 {
+            memory_set.relocate(&elf, base);
+        }
+        // map user stack with U flags, above a guard page and a random gap
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
-        );
+        let gap = PAGE_SIZE + aslr_pages(ASLR_STACK_PAGES) * PAGE_SIZE;
+        let user_stack_top = memory_set.map_user_stack(usize::from(max_end_va) + gap);
+        // the heap starts empty right above the user stack
+        memory_set.heap_bottom = user_stack_top;
+        memory_set.program_brk = user_stack_top;
+        memory_set.mmap_base = MMAP_AUTO_BASE + aslr_pages(ASLR_MMAP_PAGES) * PAGE_SIZE;
         // map TrapContext
         memory_set.push(
             MapArea::new(
@@ -209,9 +747,201 @@ impl MemorySet {
         (
             memory_set,
             user_stack_top,
-            elf.header.pt2.entry_point() as usize,
+            base + elf.header.pt2.entry_point() as usize,
         )
     }
+    /// Apply the relocations of a position-independent `elf` loaded `base`
+    /// bytes up. A static PIE only needs `R_RISCV_RELATIVE`, anything else
+    /// asks for a dynamic linker, which there is none of.
+    fn relocate(&mut self, elf: &xmas_elf::ElfFile, base: usize) {
+        const R_RISCV_NONE: u32 = 0;
+        const R_RISCV_RELATIVE: u32 = 3;
+        for section in elf.section_iter() {
+            let relas = match section.get_data(elf) {
+                Ok(xmas_elf::sections::SectionData::Rela64(relas)) => relas,
+                _ => continue,
+            };
+            for rela in relas {
+                match rela.get_type() {
+                    R_RISCV_NONE => {}
+                    R_RISCV_RELATIVE => {
+                        let va = base + rela.get_offset() as usize;
+                        self.write_u64(va, (base as u64).wrapping_add(rela.get_addend()));
+                    }
+                    other => panic!("unsupported relocation type {} in a pie elf", other),
+                }
+            }
+        }
+    }
+    /// Write `value` at `va`, whatever the permission of its page. A lazy
+    /// page gets its frame first.
+    fn write_u64(&mut self, va: usize, value: u64) {
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            let va = VirtAddr::from(va + i);
+            self.populate(va.floor());
+            let ppn = self.page_table.translate(va.floor()).unwrap().ppn();
+            ppn.get_bytes_array()[va.page_offset()] = byte;
+        }
+    }
+    /// Give the lazy page `vpn` a frame of its own if it has none yet, no
+    /// matter what the area allows. Other pages are left alone.
+    fn populate(&mut self, vpn: VirtPageNum) {
+        let page_table = &mut self.page_table;
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|area| area.map_type == MapType::Lazy && area.contains(vpn))
+        {
+            Some(area) if !area.data_frames.contains_key(&vpn) => area,
+            _ => return,
+        };
+        if page_table.translate(vpn).map_or(false, |pte| pte.is_valid()) {
+            // it was reading the zero frame
+            page_table.unmap(vpn);
+        }
+        area.map_one(page_table, vpn);
+    }
+    /// Reserve `USER_STACK_MAX_SIZE` bytes of user stack from `stack_limit` up
+    /// and map the top `USER_STACK_SIZE` of it, returns the top of the stack.
+    /// Faults below the mapped part grow it, see `grow_stack`.
+    fn map_user_stack(&mut self, stack_limit: usize) -> usize {
+        let user_stack_top = stack_limit + USER_STACK_MAX_SIZE;
+        self.push(
+            MapArea::new(
+                (user_stack_top - USER_STACK_SIZE).into(),
+                user_stack_top.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        );
+        self.stack_limit = stack_limit;
+        self.stack_top = user_stack_top;
+        user_stack_top
+    }
+    /// Extend the user stack down to `vpn` for an `access` if `vpn` lies
+    /// between the stack limit and the mapped stack and nothing else took
+    /// the pages in between. The page below the limit stays a guard page.
+    fn grow_stack(&mut self, vpn: VirtPageNum, access: MapPermission) -> bool {
+        if self.stack_top == 0 || vpn < VirtAddr::from(self.stack_limit).floor() {
+            return false;
+        }
+        let top = VirtAddr::from(self.stack_top).floor();
+        let start = match self.areas.iter().find(|area| area.vpn_range.get_end() == top) {
+            Some(area) if vpn < area.vpn_range.get_start() && area.map_perm.contains(access) => {
+                area.vpn_range.get_start()
+            }
+            _ => return false,
+        };
+        if VPNRange::new(vpn, start).into_iter().any(|page| self.is_reserved(page)) {
+            return false;
+        }
+        let page_table = &mut self.page_table;
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_end() == top)
+            .unwrap();
+        area.vpn_range = VPNRange::new(vpn, top);
+        for page in VPNRange::new(vpn, start) {
+            if !area.try_map_one(page_table, page) {
+                // out of frames, the stack stays as it was
+                for mapped in VPNRange::new(vpn, page) {
+                    area.unmap_one(page_table, mapped);
+                }
+                area.vpn_range = VPNRange::new(start, top);
+                return false;
+            }
+        }
+        true
+    }
+    /// Duplicate a user space for fork. The backed pages of user areas are
+    /// shared with `user_space` and write-protected in both spaces, the first
+    /// write on either side copies the page (see `handle_cow_fault`), except
+    /// in MAP_SHARED areas whose frames stay writable and shared. Other
+    /// areas, like the trap context the kernel writes directly, are copied
+    /// right away. Untouched lazy pages stay untouched, swapped-out pages
+    /// are copied to swap slots of their own. `None` if no frame or swap
+    /// slot is left for the copy, which is freed again then; the pages of
+    /// `user_space` may have turned read-only anyway.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Option<MemorySet> {
+        let mut memory_set = Self::new_bare();
+        // map trampoline
+        memory_set.map_trampoline();
+        let MemorySet {
+            page_table: parent_table,
+            areas: parent_areas,
+            swap_used: parent_swap_used,
+            ..
+        } = user_space;
+        // copy data sections/trap_context/user stack/heap/mmap areas
+        for area in parent_areas.iter_mut() {
+            let mut new_area = MapArea::from_another(area);
+            if area.map_perm.contains(MapPermission::U) && area.map_type != MapType::Identical {
+                if area.shared && area.map_type == MapType::Lazy {
+                    // both sides must load each page into the same frame, so
+                    // the pages of a shared file area nobody touched yet are
+                    // backed now
+                    for vpn in area.vpn_range {
+                        if !area.data_frames.contains_key(&vpn)
+                            && !area.try_map_one(parent_table, vpn)
+                        {
+                            return None;
+                        }
+                    }
+                }
+                let flags = if area.shared {
+                    PTEFlags::from_bits(area.map_perm.bits).unwrap()
+                } else {
+                    PTEFlags::from_bits((area.map_perm - MapPermission::W).bits).unwrap()
+                };
+                for (vpn, frame) in area.data_frames.iter() {
+                    if !area.shared {
+                        parent_table.remap(*vpn, frame.ppn, flags);
+                    }
+                    new_area.data_frames.insert(*vpn, frame.clone());
+                    if !memory_set.page_table.try_map(*vpn, frame.ppn, flags) {
+                        return None;
+                    }
+                }
+                memory_set.areas.push(new_area);
+                // swapped-out pages get a slot of their own right away, the
+                // drop finds them through the area just pushed
+                if *parent_swap_used {
+                    for vpn in area.vpn_range {
+                        let pte = parent_table.translate(vpn);
+                        if let Some(slot) = pte.and_then(|pte| pte.swap_slot()) {
+                            let copy = swap_duplicate(slot)?;
+                            if !memory_set.page_table.set_swapped(vpn, copy) {
+                                swap_free(copy);
+                                return None;
+                            }
+                            memory_set.swap_used = true;
+                        }
+                    }
+                }
+                continue;
+            }
+            if !new_area.try_map(&mut memory_set.page_table) {
+                return None;
+            }
+            memory_set.areas.push(new_area);
+            for vpn in area.data_frames.keys() {
+                let src_ppn = parent_table.translate(*vpn).unwrap().ppn();
+                let dst_ppn = memory_set.translate(*vpn).unwrap().ppn();
+                dst_ppn
+                    .get_bytes_array()
+                    .copy_from_slice(src_ppn.get_bytes_array());
+            }
+        }
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.program_brk = user_space.program_brk;
+        memory_set.stack_limit = user_space.stack_limit;
+        memory_set.stack_top = user_space.stack_top;
+        memory_set.mmap_base = user_space.mmap_base;
+        memory_set.note_resident();
+        Some(memory_set)
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -222,14 +952,201 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    /// The frame backing the user page `vpn` if it has one of its own.
+    pub fn user_frame(&self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        self.areas
+            .iter()
+            .find(|area| area.map_perm.contains(MapPermission::U) && area.contains(vpn))?
+            .data_frames
+            .get(&vpn)
+            .cloned()
+    }
+    /// Like `user_frame`, but a frame still shared after fork is copied
+    /// first, keeping the permissions of the pte, so a write to it is seen by
+    /// this set only. Frames of MAP_SHARED areas stay shared. `None` also if
+    /// no frame can be had for the copy.
+    pub fn private_user_frame(&mut self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        let page_table = &mut self.page_table;
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.map_perm.contains(MapPermission::U) && area.contains(vpn))?;
+        let frame = area.data_frames.get_mut(&vpn)?;
+        if !area.shared && Arc::strong_count(frame) > 1 {
+            let flags = page_table.translate(vpn)?.flags();
+            let copy = frame_alloc()?;
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            *frame = Arc::new(copy);
+            page_table.remap(vpn, frame.ppn, flags);
+            self.cow_copies += 1;
+        }
+        Some(frame.clone())
+    }
+    /// Frames owned by this memory set: area frames and page table frames.
+    pub fn frame_count(&self) -> usize {
+        self.areas.iter().map(|area| area.data_frames.len()).sum::<usize>()
+            + self.page_table.frame_count()
+    }
+    /// Writes so far that copied a shared page into a private frame.
+    pub fn cow_copies(&self) -> usize {
+        self.cow_copies
+    }
+    pub fn area_count(&self) -> usize {
+        self.areas.len()
+    }
+    /// Pages backed by a frame of their own right now, the trap context
+    /// included. Pages still reading the zero frame do not count.
+    pub fn resident_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.data_frames.len()).sum()
+    }
+    /// The most pages that were ever resident at once.
+    pub fn peak_resident_pages(&self) -> usize {
+        self.peak_resident_pages
+    }
+    /// Where mmap without an address starts looking for a hole.
+    pub fn mmap_base(&self) -> usize {
+        self.mmap_base
+    }
+    /// Pages sitting in swap right now.
+    pub fn swapped_pages(&self) -> usize {
+        if !self.swap_used {
+            return 0;
+        }
+        self.areas
+            .iter()
+            .flat_map(|area| area.vpn_range)
+            .filter_map(|vpn| self.page_table.translate(vpn))
+            .filter(|pte| pte.swap_slot().is_some())
+            .count()
+    }
+    /// Pages written out to swap so far.
+    pub fn swap_outs(&self) -> usize {
+        self.swap_outs
+    }
+    /// Pages read back from swap so far.
+    pub fn swap_ins(&self) -> usize {
+        self.swap_ins
+    }
+    /// Raise the peak to what is resident now, after frames were added.
+    fn note_resident(&mut self) {
+        self.peak_resident_pages = self.peak_resident_pages.max(self.resident_pages());
+    }
+    /// Print the areas and then every mapping of the page table.
+    pub fn debug_print(&self) {
+        println!("[kernel] {} areas:", self.areas.len());
+        for area in self.areas.iter() {
+            println!(
+                "  vpn [{:#x}, {:#x}) {:?} {:?}, {} pages backed",
+                area.vpn_range.get_start().0,
+                area.vpn_range.get_end().0,
+                area.map_type,
+                area.map_perm,
+                area.data_frames.len()
+            );
+        }
+        println!("[kernel] page table:");
+        self.page_table.dump();
+    }
+    /// Number of pages in the largest unmapped hole of user space below `TRAP_CONTEXT`.
+    pub fn largest_free_gap(&self) -> usize {
+        self.largest_free_gap_below(VirtAddr::from(TRAP_CONTEXT).floor())
+    }
+    /// Start of the lowest run of `pages` unmapped pages at or above `from`
+    /// that ends below the SV39 hole.
+    pub fn find_free_range(&self, pages: usize, from: VirtPageNum) -> Option<VirtPageNum> {
+        self.find_free_range_below(pages, from, VirtAddr::from(SV39_LOWER_END).floor())
+    }
+    fn find_free_range_below(
+        &self,
+        pages: usize,
+        from: VirtPageNum,
+        limit: VirtPageNum,
+    ) -> Option<VirtPageNum> {
+        let mut ranges: Vec<(usize, usize)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start().0, area.vpn_range.get_end().0))
+            .collect();
+        ranges.sort_unstable();
+        let mut cursor = from.0;
+        for (start, end) in ranges {
+            if end <= cursor {
+                continue;
+            }
+            if start >= cursor.saturating_add(pages) {
+                break;
+            }
+            cursor = end;
+        }
+        match cursor.checked_add(pages) {
+            Some(end) if end <= limit.0 => Some(VirtPageNum(cursor)),
+            _ => None,
+        }
+    }
+    fn largest_free_gap_below(&self, limit: VirtPageNum) -> usize {
+        let limit = limit.0;
+        let mut ranges: Vec<(usize, usize)> = self
+            .areas
+            .iter()
+            .map(|area| (area.vpn_range.get_start().0, area.vpn_range.get_end().0))
+            .collect();
+        ranges.sort_unstable();
+        let mut largest = 0;
+        let mut cursor = 0;
+        for (start, end) in ranges {
+            if start >= limit {
+                break;
+            }
+            largest = largest.max(start.saturating_sub(cursor));
+            cursor = cursor.max(end);
+        }
+        largest.max(limit.saturating_sub(cursor))
+    }
+}
+
+impl Drop for MemorySet {
+    /// Frames go back with the areas, but shared file areas write their
+    /// pages back first. Swap slots are only found through the page table.
+    fn drop(&mut self) {
+        for area in self.areas.iter() {
+            for vpn in area.data_frames.keys() {
+                area.write_back(*vpn);
+            }
+        }
+        if !self.swap_used {
+            return;
+        }
+        for area in self.areas.iter() {
+            for vpn in area.vpn_range {
+                if let Some(slot) = self.page_table.take_swapped(vpn) {
+                    swap_free(slot);
+                }
+            }
+        }
+    }
 }
 
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames shared with a forked memory set are write-protected until copied
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// a MAP_SHARED area: fork hands the child the same frames, writable on both sides
+    shared: bool,
+    /// made by mmap, its pages count against the mmap quota
+    mmap: bool,
+    /// the bytes of the elf segment a lazy area is loaded from on demand:
+    /// page i of the area starts with `image[i * PAGE_SIZE..]`, pages past
+    /// the end of the image start zeroed
+    image: Option<&'static [u8]>,
+    /// the file a lazy area made by mmap is loaded from on demand and the
+    /// offset in it of the area's first page; a shared area writes its
+    /// pages back when they are unmapped
+    file: Option<(Arc<dyn Inode>, usize)>,
 }
 
 impl MapArea {
@@ -246,40 +1163,215 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            shared: false,
+            mmap: false,
+            image: None,
+            file: None,
+        }
+    }
+    /// An area with the same range, type, permission and image but no
+    /// frames yet.
+    #[allow(unused)]
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
+            data_frames: BTreeMap::new(),
+            map_type: another.map_type,
+            map_perm: another.map_perm,
+            shared: another.shared,
+            mmap: another.mmap,
+            image: another.image,
+            file: another.file.clone(),
+        }
+    }
+    /// The part of the image that goes on `vpn`, `None` if the page starts
+    /// zeroed.
+    fn image_of(&self, vpn: VirtPageNum) -> Option<&'static [u8]> {
+        let image = self.image?;
+        let start = (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+        let page = image.get(start..)?;
+        Some(&page[..page.len().min(PAGE_SIZE)]).filter(|page| !page.is_empty())
+    }
+    /// The file the area maps and where `vpn` starts in it.
+    fn file_of(&self, vpn: VirtPageNum) -> Option<(&Arc<dyn Inode>, usize)> {
+        let (inode, start) = self.file.as_ref()?;
+        Some((inode, start + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE))
+    }
+    /// Write the backed page `vpn` of a shared file area back to the file,
+    /// as far as the file goes: a mapping never makes the file longer.
+    /// Every backed page is written, the kernel's own writes to user pages
+    /// leave no dirty bit to go by.
+    fn write_back(&self, vpn: VirtPageNum) {
+        if !self.shared {
+            return;
+        }
+        let ((inode, pos), frame) = match (self.file_of(vpn), self.data_frames.get(&vpn)) {
+            (Some(file), Some(frame)) => (file, frame),
+            _ => return,
+        };
+        let size = inode.size();
+        if pos < size {
+            let len = (size - pos).min(PAGE_SIZE);
+            inode.write_at(pos, &frame.ppn.get_bytes_array()[..len]);
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        assert!(self.try_map_one(page_table, vpn), "no frame left for vpn {:?}", vpn);
+    }
+    /// Like `map_one`, but false instead of a panic if no frame is left for
+    /// the page or for a page table on its path, for the page fault paths.
+    /// Nothing is mapped then and the page's frame is freed again.
+    pub fn try_map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum;
+        let mut frame = None;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
-            MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+            MapType::Framed | MapType::Lazy => {
+                let data_frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                ppn = data_frame.ppn;
+                if let Some(data) = self.image_of(vpn) {
+                    ppn.get_bytes_array()[..data.len()].copy_from_slice(data);
+                }
+                if let Some((inode, pos)) = self.file_of(vpn) {
+                    // past the end of the file the page stays zeroed
+                    inode.read_at(pos, ppn.get_bytes_array());
+                }
+                frame = Some(data_frame);
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        if !page_table.try_map(vpn, ppn, pte_flags) {
+            return false;
+        }
+        if let Some(frame) = frame {
+            self.data_frames.insert(vpn, Arc::new(frame));
+        }
+        true
+    }
+    /// Load the image pages without a frame in the aligned block of
+    /// `ELF_FAULT_AROUND_PAGES` pages around `vpn`.
+    fn load_around(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let first = vpn.0 - vpn.0 % ELF_FAULT_AROUND_PAGES;
+        for page in (first..first + ELF_FAULT_AROUND_PAGES).map(VirtPageNum) {
+            // neighbours are a bonus, never worth swapping for
+            if frame_stats().free < SWAP_LOW_FRAMES {
+                break;
+            }
+            if self.contains(page)
+                && !self.data_frames.contains_key(&page)
+                && self.image_of(page).is_some()
+                && page_table.translate(page).and_then(|pte| pte.swap_slot()).is_none()
+                && !self.try_map_one(page_table, page)
+            {
+                break;
+            }
+        }
+    }
+    /// Map `vpn` read-only to the shared zero frame, the page stays without a
+    /// frame of its own until it is written. False if no frame is left for a
+    /// page table on the path.
+    pub fn map_zero(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let pte_flags = PTEFlags::from_bits((self.map_perm - MapPermission::W).bits).unwrap();
+        page_table.try_map(vpn, ZERO_FRAME.ppn, pte_flags)
+    }
+    /// Change the permission of the whole area and of the pages mapped in it.
+    pub fn set_perm(&mut self, page_table: &mut PageTable, perm: MapPermission) {
+        self.map_perm = perm;
+        for vpn in self.vpn_range {
+            let pte = match page_table.translate(vpn) {
+                Some(pte) if pte.is_valid() => pte,
+                _ => continue,
+            };
+            // a shared frame or the zero frame must keep faulting on writes
+            let write_protect = match self.data_frames.get(&vpn) {
+                Some(frame) => !self.shared && Arc::strong_count(frame) > 1,
+                None => self.map_type == MapType::Lazy,
+            };
+            let perm = if write_protect { perm - MapPermission::W } else { perm };
+            page_table.remap(vpn, pte.ppn(), PTEFlags::from_bits(perm.bits).unwrap());
+        }
     }
-    #[allow(unused)]
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        #[allow(clippy::single_match)]
+        if let Some(slot) = page_table.take_swapped(vpn) {
+            swap_free(slot);
+            return;
+        }
+        self.write_back(vpn);
         match self.map_type {
             MapType::Framed => {
                 self.data_frames.remove(&vpn);
             }
-            _ => {}
+            MapType::Lazy => {
+                // a page that was never touched has no frame and no pte,
+                // one that was only read maps the shared zero frame
+                if self.data_frames.remove(&vpn).is_none()
+                    && !page_table.translate(vpn).map_or(false, |pte| pte.is_valid())
+                {
+                    return;
+                }
+            }
+            MapType::Identical => {}
         }
         page_table.unmap(vpn);
     }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Whether the backed pages may go to swap: those of private user areas
+    /// with frames of their own.
+    fn swappable(&self) -> bool {
+        self.map_type != MapType::Identical
+            && !self.shared
+            && self.map_perm.contains(MapPermission::U)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.vpn_range.get_start() == self.vpn_range.get_end()
+    }
+    /// Split the area at `at`: `self` keeps `[start, at)` and the returned
+    /// area takes `[at, end)` together with the frames and image in it.
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        self.vpn_range = VPNRange::new(start, at);
+        let offset = (at.0 - start.0) * PAGE_SIZE;
+        let image = self.image.map(|image| image.split_at(image.len().min(offset)));
+        self.image = image.map(|(head, _)| head);
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            shared: self.shared,
+            mmap: self.mmap,
+            image: image.map(|(_, tail)| tail),
+            file: self.file.clone().map(|(inode, start)| (inode, start + offset)),
+        }
+    }
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Lazy {
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
     }
-    #[allow(unused)]
+    /// Like `map`, but false as soon as a page finds no frame. The pages
+    /// mapped before it stay mapped.
+    pub fn try_map(&mut self, page_table: &mut PageTable) -> bool {
+        if self.map_type == MapType::Lazy {
+            return true;
+        }
+        for vpn in self.vpn_range {
+            if !self.try_map_one(page_table, vpn) {
+                return false;
+            }
+        }
+        true
+    }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
@@ -309,11 +1401,28 @@ impl MapArea {
     }
 }
 
+/// How a page fault of a user task was resolved.
 #[derive(Copy, Clone, PartialEq, Debug)]
-/// map type for memory set: identical or framed
+pub enum PageFault {
+    /// a lazy page got its own frame, or the shared zero frame for a read
+    Lazy,
+    /// a swapped-out page was read back in
+    SwapIn,
+    /// a shared page got a private copy, or write permission back
+    CopyOnWrite,
+    /// the user stack was extended down to the faulting page
+    StackGrowth,
+    /// the access is not allowed there, the task has to be killed
+    Invalid,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// map type for memory set: identical, framed or framed on first access
 pub enum MapType {
     Identical,
     Framed,
+    /// framed, but every frame is allocated by the page fault handler
+    Lazy,
 }
 
 bitflags! {
@@ -326,8 +1435,37 @@ bitflags! {
     }
 }
 
+// ptes are built straight from `map_perm.bits`, so the shared flags have to sit at the same bits
+const _: () = assert!(
+    MapPermission::R.bits() == PTEFlags::R.bits()
+        && MapPermission::W.bits() == PTEFlags::W.bits()
+        && MapPermission::X.bits() == PTEFlags::X.bits()
+        && MapPermission::U.bits() == PTEFlags::U.bits()
+);
+
+/// mmap `port` flag: map at `start` even when it is 0, which otherwise lets
+/// the kernel choose the address
+pub const MAP_FIXED: usize = 0x10;
+/// mmap `port` flag: writes are seen by every task the area was forked into
+pub const MAP_SHARED: usize = 0x20;
+/// mmap `port` flag: the default, writes stay private to the task after fork
+pub const MAP_PRIVATE: usize = 0x40;
+
+impl MapPermission {
+    /// Translate mmap's `port` (bit 0 = R, bit 1 = W, bit 2 = X) into a
+    /// permission, `None` if unknown bits are set.
+    pub fn from_port(port: usize) -> Option<Self> {
+        if port & !0x7 != 0 {
+            return None;
+        }
+        Self::from_bits((port as u8) << 1)
+    }
+}
+
 #[allow(unused)]
+#[test_case]
 pub fn remap_test() {
+    assert_heap_ready("remap_test");
     let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
@@ -348,4 +1486,699 @@ pub fn remap_test() {
         .unwrap()
         .executable());
     info!("remap_test passed!");
-}
\ No newline at end of file
+}
+
+#[allow(unused)]
+#[test_case]
+pub fn free_gap_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let limit = VirtPageNum(0x30);
+    assert_eq!(memory_set.largest_free_gap_below(limit), 0x30);
+    // holes: [0, 0x10), [0x11, 0x20), [0x24, 0x30)
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x11).into(), user_rw);
+    memory_set.insert_framed_area(VirtPageNum(0x20).into(), VirtPageNum(0x24).into(), user_rw);
+    assert_eq!(memory_set.largest_free_gap_below(limit), 0x10);
+    // holes: [0x8, 0x10), [0x11, 0x20), [0x24, 0x30)
+    memory_set.insert_framed_area(VirtPageNum(0).into(), VirtPageNum(0x8).into(), user_rw);
+    assert_eq!(memory_set.largest_free_gap_below(limit), 0xf);
+    info!("free_gap_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// first fit above `from`, skipping areas, and nothing past the limit
+pub fn free_range_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x11).into(), user_rw);
+    memory_set.insert_framed_area(VirtPageNum(0x20).into(), VirtPageNum(0x24).into(), user_rw);
+    let limit = VirtPageNum(0x30);
+    let find = |pages, from| memory_set.find_free_range_below(pages, VirtPageNum(from), limit);
+    assert_eq!(find(4, 0x10), Some(VirtPageNum(0x11)));
+    assert_eq!(find(0xf, 0x10), Some(VirtPageNum(0x11)));
+    assert_eq!(find(0xc, 0x10), Some(VirtPageNum(0x24)));
+    assert_eq!(find(0x10, 0x10), None);
+    assert_eq!(find(1, 0x21), Some(VirtPageNum(0x24)));
+    assert_eq!(find(1, 0), Some(VirtPageNum(0)));
+    assert_eq!(find(0xc, 0x20), Some(VirtPageNum(0x24)));
+    assert_eq!(find(0xd, 0x20), None);
+    assert_eq!(find(usize::MAX, 0), None);
+    info!("free_range_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+pub fn fork_copy_test() {
+    let mut parent = MemorySet::new_bare();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let data: Vec<u8> = (0..PAGE_SIZE + 16).map(|i| i as u8).collect();
+    parent.push(
+        MapArea::new(
+            VirtPageNum(0x10).into(),
+            VirtPageNum(0x12).into(),
+            MapType::Framed,
+            user_rw,
+        ),
+        Some(&data),
+    );
+    parent.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x24).into(), user_rw);
+    assert!(parent.handle_lazy_fault(VirtPageNum(0x21), MapPermission::W));
+    let page_of = |memory_set: &MemorySet, vpn: usize| {
+        memory_set.translate(VirtPageNum(vpn)).unwrap().ppn().get_bytes_array()
+    };
+    page_of(&parent, 0x21)[7] = 0x5a;
+
+    let mut child = MemorySet::from_existed_user(&mut parent).unwrap();
+    assert_eq!(&page_of(&child, 0x10)[..], &data[..PAGE_SIZE]);
+    assert_eq!(&page_of(&child, 0x11)[..16], &data[PAGE_SIZE..]);
+    assert_eq!(page_of(&child, 0x21)[7], 0x5a);
+    // untouched lazy pages are not backed in the child either
+    assert!(child
+        .translate(VirtPageNum(0x20))
+        .map_or(true, |pte| !pte.is_valid()));
+    // backed pages are shared and write-protected on both sides
+    for vpn in [0x10, 0x11, 0x21] {
+        let p = parent.translate(VirtPageNum(vpn)).unwrap();
+        let c = child.translate(VirtPageNum(vpn)).unwrap();
+        assert_eq!(p.ppn(), c.ppn());
+        assert!(!p.writable() && !c.writable());
+    }
+
+    // the first writer gets a copy, the last sharer keeps the frame
+    let shared = parent.translate(VirtPageNum(0x10)).unwrap().ppn();
+    assert!(parent.handle_lazy_fault(VirtPageNum(0x10), MapPermission::W));
+    assert_ne!(parent.translate(VirtPageNum(0x10)).unwrap().ppn(), shared);
+    assert!(parent.translate(VirtPageNum(0x10)).unwrap().writable());
+    assert_eq!(parent.cow_copies(), 1);
+    assert!(child.handle_lazy_fault(VirtPageNum(0x10), MapPermission::W));
+    assert_eq!(child.translate(VirtPageNum(0x10)).unwrap().ppn(), shared);
+    assert_eq!(child.cow_copies(), 0);
+    // a writable page is not a copy-on-write fault
+    assert!(!child.handle_lazy_fault(VirtPageNum(0x10), MapPermission::W));
+
+    // the two spaces no longer share frames
+    assert!(child.handle_lazy_fault(VirtPageNum(0x21), MapPermission::W));
+    page_of(&parent, 0x10)[0] = 0xff;
+    page_of(&child, 0x21)[7] = 0;
+    assert_eq!(page_of(&child, 0x10)[0], data[0]);
+    assert_eq!(page_of(&parent, 0x21)[7], 0x5a);
+    info!("fork_copy_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+pub fn lazy_zero_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let vpn = VirtPageNum(0x20);
+    let ppn_of = |memory_set: &MemorySet| memory_set.translate(vpn).unwrap().ppn();
+    for lazy_zero in [false, true] {
+        let mut memory_set = MemorySet::new_bare();
+        memory_set.insert_lazy_area(vpn.into(), VirtPageNum(0x21).into(), user_rw);
+        assert!(memory_set.handle_lazy_fault_with(vpn, MapPermission::R, lazy_zero));
+        assert!(ppn_of(&memory_set).get_bytes_array().iter().all(|b| *b == 0));
+        // a read-only access takes a private frame only in eager mode
+        assert_eq!(memory_set.areas[0].data_frames.contains_key(&vpn), !lazy_zero);
+        assert_eq!(ppn_of(&memory_set) == ZERO_FRAME.ppn, lazy_zero);
+        assert!(!memory_set.handle_lazy_fault_with(vpn, MapPermission::R, lazy_zero));
+        if lazy_zero {
+            // the first write replaces the zero frame with a private one
+            assert!(memory_set.handle_lazy_fault_with(vpn, MapPermission::W, lazy_zero));
+            assert!(memory_set.areas[0].data_frames.contains_key(&vpn));
+            assert_ne!(ppn_of(&memory_set), ZERO_FRAME.ppn);
+            assert!(memory_set.translate(vpn).unwrap().writable());
+        }
+        memory_set.munmap(vpn);
+        assert!(memory_set.translate(vpn).map_or(true, |pte| !pte.is_valid()));
+    }
+    assert!(ZERO_FRAME.ppn.get_bytes_array().iter().all(|b| *b == 0));
+    info!("lazy_zero_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// only writes to pages still sharing the zero frame count as copies
+pub fn cow_copies_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x24).into(), user_rw);
+    let shared = [VirtPageNum(0x20), VirtPageNum(0x21), VirtPageNum(0x22)];
+    for vpn in shared {
+        assert!(memory_set.handle_lazy_fault_with(vpn, MapPermission::R, true));
+    }
+    assert_eq!(memory_set.cow_copies(), 0);
+    for vpn in shared {
+        assert!(memory_set.handle_lazy_fault_with(vpn, MapPermission::W, true));
+    }
+    assert_eq!(memory_set.cow_copies(), 3);
+    // private pages are written in place, an untouched page just gets a frame
+    for vpn in shared {
+        assert!(!memory_set.handle_lazy_fault_with(vpn, MapPermission::W, true));
+    }
+    assert!(memory_set.handle_lazy_fault_with(VirtPageNum(0x23), MapPermission::W, true));
+    assert_eq!(memory_set.cow_copies(), 3);
+    info!("cow_copies_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// only pages with a frame of their own are resident, and the peak stays
+/// after they are unmapped
+pub fn resident_pages_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x12).into(), user_rw);
+    memory_set.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x24).into(), user_rw);
+    assert_eq!(memory_set.area_count(), 2);
+    assert_eq!(memory_set.resident_pages(), 2);
+    assert_eq!(memory_set.peak_resident_pages(), 2);
+    // a read may only map the zero frame, a write always takes a frame
+    memory_set.resolve_fault(VirtPageNum(0x20), MapPermission::R);
+    let after_read = if LAZY_ZERO_PAGE { 2 } else { 3 };
+    assert_eq!(memory_set.resident_pages(), after_read);
+    memory_set.resolve_fault(VirtPageNum(0x21), MapPermission::W);
+    memory_set.resolve_fault(VirtPageNum(0x22), MapPermission::W);
+    assert_eq!(memory_set.resident_pages(), after_read + 2);
+    let peak = memory_set.peak_resident_pages();
+    assert_eq!(peak, after_read + 2);
+    memory_set.remove_area_with_start_vpn(VirtPageNum(0x20));
+    assert_eq!(memory_set.area_count(), 1);
+    assert_eq!(memory_set.resident_pages(), 2);
+    assert_eq!(memory_set.peak_resident_pages(), peak);
+    info!("resident_pages_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// each kind of fault is told apart, and nothing is mapped for an invalid one
+pub fn page_fault_kind_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let user_r = MapPermission::R | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x22).into(), user_rw);
+    memory_set.insert_lazy_area(VirtPageNum(0x30).into(), VirtPageNum(0x31).into(), user_r);
+    let read = MapPermission::R;
+    let write = MapPermission::W;
+    let fault = |memory_set: &mut MemorySet, vpn, access| {
+        memory_set.resolve_fault(VirtPageNum(vpn), access)
+    };
+    assert_eq!(fault(&mut memory_set, 0x20, read), PageFault::Lazy);
+    // a read either mapped the zero frame or already gave the page its own frame
+    let write_after_read = if LAZY_ZERO_PAGE {
+        PageFault::CopyOnWrite
+    } else {
+        PageFault::Invalid
+    };
+    assert_eq!(fault(&mut memory_set, 0x20, write), write_after_read);
+    assert_eq!(fault(&mut memory_set, 0x21, write), PageFault::Lazy);
+    // backed and writable already, the fault came from something else
+    assert_eq!(fault(&mut memory_set, 0x21, write), PageFault::Invalid);
+    // the area is read-only, and nothing at all is mapped at 0x40
+    assert_eq!(fault(&mut memory_set, 0x30, write), PageFault::Invalid);
+    assert!(memory_set.translate(VirtPageNum(0x30)).map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(fault(&mut memory_set, 0x40, read), PageFault::Invalid);
+    // after fork, a write to a shared page is copy on write on either side
+    let mut child = MemorySet::from_existed_user(&mut memory_set).unwrap();
+    assert_eq!(fault(&mut child, 0x21, write), PageFault::CopyOnWrite);
+    assert_eq!(fault(&mut memory_set, 0x21, write), PageFault::CopyOnWrite);
+    assert_eq!(fault(&mut memory_set, 0x21, read), PageFault::Invalid);
+    info!("page_fault_kind_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// the stack grows page by page down to its limit, the guard page below stays unmapped
+pub fn stack_growth_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let limit = VirtPageNum(0x20);
+    let top: usize = memory_set.map_user_stack(VirtAddr::from(limit).into());
+    let top = VirtAddr::from(top).floor();
+    let initial = USER_STACK_SIZE / PAGE_SIZE;
+    let lowest_mapped = VirtPageNum(top.0 - initial);
+    assert!(memory_set.translate(lowest_mapped).unwrap().is_valid());
+    let below = VirtPageNum(lowest_mapped.0 - 2);
+    assert!(memory_set.translate(below).map_or(true, |pte| !pte.is_valid()));
+    // touching two pages below the stack maps both of them
+    assert_eq!(memory_set.resolve_fault(below, MapPermission::W), PageFault::StackGrowth);
+    for vpn in below.0..top.0 {
+        assert!(memory_set.translate(VirtPageNum(vpn)).unwrap().writable());
+    }
+    assert_eq!(memory_set.area_count(), 1);
+    // the stack may not be executed, and never passes its limit
+    assert_eq!(
+        memory_set.resolve_fault(VirtPageNum(below.0 - 1), MapPermission::X),
+        PageFault::Invalid
+    );
+    assert_eq!(memory_set.resolve_fault(limit, MapPermission::R), PageFault::StackGrowth);
+    let guard = VirtPageNum(limit.0 - 1);
+    assert_eq!(memory_set.resolve_fault(guard, MapPermission::W), PageFault::Invalid);
+    assert!(memory_set.translate(guard).map_or(true, |pte| !pte.is_valid()));
+    // pages somebody else reserved stop the growth
+    let mut memory_set = MemorySet::new_bare();
+    let top = VirtAddr::from(memory_set.map_user_stack(VirtAddr::from(limit).into())).floor();
+    memory_set.insert_lazy_area(
+        VirtPageNum(limit.0 + 1).into(),
+        VirtPageNum(limit.0 + 2).into(),
+        MapPermission::R | MapPermission::U,
+    );
+    assert_eq!(memory_set.resolve_fault(limit, MapPermission::W), PageFault::Invalid);
+    assert_eq!(
+        memory_set.resolve_fault(VirtPageNum(top.0 - initial - 1), MapPermission::W),
+        PageFault::StackGrowth
+    );
+    info!("stack_growth_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// the heap stays one lazy area that grows and shrinks with the break
+pub fn heap_area_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let bottom = VirtAddr::from(VirtPageNum(0x40)).0;
+    memory_set.heap_bottom = bottom;
+    memory_set.program_brk = bottom;
+    assert_eq!(memory_set.sbrk(-1), None);
+    assert_eq!(memory_set.area_count(), 0);
+    assert_eq!(memory_set.sbrk(PAGE_SIZE as isize + 1), Some(bottom));
+    assert_eq!(memory_set.sbrk(PAGE_SIZE as isize), Some(bottom + PAGE_SIZE + 1));
+    assert_eq!(memory_set.area_count(), 1);
+    // nothing is backed until touched
+    assert_eq!(memory_set.resident_pages(), 0);
+    assert!(memory_set.translate(VirtPageNum(0x42)).map_or(true, |pte| !pte.is_valid()));
+    for vpn in 0x40..0x43 {
+        assert_eq!(memory_set.resolve_fault(VirtPageNum(vpn), MapPermission::W), PageFault::Lazy);
+    }
+    assert!(memory_set.is_reserved(VirtPageNum(0x42)));
+    assert!(!memory_set.is_reserved(VirtPageNum(0x43)));
+    assert_eq!(memory_set.resident_pages(), 3);
+    // shrinking to the middle of a page keeps that page
+    let brk = bottom + 2 * PAGE_SIZE + 1;
+    assert_eq!(memory_set.sbrk(-(PAGE_SIZE as isize)), Some(brk));
+    assert_eq!(memory_set.area_count(), 1);
+    assert_eq!(memory_set.resident_pages(), 2);
+    assert!(memory_set.translate(VirtPageNum(0x42)).map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(memory_set.sbrk(-((brk - PAGE_SIZE - bottom) as isize)), Some(brk - PAGE_SIZE));
+    assert_eq!(memory_set.sbrk(0), Some(bottom));
+    assert_eq!(memory_set.area_count(), 0);
+    assert_eq!(memory_set.resident_pages(), 0);
+    info!("heap_area_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// loads of the same app get their stack and mmap base moved around, and
+/// relocations get written even into read-only pages
+pub fn aslr_test() {
+    use crate::loader::get_app_data;
+    let mut layouts = Vec::new();
+    for _ in 0..8 {
+        let (memory_set, user_sp, _) = MemorySet::from_elf(get_app_data(0));
+        let mmap_base = memory_set.mmap_base();
+        assert_eq!(mmap_base % PAGE_SIZE, 0);
+        let mmap_range = MMAP_AUTO_BASE..MMAP_AUTO_BASE + ASLR_MMAP_PAGES * PAGE_SIZE;
+        assert!(mmap_range.contains(&mmap_base));
+        assert_eq!(user_sp % PAGE_SIZE, 0);
+        assert_eq!(memory_set.heap_bottom, user_sp);
+        layouts.push((user_sp, mmap_base));
+    }
+    assert_eq!(layouts.iter().any(|layout| *layout != layouts[0]), ASLR);
+    let mut memory_set = MemorySet::new_bare();
+    let user_r = MapPermission::R | MapPermission::U;
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x12).into(), user_r);
+    let va = VirtAddr::from(VirtPageNum(0x11)).0 - 4;
+    memory_set.write_u64(va, 0x1122_3344_5566_7788);
+    let mut bytes = [0u8; 8];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let va = VirtAddr::from(va + i);
+        let ppn = memory_set.translate(va.floor()).unwrap().ppn();
+        *byte = ppn.get_bytes_array()[va.page_offset()];
+    }
+    assert_eq!(u64::from_le_bytes(bytes), 0x1122_3344_5566_7788);
+    info!("aslr_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// elf pages are only read from the image when first touched, and an area
+/// cut by munmap still loads the right part of it
+pub fn elf_demand_paging_test() {
+    use crate::loader::get_app_data;
+    let elf_data = get_app_data(0);
+    let (mut memory_set, _, entry) = MemorySet::from_elf(elf_data);
+    // only the user stack and the trap context are backed
+    let backed = USER_STACK_SIZE / PAGE_SIZE + 1;
+    assert_eq!(memory_set.resident_pages(), backed);
+    let vpn = VirtAddr::from(entry).floor();
+    assert!(memory_set.translate(vpn).map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(memory_set.resolve_fault(vpn, MapPermission::X), PageFault::Lazy);
+    let area = memory_set.areas.iter().find(|area| area.contains(vpn)).unwrap();
+    // the rest of the block around the entry came along
+    let first = vpn.0 - vpn.0 % ELF_FAULT_AROUND_PAGES;
+    let around = (first..first + ELF_FAULT_AROUND_PAGES)
+        .map(VirtPageNum)
+        .filter(|page| area.contains(*page) && area.image_of(*page).is_some())
+        .count();
+    assert_eq!(memory_set.resident_pages(), backed + around);
+    let expected = area.image_of(vpn).unwrap();
+    let page = memory_set.translate(vpn).unwrap().ppn().get_bytes_array();
+    assert_eq!(&page[..expected.len()], expected);
+    assert!(page[expected.len()..].iter().all(|byte| *byte == 0));
+    // text stays read-only
+    assert_eq!(memory_set.resolve_fault(vpn, MapPermission::W), PageFault::Invalid);
+
+    let mut memory_set = MemorySet::new_bare();
+    let mut area = MapArea::new(
+        VirtPageNum(0x10).into(),
+        VirtPageNum(0x14).into(),
+        MapType::Lazy,
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    area.image = Some(&elf_data[..2 * PAGE_SIZE + 100]);
+    memory_set.push(area, None);
+    memory_set.munmap(VirtPageNum(0x10));
+    // a read of an image page takes a frame, so does the other page left
+    // with image, a page past the image does not
+    assert_eq!(memory_set.resolve_fault(VirtPageNum(0x12), MapPermission::R), PageFault::Lazy);
+    assert_eq!(memory_set.resident_pages(), 2);
+    let page = memory_set.translate(VirtPageNum(0x11)).unwrap().ppn().get_bytes_array();
+    assert_eq!(&page[..], &elf_data[PAGE_SIZE..2 * PAGE_SIZE]);
+    let page = memory_set.translate(VirtPageNum(0x12)).unwrap().ppn().get_bytes_array();
+    assert_eq!(&page[..100], &elf_data[2 * PAGE_SIZE..2 * PAGE_SIZE + 100]);
+    assert!(page[100..].iter().all(|byte| *byte == 0));
+    assert_eq!(memory_set.resolve_fault(VirtPageNum(0x13), MapPermission::R), PageFault::Lazy);
+    let past_image = if LAZY_ZERO_PAGE { 2 } else { 3 };
+    assert_eq!(memory_set.resident_pages(), past_image);
+    info!("elf_demand_paging_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// the clock passes over recently referenced pages, swapped pages come back
+/// intact, also in a fork, and unmapping gives their slots back
+pub fn swap_test() {
+    use super::swap::swap_used;
+    let slots = swap_used();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x14).into(), user_rw);
+    for vpn in 0x10..0x14 {
+        memory_set.translate(VirtPageNum(vpn)).unwrap().ppn().get_bytes_array().fill(vpn as u8);
+    }
+    // every page starts referenced, so the first round only clears A bits
+    assert_eq!(memory_set.swap_out(2), 2);
+    assert_eq!(memory_set.swapped_pages(), 2);
+    assert_eq!(memory_set.resident_pages(), 2);
+    assert_eq!(swap_used(), slots + 2);
+    let pte = memory_set.translate(VirtPageNum(0x10)).unwrap();
+    assert!(!pte.is_valid() && pte.swap_slot().is_some());
+    assert!(memory_set.translate(VirtPageNum(0x12)).unwrap().is_valid());
+    // a read brings the page back, referenced again
+    assert_eq!(memory_set.resolve_fault(VirtPageNum(0x10), MapPermission::R), PageFault::SwapIn);
+    let page = memory_set.translate(VirtPageNum(0x10)).unwrap().ppn().get_bytes_array();
+    assert!(page.iter().all(|byte| *byte == 0x10));
+    // the hand goes on at 0x12, whose bit is clear
+    assert_eq!(memory_set.swap_out(1), 1);
+    assert!(memory_set.translate(VirtPageNum(0x10)).unwrap().is_valid());
+    assert!(memory_set.translate(VirtPageNum(0x12)).unwrap().swap_slot().is_some());
+    assert_eq!((memory_set.swap_outs(), memory_set.swap_ins()), (3, 1));
+
+    // with the swap area full a fork fails and keeps no slot
+    let mut hoard = Vec::new();
+    while let Some(slot) = swap_alloc() {
+        hoard.push(slot);
+    }
+    assert!(MemorySet::from_existed_user(&mut memory_set).is_none());
+    hoard.into_iter().for_each(swap_free);
+    assert_eq!(swap_used(), slots + 2);
+    let mut child = MemorySet::from_existed_user(&mut memory_set).unwrap();
+    assert_eq!(child.swapped_pages(), 2);
+    assert_eq!(swap_used(), slots + 4);
+    assert_eq!(child.resolve_fault(VirtPageNum(0x12), MapPermission::W), PageFault::SwapIn);
+    let page = child.translate(VirtPageNum(0x12)).unwrap().ppn().get_bytes_array();
+    assert!(page.iter().all(|byte| *byte == 0x12));
+    drop(child);
+    assert_eq!(swap_used(), slots + 2);
+    memory_set.unmap_range(VPNRange::new(VirtPageNum(0x10), VirtPageNum(0x12)));
+    assert_eq!(memory_set.swapped_pages(), 1);
+    assert_eq!(swap_used(), slots + 1);
+    drop(memory_set);
+    assert_eq!(swap_used(), slots);
+    info!("swap_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// unmapping the middle of an area leaves two pieces that keep their frames,
+/// only the pages taken from mmap areas are reported
+pub fn partial_munmap_test() {
+    use super::frame_stats;
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x18).into(), user_rw);
+    memory_set.insert_mmap_area(VirtPageNum(0x18).into(), VirtPageNum(0x1c).into(), user_rw, true);
+    for vpn in 0x10..0x1c {
+        memory_set.translate(VirtPageNum(vpn)).unwrap().ppn().get_bytes_array()[0] = vpn as u8;
+    }
+    let data_frames = |memory_set: &MemorySet| {
+        memory_set.areas.iter().map(|area| area.data_frames.len()).sum::<usize>()
+    };
+    let free = frame_stats().free;
+    assert_eq!(memory_set.unmap_range(VPNRange::new(VirtPageNum(0x12), VirtPageNum(0x14))), 0);
+    assert_eq!(frame_stats().free, free + 2);
+    assert_eq!(memory_set.area_count(), 3);
+    assert_eq!(data_frames(&memory_set), 10);
+    for vpn in 0x10..0x1c {
+        let pte = memory_set.translate(VirtPageNum(vpn));
+        if (0x12..0x14).contains(&vpn) {
+            assert!(pte.map_or(true, |pte| !pte.is_valid()));
+            assert!(!memory_set.is_reserved(VirtPageNum(vpn)));
+        } else {
+            assert_eq!(pte.unwrap().ppn().get_bytes_array()[0], vpn as u8);
+        }
+    }
+    // a range across two areas trims both, touching holes is fine
+    assert_eq!(memory_set.unmap_range(VPNRange::new(VirtPageNum(0x13), VirtPageNum(0x19))), 1);
+    assert_eq!(memory_set.area_count(), 2);
+    assert_eq!(data_frames(&memory_set), 5);
+    assert!(memory_set.is_reserved(VirtPageNum(0x11)));
+    assert!(memory_set.is_reserved(VirtPageNum(0x19)));
+    assert!(!memory_set.is_reserved(VirtPageNum(0x18)));
+    assert_eq!(memory_set.unmap_range(VPNRange::new(VirtPageNum(0x10), VirtPageNum(0x1c))), 3);
+    assert_eq!(memory_set.area_count(), 0);
+    info!("partial_munmap_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// mprotect splits the area and rewrites the ptes, shared pages stay read-only
+pub fn mprotect_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let user_r = MapPermission::R | MapPermission::U;
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x14).into(), user_rw);
+    memory_set.mprotect(VPNRange::new(VirtPageNum(0x11), VirtPageNum(0x13)), user_r);
+    assert_eq!(memory_set.area_count(), 3);
+    for vpn in 0x10..0x14 {
+        let pte = memory_set.translate(VirtPageNum(vpn)).unwrap();
+        assert!(pte.is_valid() && pte.readable() && pte.is_user());
+        assert_eq!(pte.writable(), !(0x11..0x13).contains(&vpn));
+    }
+    // a read-only page is a real violation, not a copy-on-write fault
+    assert_eq!(
+        memory_set.resolve_fault(VirtPageNum(0x11), MapPermission::W),
+        PageFault::Invalid
+    );
+    // after fork the frames are shared, making them writable again must not unshare them
+    let child = MemorySet::from_existed_user(&mut memory_set).unwrap();
+    memory_set.mprotect(VPNRange::new(VirtPageNum(0x10), VirtPageNum(0x14)), user_rw);
+    assert!(!memory_set.translate(VirtPageNum(0x11)).unwrap().writable());
+    assert_eq!(
+        memory_set.resolve_fault(VirtPageNum(0x11), MapPermission::W),
+        PageFault::CopyOnWrite
+    );
+    assert!(memory_set.translate(VirtPageNum(0x11)).unwrap().writable());
+    assert!(!child.translate(VirtPageNum(0x11)).unwrap().writable());
+    // lazy pages that were never touched keep no pte, the new permission applies on first touch
+    memory_set.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x22).into(), user_rw);
+    memory_set.mprotect(VPNRange::new(VirtPageNum(0x20), VirtPageNum(0x22)), user_r);
+    assert!(memory_set.translate(VirtPageNum(0x20)).map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(
+        memory_set.resolve_fault(VirtPageNum(0x20), MapPermission::W),
+        PageFault::Invalid
+    );
+    drop(child);
+    info!("mprotect_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// pin the bit positions mmap's port encoding depends on
+pub fn permission_bits_test() {
+    assert_eq!(MapPermission::R.bits(), 1 << 1);
+    assert_eq!(MapPermission::W.bits(), 1 << 2);
+    assert_eq!(MapPermission::X.bits(), 1 << 3);
+    assert_eq!(MapPermission::U.bits(), 1 << 4);
+    assert_eq!(PTEFlags::R.bits(), 1 << 1);
+    assert_eq!(PTEFlags::W.bits(), 1 << 2);
+    assert_eq!(PTEFlags::X.bits(), 1 << 3);
+    assert_eq!(PTEFlags::U.bits(), 1 << 4);
+    assert_eq!(MapPermission::from_port(0b001), Some(MapPermission::R));
+    assert_eq!(MapPermission::from_port(0b010), Some(MapPermission::W));
+    assert_eq!(MapPermission::from_port(0b100), Some(MapPermission::X));
+    assert_eq!(
+        MapPermission::from_port(0b111),
+        Some(MapPermission::R | MapPermission::W | MapPermission::X)
+    );
+    assert_eq!(MapPermission::from_port(0b1000), None);
+    info!("permission_bits_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a fault with a frame for the page but none for its page tables maps
+/// nothing and gives the page's frame back
+pub fn fault_oom_test() {
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let vpn = VirtPageNum(0x20);
+    let mut memory_set = MemorySet::new_bare();
+    // only the root exists, so backing the page needs a middle and a leaf table
+    memory_set.insert_lazy_area(vpn.into(), VirtPageNum(0x21).into(), user_rw);
+    let mut hoard = Vec::new();
+    while let Some(frame) = frame_alloc() {
+        hoard.push(frame);
+    }
+    // not even the zero frame can be mapped without tables
+    assert!(!memory_set.handle_lazy_fault_with(vpn, MapPermission::R, true));
+    // one frame left: the page takes it and the middle table finds none
+    hoard.pop();
+    assert_eq!(memory_set.resolve_fault(vpn, MapPermission::W), PageFault::Invalid);
+    assert_eq!(frame_stats().free, 1);
+    assert!(memory_set.translate(vpn).map_or(true, |pte| !pte.is_valid()));
+    assert_eq!(memory_set.resident_pages(), 0);
+    drop(hoard);
+    assert_eq!(memory_set.resolve_fault(vpn, MapPermission::W), PageFault::Lazy);
+    assert!(memory_set.translate(vpn).unwrap().writable());
+    info!("fault_oom_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// file pages are read in on first touch, and only a shared mapping writes
+/// them back, no further than the file goes
+pub fn file_mapping_test() {
+    use alloc::string::String;
+    /// a file kept in memory
+    struct MemInode(Mutex<Vec<u8>>);
+    impl Inode for MemInode {
+        fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+            let data = self.0.lock();
+            let len = data.len().saturating_sub(offset).min(buf.len());
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            len
+        }
+        fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+            let mut data = self.0.lock();
+            if data.len() < offset + buf.len() {
+                data.resize(offset + buf.len(), 0);
+            }
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+            buf.len()
+        }
+        fn clear(&self) {
+            self.0.lock().clear();
+        }
+        fn size(&self) -> usize {
+            self.0.lock().len()
+        }
+        fn find(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+            None
+        }
+        fn create(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+            None
+        }
+        fn ls(&self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let (start, end) = (VirtPageNum(0x20), VirtPageNum(0x23));
+    // two and a half pages of 1s, 2s and 3s, mapped from the second page on
+    let size = PAGE_SIZE * 5 / 2;
+    let data = (0..size).map(|i| (i / PAGE_SIZE) as u8 + 1).collect();
+    let inode = Arc::new(MemInode(Mutex::new(data)));
+    let file = || (inode.clone() as Arc<dyn Inode>, PAGE_SIZE);
+    let page = |memory_set: &MemorySet, vpn: usize| {
+        memory_set.translate(VirtPageNum(vpn)).unwrap().ppn().get_bytes_array()
+    };
+
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.insert_file_area(start.into(), end.into(), user_rw, false, file());
+    assert_eq!(memory_set.resident_pages(), 0);
+    // even a read gets a frame of its own, past the end of the file it is zeroed
+    for vpn in 0x20..0x23 {
+        assert_eq!(memory_set.resolve_fault(VirtPageNum(vpn), MapPermission::R), PageFault::Lazy);
+    }
+    assert!(page(&memory_set, 0x20).iter().all(|b| *b == 2));
+    assert!(page(&memory_set, 0x21)[..PAGE_SIZE / 2].iter().all(|b| *b == 3));
+    assert!(page(&memory_set, 0x21)[PAGE_SIZE / 2..].iter().all(|b| *b == 0));
+    assert!(page(&memory_set, 0x22).iter().all(|b| *b == 0));
+    // a private mapping keeps its writes
+    page(&memory_set, 0x20).fill(9);
+    memory_set.unmap_range(VPNRange::new(start, end));
+    assert!(inode.0.lock()[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|b| *b == 2));
+
+    // a shared one is backed in full for fork, and both sides see one frame
+    memory_set.insert_file_area(start.into(), end.into(), user_rw, true, file());
+    let child = MemorySet::from_existed_user(&mut memory_set).unwrap();
+    assert_eq!(memory_set.resident_pages(), 3);
+    page(&child, 0x21).fill(7);
+    assert!(page(&memory_set, 0x21).iter().all(|b| *b == 7));
+    drop(child);
+    let data = inode.0.lock();
+    assert_eq!(data.len(), size);
+    assert!(data[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|b| *b == 2));
+    assert!(data[2 * PAGE_SIZE..].iter().all(|b| *b == 7));
+    drop(data);
+    // unmapping writes back too
+    page(&memory_set, 0x20).fill(5);
+    memory_set.unmap_range(VPNRange::new(start, end));
+    assert!(inode.0.lock()[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|b| *b == 5));
+    info!("file_mapping_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// under memory pressure, backing the rest of a sys_write buffer swaps out
+/// other pages, never the buffer's own, so the whole buffer stays readable
+pub fn populate_pinned_test() {
+    use super::user_buffer;
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    let (start, middle, end) = (VirtPageNum(0x10), VirtPageNum(0x14), VirtPageNum(0x18));
+    let mut memory_set = MemorySet::new_bare();
+    // the buffer: four pages written to, four never touched
+    memory_set.insert_framed_area(start.into(), middle.into(), user_rw);
+    memory_set.insert_lazy_area(middle.into(), end.into(), user_rw);
+    for vpn in start.0..middle.0 {
+        memory_set.translate(VirtPageNum(vpn)).unwrap().ppn().get_bytes_array().fill(vpn as u8);
+    }
+    // the clock sweeps addresses in order, so without pinning it would take
+    // the buffer's pages before these
+    memory_set.insert_framed_area(VirtPageNum(0x20).into(), VirtPageNum(0x28).into(), user_rw);
+    let mut hoard = Vec::new();
+    while frame_stats().free > 2 {
+        hoard.push(frame_alloc().unwrap());
+    }
+    assert!(memory_set.populate(VPNRange::new(start, end), MapPermission::R));
+    // the zero frame may be made on the way and cost another page
+    assert!(memory_set.swapped_pages() >= SWAP_LOW_FRAMES - 2);
+    for vpn in start.0..middle.0 {
+        let pte = memory_set.translate(VirtPageNum(vpn)).unwrap();
+        assert!(pte.is_valid());
+        assert!(pte.ppn().get_bytes_array().iter().all(|byte| *byte == vpn as u8));
+    }
+    let len = (end.0 - start.0) * PAGE_SIZE;
+    let ptr = VirtAddr::from(start).0 as *const u8;
+    assert!(user_buffer(memory_set.token(), ptr, len, false).is_some());
+    drop(hoard);
+    info!("populate_pinned_test passed!");
+}
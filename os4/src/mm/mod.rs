@@ -11,13 +11,26 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod swap;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_fragmentation, frame_stats, FragmentationInfo,
+    FrameStats, FrameTracker,
+};
 pub use memory_set::remap_test;
-pub use memory_set::{MapArea, MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{get_refmut, translated_byte_buffer, PageTableEntry};
+pub use memory_set::{MapArea, MapPermission, MemorySet, PageFault, KERNEL_SPACE};
+pub use memory_set::{MAP_FIXED, MAP_PRIVATE, MAP_SHARED};
+pub use shm::{
+    shm_attach, shm_create, shm_lookup, shm_pages, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, SHM_RDONLY,
+};
+pub use page_table::{
+    copy_from_user, copy_to_user, expire_user_buffers, nofault_copy_from, token_is_valid,
+    translated_byte_buffer, translated_str, user_buffer, user_buffer_writable, PageTableEntry,
+    UserBuffer,
+};
 use page_table::{PTEFlags, PageTable};
 
 /// initiate heap allocator, frame allocator and kernel space
@@ -25,4 +38,48 @@ pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.lock().activate();
-}
\ No newline at end of file
+    crate::shutdown::register(crate::shutdown::ShutdownHook {
+        name: "frame fragmentation",
+        priority: 10,
+        func: report_fragmentation,
+    });
+    #[cfg(feature = "frame_trace")]
+    crate::shutdown::register(crate::shutdown::ShutdownHook {
+        name: "frame leaks",
+        priority: 20,
+        func: report_frame_leaks,
+    });
+}
+
+/// switch a hart started after `init` to the kernel space
+pub fn init_other_hart() {
+    KERNEL_SPACE.lock().activate();
+}
+
+/// Drop stale translations after the current page table changed, on the
+/// other harts too since they may run threads of the same process.
+pub fn flush_tlb() {
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+    if crate::smp::online_harts() > 1 {
+        crate::sbi::remote_sfence_vma_all();
+    }
+}
+
+/// print how fragmented the free frames are at shutdown
+fn report_fragmentation() {
+    let fragmentation = frame_fragmentation();
+    println!(
+        "[kernel] largest free frame run = {}, free run histogram = {:?}",
+        fragmentation.largest_run, fragmentation.histogram
+    );
+}
+
+/// print where the frames still allocated at shutdown came from
+#[cfg(feature = "frame_trace")]
+fn report_frame_leaks() {
+    for (site, count) in frame_allocator::frame_leak_report() {
+        println!("[kernel] {} frame(s) still allocated from {}", count, site);
+    }
+}
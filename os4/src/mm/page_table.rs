@@ -1,7 +1,10 @@
 //! 实现[`PageTableEntry`]和[`PageTable`]。
-use super::{frame_alloc, FrameTracker, PhysPageNum, StepByOne, PhysAddr, VirtAddr, VirtPageNum};
+use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use crate::config::{MEMORY_END, PAGE_SIZE};
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 //bitflags 是一个 Rust 中常用来比特标志位的 crate 。它提供了 一个 bitflags! 宏
 use bitflags::*;
 
@@ -24,6 +27,10 @@ bitflags! {
 //实现页表项 PageTableEntry 
 //#[derive(Copy, Clone)]让编译器自动为 PageTableEntry 实现 Copy/Clone Trait，
 //来让这个类型以值语义赋值/传参的时候 不会发生所有权转移，而是拷贝一份新的副本。
+/// the first bit reserved for software, set in the invalid pte of a page
+/// that is swapped out, whose ppn field then holds the swap slot
+const PTE_SWAPPED: usize = 1 << 8;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// page table entry structure
@@ -63,6 +70,23 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+    /// An invalid entry for a page that went to swap slot `slot`.
+    pub fn swapped(slot: usize) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+    /// The swap slot of a swapped-out page, `None` for any other entry.
+    pub fn swap_slot(&self) -> Option<usize> {
+        if !self.is_valid() && self.bits & PTE_SWAPPED != 0 {
+            Some(self.bits >> 10)
+        } else {
+            None
+        }
+    }
 }
 
 /// page table structure
@@ -82,11 +106,14 @@ impl PageTable {
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
+        debug_assert!(token_is_valid(satp), "invalid satp token {:#x}", satp);
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
         }
     }
+    /// The leaf entry of `vpn`, creating the tables on its path. `None` if
+    /// no frame is left for a missing table.
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let mut idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -98,7 +125,7 @@ impl PageTable {
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -123,36 +150,218 @@ impl PageTable {
         }
         result
     }
+    /// Map `vpn` to `ppn`, also over the entry of a swapped-out page. A is
+    /// set right away, so a new page counts as referenced for the clock.
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(self.try_map(vpn, ppn, flags), "no frame left for a page table of {:?}", vpn);
+    }
+    /// Like `map`, but false instead of a panic if no frame is left for a
+    /// table on the path, for the page fault paths. Nothing is mapped then.
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        let pte = match self.find_pte_create(vpn) {
+            Some(pte) => pte,
+            None => return false,
+        };
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
-        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::A);
+        true
     }
+    /// Unmap `vpn`, then free the leaf and middle tables on its path once
+    /// nothing in them is mapped or swapped out any more.
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
+        assert!(
+            self.translate(vpn).map_or(false, |pte| pte.is_valid()),
+            "vpn {:?} is invalid before unmapping",
+            vpn
+        );
+        self.clear(vpn);
+    }
+    /// Turn the mapped `vpn` into a swap entry for `slot`.
+    pub fn swap_out(&mut self, vpn: VirtPageNum, slot: usize) {
         let pte = self.find_pte_create(vpn).unwrap();
-        assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
-        *pte = PageTableEntry::empty();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before swapping out", vpn);
+        *pte = PageTableEntry::swapped(slot);
+    }
+    /// Give the unmapped `vpn` a swap entry for `slot`, for fork. False if
+    /// no frame is left for a table on the path.
+    pub fn set_swapped(&mut self, vpn: VirtPageNum, slot: usize) -> bool {
+        let pte = match self.find_pte_create(vpn) {
+            Some(pte) => pte,
+            None => return false,
+        };
+        assert!(pte.bits == 0, "vpn {:?} is in use before setting a swap entry", vpn);
+        *pte = PageTableEntry::swapped(slot);
+        true
+    }
+    /// Drop the swap entry of `vpn` and return its slot, `None` if `vpn` is
+    /// not swapped out.
+    pub fn take_swapped(&mut self, vpn: VirtPageNum) -> Option<usize> {
+        let slot = self.translate(vpn)?.swap_slot()?;
+        self.clear(vpn);
+        Some(slot)
+    }
+    /// Clear the A bit of the mapped `vpn` and return whether it was set.
+    pub fn test_and_clear_accessed(&mut self, vpn: VirtPageNum) -> bool {
+        let accessed = self
+            .translate(vpn)
+            .map_or(false, |pte| pte.is_valid() && pte.flags().contains(PTEFlags::A));
+        if accessed {
+            // the path is there, nothing gets created
+            self.find_pte_create(vpn).unwrap().bits &= !(PTEFlags::A.bits as usize);
+        }
+        accessed
+    }
+    /// Empty the leaf entry of `vpn`, whose path must exist, then free the
+    /// leaf and middle tables on the path once all their entries are empty.
+    fn clear(&mut self, vpn: VirtPageNum) {
+        let idxs = vpn.indexes();
+        // root, middle and leaf table on the way to `vpn`
+        let mut tables = [self.root_ppn; 3];
+        for i in 0..2 {
+            tables[i + 1] = tables[i].get_pte_array()[idxs[i]].ppn();
+        }
+        tables[2].get_pte_array()[idxs[2]] = PageTableEntry::empty();
+        for i in (1..3).rev() {
+            if tables[i].get_pte_array().iter().any(|pte| pte.bits != 0) {
+                break;
+            }
+            // clear the parent entry before the frame goes back to the allocator
+            tables[i - 1].get_pte_array()[idxs[i - 1]] = PageTableEntry::empty();
+            self.frames.retain(|frame| frame.ppn != tables[i]);
+        }
+    }
+    /// Point the mapped `vpn` at `ppn` with `flags`, keeping the tables on its path.
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).copied()
     }
     pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+        SATP_MODE_SV39 << 60 | self.root_ppn.0
+    }
+    /// Point the table at `root` and return the old root, for tests that
+    /// need a corrupted address space. `frames` keeps the real root.
+    #[allow(unused)]
+    pub fn replace_root(&mut self, root: PhysPageNum) -> PhysPageNum {
+        core::mem::replace(&mut self.root_ppn, root)
     }
+    /// Frames used by the page table itself.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// Print every mapping as `[start_vpn, end_vpn) -> ppn flags`, merging
+    /// neighbours that are contiguous in both vpn and ppn and share flags.
+    pub fn dump(&self) {
+        // (start vpn, end vpn, start ppn, flags) of the run being merged
+        let mut run: Option<(usize, usize, usize, PTEFlags)> = None;
+        walk_leaves(self.root_ppn, 0, 0, &mut |vpn, pte| {
+            let (ppn, flags) = (pte.ppn().0, pte.flags());
+            match run {
+                Some((start, end, start_ppn, run_flags))
+                    if vpn == end && ppn == start_ppn + (end - start) && flags == run_flags =>
+                {
+                    run = Some((start, end + 1, start_ppn, run_flags));
+                }
+                _ => {
+                    if let Some(finished) = run {
+                        print_run(finished);
+                    }
+                    run = Some((vpn, vpn + 1, ppn, flags));
+                }
+            }
+        });
+        if let Some(finished) = run {
+            print_run(finished);
+        }
+    }
+}
+
+/// Call `f` with the vpn and pte of every valid leaf below the table in `ppn`.
+fn walk_leaves(
+    ppn: PhysPageNum,
+    level: usize,
+    vpn_prefix: usize,
+    f: &mut impl FnMut(usize, PageTableEntry),
+) {
+    for (idx, pte) in ppn.get_pte_array().iter().enumerate() {
+        if !pte.is_valid() {
+            continue;
+        }
+        let vpn = vpn_prefix << 9 | idx;
+        if level == 2 {
+            f(vpn, *pte);
+        } else {
+            walk_leaves(pte.ppn(), level + 1, vpn, f);
+        }
+    }
+}
+
+fn print_run((start, end, ppn, flags): (usize, usize, usize, PTEFlags)) {
+    let flag = |bit: PTEFlags, c: char| if flags.contains(bit) { c } else { '-' };
+    println!(
+        "  vpn [{:#x}, {:#x}) -> ppn {:#x} {}{}{}{}",
+        start,
+        end,
+        ppn,
+        flag(PTEFlags::R, 'R'),
+        flag(PTEFlags::W, 'W'),
+        flag(PTEFlags::X, 'X'),
+        flag(PTEFlags::U, 'U')
+    );
+}
+
+const SATP_MODE_SV39: usize = 8;
+const SATP_PPN_MASK: usize = (1usize << 44) - 1;
+
+/// Check that a satp token selects SV39, has no ASID bits set and that its
+/// root ppn lies inside the frames handed out by the frame allocator.
+pub fn token_is_valid(satp: usize) -> bool {
+    extern "C" {
+        fn ekernel();
+    }
+    let ppn = satp & SATP_PPN_MASK;
+    satp >> 60 == SATP_MODE_SV39
+        && satp & !(SATP_PPN_MASK | 0xf << 60) == 0
+        && ppn >= PhysAddr::from(ekernel as usize).ceil().0
+        && ppn < PhysAddr::from(MEMORY_END).floor().0
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// translate a pointer to a user buffer through page table
+///
+/// The slices point straight into the user's frames and are only valid until
+/// the current syscall returns: a later munmap frees those frames. They are
+/// only lent out by the [`UserBuffer`], which debug builds check for that.
+#[allow(unused)]
+pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> UserBuffer {
+    user_buffer(token, ptr, len, false).expect("translated_byte_buffer: user buffer is not mapped")
+}
+
+/// Translate a user buffer page by page like [`translated_byte_buffer`], but
+/// return `None` unless every page is valid, user accessible and carries the
+/// `required` flags.
+pub fn checked_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    required: PTEFlags,
+) -> Option<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let pte = page_table.translate(vpn)?;
+        if !pte.is_valid() || !pte.flags().contains(required | PTEFlags::U) {
+            return None;
+        }
+        let ppn = pte.ppn();
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -163,25 +372,364 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
 
-///通过页表转换泛型并返回可变引用
-//实现虚拟地址到物理地址的转换
-pub fn get_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+/// Read the nul-terminated string at `ptr` in the user space of `token`.
+/// `None` if it reaches a page the task cannot read, is longer than
+/// `max_len` bytes or is not utf-8.
+pub fn translated_str(token: usize, ptr: *const u8, max_len: usize) -> Option<String> {
     let page_table = PageTable::from_token(token);
-    let virtual_address = VirtAddr::from(ptr as usize);
-    let offset = virtual_address.page_offset();
+    let mut bytes = Vec::new();
+    let mut va = ptr as usize;
+    loop {
+        let pte = page_table.translate(VirtAddr::from(va).floor())?;
+        if !pte.is_valid() || !pte.flags().contains(PTEFlags::R | PTEFlags::U) {
+            return None;
+        }
+        let byte = pte.ppn().get_bytes_array()[VirtAddr::from(va).page_offset()];
+        if byte == 0 {
+            break;
+        }
+        if bytes.len() == max_len {
+            return None;
+        }
+        bytes.push(byte);
+        va = va.checked_add(1)?;
+    }
+    String::from_utf8(bytes).ok()
+}
 
-    let virtual_page_number = virtual_address.floor();
+/// Copy user memory at `va` into `buf` for diagnostics, by walking the page
+/// table of `token` and reading the frames directly instead of dereferencing
+/// a user address from S-mode.
+///
+/// Bytes on pages that are unmapped, not user accessible or lie outside the
+/// frame allocator's range read as zero. Returns how many bytes came from
+/// mapped pages. Never allocates and never panics, whatever `token` and `va` are.
+pub fn nofault_copy_from(token: usize, va: usize, buf: &mut [u8]) -> usize {
+    if !token_is_valid(token) {
+        buf.fill(0);
+        return 0;
+    }
+    let root = PhysPageNum(token & SATP_PPN_MASK);
+    let mut copied = 0;
+    let mut offset = 0;
+    while offset < buf.len() {
+        let addr = match va.checked_add(offset) {
+            Some(addr) => addr,
+            None => {
+                buf[offset..].fill(0);
+                break;
+            }
+        };
+        let page_offset = addr % PAGE_SIZE;
+        let chunk = (PAGE_SIZE - page_offset).min(buf.len() - offset);
+        let dst = &mut buf[offset..offset + chunk];
+        match nofault_translate(root, addr / PAGE_SIZE) {
+            Some(ppn) => {
+                dst.copy_from_slice(&ppn.get_bytes_array()[page_offset..page_offset + chunk]);
+                copied += chunk;
+            }
+            None => dst.fill(0),
+        }
+        offset += chunk;
+    }
+    copied
+}
+
+/// Walk the table rooted at `root` for `vpn`, checking every ppn on the way
+/// lies in allocatable memory before it is read.
+fn nofault_translate(root: PhysPageNum, vpn: usize) -> Option<PhysPageNum> {
+    extern "C" {
+        fn ekernel();
+    }
+    let frames = PhysAddr::from(ekernel as usize).ceil().0..PhysAddr::from(MEMORY_END).floor().0;
+    // SV39 only translates the low 2^27 vpns through this table
+    if vpn >> 27 != 0 {
+        return None;
+    }
+    let mut ppn = root;
+    for level in 0..3 {
+        if !frames.contains(&ppn.0) {
+            return None;
+        }
+        let idx = (vpn >> (9 * (2 - level))) & 511;
+        let pte = ppn.get_pte_array()[idx];
+        let flags = PTEFlags::from_bits_truncate(pte.bits as u8);
+        if !flags.contains(PTEFlags::V) {
+            return None;
+        }
+        ppn = pte.ppn();
+        if level == 2 && !flags.contains(PTEFlags::U) {
+            return None;
+        }
+    }
+    if frames.contains(&ppn.0) {
+        Some(ppn)
+    } else {
+        None
+    }
+}
+
+/// Whether all of `[ptr, ptr + len)` is mapped writable for the user.
+pub fn user_buffer_writable(token: usize, ptr: *const u8, len: usize) -> bool {
+    checked_byte_buffer(token, ptr, len, PTEFlags::W).is_some()
+}
+
+/// Moves on with every trap from user mode, see [`expire_user_buffers`].
+static USER_BUFFER_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Make every [`UserBuffer`] translated so far stale, on each trap from user
+/// mode. Only debug builds keep count.
+pub fn expire_user_buffers() {
+    if cfg!(debug_assertions) {
+        USER_BUFFER_EPOCH.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A user buffer as slices of the frames behind it, one per page it touches.
+/// It is only valid during the syscall it was made for, and only until the
+/// task blocks: whatever runs in between may unmap, swap out or share the
+/// pages, so a file that blocks calls [`UserBuffer::translate_again`] before
+/// it touches the slices again. The slices are only lent out for as long as
+/// the buffer is borrowed, and debug builds panic on any use after another
+/// trap from user mode.
+pub struct UserBuffer {
+    buffers: Vec<&'static mut [u8]>,
+    token: usize,
+    ptr: usize,
+    len: usize,
+    write: bool,
+    /// `USER_BUFFER_EPOCH` when the slices were translated
+    epoch: usize,
+}
+
+impl UserBuffer {
+    /// Whether no trap from user mode came since the slices were translated,
+    /// always true in release builds.
+    pub fn is_current(&self) -> bool {
+        self.epoch == USER_BUFFER_EPOCH.load(Ordering::Relaxed)
+    }
+    fn check_current(&self) {
+        debug_assert!(
+            self.is_current(),
+            "user buffer at {:#x} used past its syscall or after blocking",
+            self.ptr
+        );
+    }
+    /// The slices, one per page.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.check_current();
+        self.buffers.iter().map(|buffer| &**buffer)
+    }
+    /// The slices, one per page, for writing.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut [u8]> {
+        self.check_current();
+        self.buffers.iter_mut().map(|buffer| &mut **buffer)
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Where the buffer starts in user space.
+    pub fn ptr(&self) -> usize {
+        self.ptr
+    }
+    /// Whether the buffer was checked for writing.
+    pub fn writable(&self) -> bool {
+        self.write
+    }
+    /// Translate the buffer again, false if some page no longer allows the
+    /// access it was made for. The slices are left empty in that case.
+    pub fn translate_again(&mut self) -> bool {
+        let required = if self.write { PTEFlags::R | PTEFlags::W } else { PTEFlags::R };
+        match checked_byte_buffer(self.token, self.ptr as *const u8, self.len, required) {
+            Some(buffers) => {
+                self.buffers = buffers;
+                self.epoch = USER_BUFFER_EPOCH.load(Ordering::Relaxed);
+                true
+            }
+            None => {
+                self.buffers.clear();
+                false
+            }
+        }
+    }
+    /// The bytes from offset `from` on.
+    pub fn bytes(&self, from: usize) -> impl Iterator<Item = &u8> {
+        self.check_current();
+        let mut skip = from;
+        self.buffers.iter().flat_map(move |buffer| {
+            let n = skip.min(buffer.len());
+            skip -= n;
+            buffer[n..].iter()
+        })
+    }
+}
+
+/// `[ptr, ptr + len)` as a [`UserBuffer`], `None` unless the user may read
+/// it, and also write it with `write`.
+pub fn user_buffer(token: usize, ptr: *const u8, len: usize, write: bool) -> Option<UserBuffer> {
+    let required = if write { PTEFlags::R | PTEFlags::W } else { PTEFlags::R };
+    let buffers = checked_byte_buffer(token, ptr, len, required)?;
+    Some(UserBuffer {
+        buffers,
+        token,
+        ptr: ptr as usize,
+        len,
+        write,
+        epoch: USER_BUFFER_EPOCH.load(Ordering::Relaxed),
+    })
+}
 
-    let physical_page_entry = page_table.find_pte(virtual_page_number).unwrap();
+/// Copy `src` into user memory at `dst`, which may straddle page boundaries.
+///
+/// The copy goes byte by byte through the translated frames, so `dst` does
+/// not have to be aligned for `T`. Fails with -1 if any byte of the destination is unmapped, not writable or
+/// not user accessible; nothing is written in that case.
+pub fn copy_to_user<T: ?Sized>(token: usize, dst: *mut T, src: &T) -> Result<(), isize> {
+    let src = unsafe {
+        core::slice::from_raw_parts(src as *const T as *const u8, core::mem::size_of_val(src))
+    };
+    let buffers = checked_byte_buffer(token, dst as *const u8, src.len(), PTEFlags::W).ok_or(-1)?;
+    let mut copied = 0;
+    for buffer in buffers {
+        buffer.copy_from_slice(&src[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    Ok(())
+}
 
-    let physical_page_num = physical_page_entry.ppn();
+#[allow(unused)]
+/// Copy a `T` out of user memory at `src`, which may straddle page boundaries
+/// and does not have to be aligned for `T`.
+///
+/// `T` must be plain data that is valid for any bit pattern. Fails with -1
+/// if any byte of the source is unmapped, not readable or not user accessible.
+pub fn copy_from_user<T>(token: usize, src: *const T, dst: &mut T) -> Result<(), isize> {
+    let dst = unsafe {
+        core::slice::from_raw_parts_mut(dst as *mut T as *mut u8, core::mem::size_of::<T>())
+    };
+    let buffers = checked_byte_buffer(token, src as *const u8, dst.len(), PTEFlags::R).ok_or(-1)?;
+    let mut copied = 0;
+    for buffer in buffers {
+        dst[copied..copied + buffer.len()].copy_from_slice(buffer);
+        copied += buffer.len();
+    }
+    Ok(())
+}
 
-    let start_address = PhysAddr::from(physical_page_num);
+#[allow(unused)]
+#[test_case]
+/// a simple test for satp token validation
+pub fn token_validation_test() {
+    let page_table = PageTable::new();
+    let token = page_table.token();
+    assert!(token_is_valid(token));
+    // wrong paging mode
+    assert!(!token_is_valid(token & SATP_PPN_MASK));
+    assert!(!token_is_valid(9usize << 60 | (token & SATP_PPN_MASK)));
+    // stray ASID bits
+    assert!(!token_is_valid(token | 1 << 44));
+    // corrupted root ppn: inside the kernel image, and past the end of memory
+    assert!(!token_is_valid(SATP_MODE_SV39 << 60));
+    assert!(!token_is_valid(
+        SATP_MODE_SV39 << 60 | PhysAddr::from(MEMORY_END).floor().0
+    ));
+    info!("token_validation_test passed!");
+}
 
-    let physical_address = PhysAddr::from(usize::from(start_address) + offset);
+#[allow(unused)]
+#[test_case]
+/// nofault copies must give best-effort output for any address
+pub fn nofault_copy_test() {
+    use super::{MapPermission, MemorySet};
+    let mut memory_set = MemorySet::new_bare();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x11).into(), user_rw);
+    memory_set.insert_lazy_area(VirtPageNum(0x20).into(), VirtPageNum(0x21).into(), user_rw);
+    let page = memory_set.translate(VirtPageNum(0x10)).unwrap().ppn();
+    page.get_bytes_array().fill(0xaa);
+    let token = memory_set.token();
+    let mut buf = [0xffu8; 32];
 
-    physical_address.get_mut()
-}
\ No newline at end of file
+    // straddling the end of the mapped page into an unmapped hole
+    let end: usize = VirtAddr::from(VirtPageNum(0x11)).into();
+    assert_eq!(nofault_copy_from(token, end - 16, &mut buf), 16);
+    assert!(buf[..16].iter().all(|b| *b == 0xaa));
+    assert!(buf[16..].iter().all(|b| *b == 0));
+    // a lazily mapped page that was never touched
+    buf.fill(0xff);
+    let lazy: usize = VirtAddr::from(VirtPageNum(0x20)).into();
+    assert_eq!(nofault_copy_from(token, lazy, &mut buf), 0);
+    assert!(buf.iter().all(|b| *b == 0));
+    // the top of user space and the very end of the address space
+    assert_eq!(nofault_copy_from(token, (1 << 39) - 16, &mut buf), 0);
+    assert_eq!(nofault_copy_from(token, usize::MAX - 8, &mut buf), 0);
+    // a bogus token is refused without walking anything
+    assert_eq!(nofault_copy_from(0, end - 16, &mut buf), 0);
+    info!("nofault_copy_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// unmapping the last page under a leaf table must give both intermediate frames back
+pub fn unmap_reclaim_test() {
+    use super::frame_stats;
+    use crate::config::TRAP_CONTEXT;
+    let baseline = frame_stats().allocated;
+    let mut page_table = PageTable::new();
+    let data = frame_alloc().unwrap();
+    let before_map = frame_stats().allocated;
+    assert_eq!(before_map, baseline + 2);
+    // the root alone is all there is, so this needs a middle and a leaf table
+    let vpn = VirtAddr::from(TRAP_CONTEXT - PAGE_SIZE).floor();
+    let neighbour = VirtPageNum(vpn.0 - 1);
+    page_table.map(vpn, data.ppn, PTEFlags::R | PTEFlags::W);
+    assert_eq!(page_table.frame_count(), 3);
+    assert_eq!(frame_stats().allocated, before_map + 2);
+    let idxs = vpn.indexes();
+    let middle = page_table.root_ppn.get_pte_array()[idxs[0]].ppn();
+    let leaf = middle.get_pte_array()[idxs[1]].ppn();
+
+    // a page still mapped under the same leaf table keeps both tables alive
+    page_table.map(neighbour, data.ppn, PTEFlags::R);
+    page_table.unmap(neighbour);
+    assert_eq!(page_table.frame_count(), 3);
+    assert!(page_table.translate(vpn).unwrap().is_valid());
+
+    page_table.unmap(vpn);
+    assert!(!leaf.get_pte_array()[idxs[2]].is_valid());
+    assert!(!page_table.root_ppn.get_pte_array()[idxs[0]].is_valid());
+    assert_eq!(page_table.frame_count(), 1);
+    assert_eq!(frame_stats().allocated, before_map);
+    assert!(page_table.translate(vpn).is_none());
+    drop(data);
+    drop(page_table);
+    assert_eq!(frame_stats().allocated, baseline);
+    info!("unmap_reclaim_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a user buffer goes stale with the next trap from user mode until it is translated again
+pub fn user_buffer_epoch_test() {
+    use super::{MapPermission, MemorySet};
+    let mut memory_set = MemorySet::new_bare();
+    let user_rw = MapPermission::R | MapPermission::W | MapPermission::U;
+    memory_set.insert_framed_area(VirtPageNum(0x10).into(), VirtPageNum(0x12).into(), user_rw);
+    let end: usize = VirtAddr::from(VirtPageNum(0x11)).into();
+    let mut buf = user_buffer(memory_set.token(), (end - 8) as *const u8, 16, true).unwrap();
+    assert!(buf.is_current());
+    buf.iter_mut().for_each(|slice| slice.fill(0x5a));
+    assert_eq!(buf.iter().map(|slice| slice.len()).collect::<Vec<_>>(), vec![8, 8]);
+    expire_user_buffers();
+    assert_eq!(buf.is_current(), !cfg!(debug_assertions));
+    assert!(buf.translate_again());
+    assert!(buf.is_current());
+    assert!(buf.bytes(0).all(|byte| *byte == 0x5a));
+    info!("user_buffer_epoch_test passed!");
+}
@@ -0,0 +1,150 @@
+//! System V style shared memory segments
+//!
+//! A segment is a run of frames owned by the registry under an id, found by
+//! its key. Attaching it maps the same frames into a memory set as a shared
+//! area, so every attachment holds a reference to each frame and fork hands
+//! the child the attachments too. A segment is destroyed once it was
+//! attached and the last attachment is gone, by detach, exec or exit; the
+//! registry notices that lazily, on its next use.
+
+use super::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// the key that always gets a new segment, never found by later lookups
+pub const IPC_PRIVATE: usize = 0;
+/// shmget flag: create the segment if there is none with the key
+pub const IPC_CREAT: usize = 0o1000;
+/// shmget flag: with `IPC_CREAT`, fail if the key is taken
+pub const IPC_EXCL: usize = 0o2000;
+/// shmat flag: attach read-only
+pub const SHM_RDONLY: usize = 0o10000;
+
+struct ShmSegment {
+    key: usize,
+    frames: Vec<Arc<FrameTracker>>,
+    /// some memory set attached it, so it goes away with the last attachment
+    attached: bool,
+}
+
+impl ShmSegment {
+    /// Memory sets holding the segment right now.
+    fn attachments(&self) -> usize {
+        Arc::strong_count(&self.frames[0]) - 1
+    }
+}
+
+struct ShmRegistry {
+    next_id: usize,
+    segments: BTreeMap<usize, ShmSegment>,
+}
+
+impl ShmRegistry {
+    /// Drop the segments whose last attachment went away.
+    fn collect(&mut self) {
+        self.segments
+            .retain(|_, segment| !segment.attached || segment.attachments() > 0);
+    }
+}
+
+lazy_static! {
+    static ref SHM_REGISTRY: UPSafeCell<ShmRegistry> = unsafe {
+        UPSafeCell::new(ShmRegistry {
+            next_id: 1,
+            segments: BTreeMap::new(),
+        })
+    };
+}
+
+/// The id of the segment with `key` and its size in pages.
+pub fn shm_lookup(key: usize) -> Option<(usize, usize)> {
+    if key == IPC_PRIVATE {
+        return None;
+    }
+    let mut registry = SHM_REGISTRY.exclusive_access();
+    registry.collect();
+    registry
+        .segments
+        .iter()
+        .find(|(_, segment)| segment.key == key)
+        .map(|(id, segment)| (*id, segment.frames.len()))
+}
+
+/// Make a zeroed segment of `pages` pages under `key` and return its id,
+/// `None` if there are not enough frames. `pages` must not be 0.
+pub fn shm_create(key: usize, pages: usize) -> Option<usize> {
+    assert!(pages > 0, "empty shared memory segment");
+    let mut frames = Vec::with_capacity(pages);
+    for _ in 0..pages {
+        frames.push(Arc::new(frame_alloc()?));
+    }
+    let mut registry = SHM_REGISTRY.exclusive_access();
+    let id = registry.next_id;
+    registry.next_id += 1;
+    registry.segments.insert(
+        id,
+        ShmSegment {
+            key,
+            frames,
+            attached: false,
+        },
+    );
+    Some(id)
+}
+
+/// Size of segment `id` in pages.
+pub fn shm_pages(id: usize) -> Option<usize> {
+    let mut registry = SHM_REGISTRY.exclusive_access();
+    registry.collect();
+    registry.segments.get(&id).map(|segment| segment.frames.len())
+}
+
+/// The frames of segment `id` for a new attachment.
+pub fn shm_attach(id: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    let mut registry = SHM_REGISTRY.exclusive_access();
+    registry.collect();
+    let segment = registry.segments.get_mut(&id)?;
+    segment.attached = true;
+    Some(segment.frames.clone())
+}
+
+/// Whether `first`, the frame at the start of an area, is the first frame
+/// of a segment, i.e. the area is an attachment.
+pub fn shm_is_attachment(first: &Arc<FrameTracker>) -> bool {
+    SHM_REGISTRY
+        .exclusive_access()
+        .segments
+        .values()
+        .any(|segment| Arc::ptr_eq(&segment.frames[0], first))
+}
+
+#[allow(unused)]
+#[test_case]
+/// a segment outlives its creator until it is attached and detached
+pub fn shm_registry_test() {
+    let key = 0x5348_4d54;
+    assert!(shm_lookup(key).is_none());
+    let id = shm_create(key, 2).unwrap();
+    assert_eq!(shm_lookup(key), Some((id, 2)));
+    // nobody attached yet, the segment stays
+    assert_eq!(shm_lookup(key), Some((id, 2)));
+    assert_eq!(shm_pages(id), Some(2));
+    let frames = shm_attach(id).unwrap();
+    let again = shm_attach(id).unwrap();
+    assert!(Arc::ptr_eq(&frames[1], &again[1]));
+    assert!(shm_is_attachment(&frames[0]));
+    assert!(!shm_is_attachment(&frames[1]));
+    drop(again);
+    assert_eq!(shm_lookup(key), Some((id, 2)));
+    drop(frames);
+    assert!(shm_lookup(key).is_none());
+    // private segments are never found by key
+    let private = shm_create(IPC_PRIVATE, 1).unwrap();
+    assert_ne!(private, id);
+    assert!(shm_lookup(IPC_PRIVATE).is_none());
+    drop(shm_attach(private));
+    info!("shm_registry_test passed!");
+}
@@ -0,0 +1,125 @@
+//! Swap space for user pages on the block device
+//!
+//! The swap area is `SWAP_PAGES` page-sized slots on the disk right after
+//! the easy-fs image, slot `i` taking the `PAGE_SIZE / BLOCK_SZ` blocks from
+//! `SWAP_START_BLOCK + i * PAGE_SIZE / BLOCK_SZ` on. A page that was swapped
+//! out keeps its slot number in its invalid pte, see
+//! [`super::PageTableEntry::swap_slot`].
+
+use crate::config::{PAGE_SIZE, SWAP_PAGES, SWAP_START_BLOCK};
+use crate::drivers::block::BLOCK_SZ;
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// Hands out swap slots like the frame allocator hands out frames.
+struct SwapSpace {
+    /// slots from here on were never handed out
+    current: usize,
+    recycled: Vec<usize>,
+    /// slots currently handed out
+    used: usize,
+}
+
+impl SwapSpace {
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = if let Some(slot) = self.recycled.pop() {
+            slot
+        } else if self.current == SWAP_PAGES {
+            return None;
+        } else {
+            self.current += 1;
+            self.current - 1
+        };
+        self.used += 1;
+        Some(slot)
+    }
+    fn dealloc(&mut self, slot: usize) {
+        if slot >= self.current || self.recycled.contains(&slot) {
+            panic!("swap slot {} has not been allocated!", slot);
+        }
+        self.recycled.push(slot);
+        self.used -= 1;
+    }
+}
+
+lazy_static! {
+    static ref SWAP_SPACE: UPSafeCell<SwapSpace> = unsafe {
+        UPSafeCell::new(SwapSpace {
+            current: 0,
+            recycled: Vec::new(),
+            used: 0,
+        })
+    };
+}
+
+/// A free slot, `None` if the swap area is full.
+pub fn swap_alloc() -> Option<usize> {
+    SWAP_SPACE.exclusive_access().alloc()
+}
+
+/// Give `slot` back, whatever it holds is lost.
+pub fn swap_free(slot: usize) {
+    SWAP_SPACE.exclusive_access().dealloc(slot);
+}
+
+/// Slots currently holding a page.
+#[allow(unused)]
+pub fn swap_used() -> usize {
+    SWAP_SPACE.exclusive_access().used
+}
+
+fn first_block(slot: usize) -> usize {
+    SWAP_START_BLOCK + slot * BLOCKS_PER_SLOT
+}
+
+/// Write the page `page` to `slot`.
+pub fn swap_write(slot: usize, page: &[u8]) {
+    for (i, block) in page.chunks(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.write_block(first_block(slot) + i, block);
+    }
+}
+
+/// Read the page in `slot` into `page`.
+pub fn swap_read(slot: usize, page: &mut [u8]) {
+    for (i, block) in page.chunks_mut(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.read_block(first_block(slot) + i, block);
+    }
+}
+
+/// A new slot holding the same page as `slot`, for fork.
+pub fn swap_duplicate(slot: usize) -> Option<usize> {
+    let copy = swap_alloc()?;
+    let mut block = [0u8; BLOCK_SZ];
+    for i in 0..BLOCKS_PER_SLOT {
+        BLOCK_DEVICE.read_block(first_block(slot) + i, &mut block);
+        BLOCK_DEVICE.write_block(first_block(copy) + i, &block);
+    }
+    Some(copy)
+}
+
+#[allow(unused)]
+#[test_case]
+/// a page survives the trip through a slot and its duplicate
+pub fn swap_space_test() {
+    let used = swap_used();
+    let mut page = [0u8; PAGE_SIZE];
+    for (i, byte) in page.iter_mut().enumerate() {
+        *byte = (i * 7) as u8;
+    }
+    let slot = swap_alloc().unwrap();
+    swap_write(slot, &page);
+    let copy = swap_duplicate(slot).unwrap();
+    assert_ne!(copy, slot);
+    assert_eq!(swap_used(), used + 2);
+    let mut read = [0u8; PAGE_SIZE];
+    swap_read(copy, &mut read);
+    assert_eq!(read, page);
+    swap_free(slot);
+    swap_free(copy);
+    assert_eq!(swap_used(), used);
+    info!("swap_space_test passed!");
+}
@@ -0,0 +1,61 @@
+//! A small entropy source for address space randomization
+//!
+//! There is no hardware rng to read, so this is an xorshift generator seeded
+//! from the `time` csr, with the time of every call mixed in again. Good
+//! enough to keep user layouts from being guessed, not for cryptography.
+
+use crate::sync::UPSafeCell;
+use crate::timer::get_time;
+use lazy_static::*;
+
+/// Marsaglia's xorshift64, the state must never be 0.
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+lazy_static! {
+    static ref RNG: UPSafeCell<XorShift64> =
+        unsafe { UPSafeCell::new(XorShift64(get_time() as u64 | 1)) };
+}
+
+/// A random number.
+pub fn random() -> u64 {
+    let mut rng = RNG.exclusive_access();
+    rng.0 ^= (get_time() as u64).rotate_left(32);
+    if rng.0 == 0 {
+        rng.0 = 1;
+    }
+    rng.next()
+}
+
+/// A random number below `bound`, 0 if `bound` is 0.
+pub fn random_below(bound: usize) -> usize {
+    if bound == 0 {
+        0
+    } else {
+        (random() % bound as u64) as usize
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// numbers stay below the bound and do not repeat one value
+pub fn random_test() {
+    assert_eq!(random_below(0), 0);
+    let mut values = [0; 16];
+    for value in values.iter_mut() {
+        *value = random_below(1000);
+        assert!(*value < 1000);
+    }
+    assert!(values.iter().any(|value| *value != values[0]));
+    info!("random_test passed!");
+}
@@ -5,6 +5,21 @@ const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
 
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_HSM_HART_START: usize = 0;
+const SBI_HSM_HART_GET_STATUS: usize = 2;
+/// what hart_get_status says of a hart waiting for hart_start
+const HSM_STATE_STOPPED: usize = 1;
+
+const SBI_EXT_RFENCE: usize = 0x52464E43;
+const SBI_RFENCE_REMOTE_SFENCE_VMA: usize = 1;
+
+const SBI_EXT_SRST: usize = 0x53525354;
+const SBI_SRST_SYSTEM_RESET: usize = 0;
+const SRST_TYPE_SHUTDOWN: usize = 0;
+const SRST_REASON_NONE: usize = 0;
+const SRST_REASON_SYSTEM_FAILURE: usize = 1;
+
 #[inline(always)]
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     let mut ret;
@@ -21,6 +36,29 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// Call function `fid` of the SBI extension `eid`, the value it returns or
+/// the SBI error code.
+#[inline(always)]
+fn sbi_call_ext(eid: usize, fid: usize, args: [usize; 4]) -> Result<usize, isize> {
+    let (error, value): (isize, usize);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => error,
+            inlateout("x11") args[1] => value,
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(error)
+    }
+}
+
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
 }
@@ -33,7 +71,48 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
-pub fn shutdown() -> ! {
+/// Start the stopped hart `hart_id` in supervisor mode at the physical
+/// address `start_addr`, with its id in a0 and `opaque` in a1.
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<(), isize> {
+    sbi_call_ext(SBI_EXT_HSM, SBI_HSM_HART_START, [hart_id, start_addr, opaque, 0]).map(|_| ())
+}
+
+/// Whether `hart_id` exists and waits to be started.
+pub fn hart_stopped(hart_id: usize) -> bool {
+    sbi_call_ext(SBI_EXT_HSM, SBI_HSM_HART_GET_STATUS, [hart_id, 0, 0, 0])
+        == Ok(HSM_STATE_STOPPED)
+}
+
+/// Flush the whole TLB of every hart, this one included.
+pub fn remote_sfence_vma_all() {
+    // a hart mask base of -1 stands for all harts
+    let _ = sbi_call_ext(
+        SBI_EXT_RFENCE,
+        SBI_RFENCE_REMOTE_SFENCE_VMA,
+        [0, usize::MAX, 0, usize::MAX],
+    );
+}
+
+/// Power the machine off, telling the firmware whether it is because
+/// something failed. Firmware without the SRST extension gets the legacy
+/// shutdown call, which cannot tell the two apart.
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure {
+        SRST_REASON_SYSTEM_FAILURE
+    } else {
+        SRST_REASON_NONE
+    };
+    // only returns if the extension is missing or refused
+    let _ = sbi_call_ext(
+        SBI_EXT_SRST,
+        SBI_SRST_SYSTEM_RESET,
+        [SRST_TYPE_SHUTDOWN, reason, 0, 0],
+    );
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
-    panic!("It should shutdown!");
+    // not panicking, this may run in the panic handler already
+    loop {
+        unsafe {
+            core::arch::asm!("wfi");
+        }
+    }
 }
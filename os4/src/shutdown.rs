@@ -0,0 +1,142 @@
+//! Hooks that run in a fixed order when the kernel shuts down cleanly
+//!
+//! Subsystems register a [`ShutdownHook`] during init. [`run_hooks`] runs them
+//! lowest priority first. A hook that panics is reported by the panic handler,
+//! which then carries on with the remaining hooks before the final SBI shutdown.
+
+use crate::config::CompletionPolicy;
+use crate::sync::UPSafeCell;
+use crate::timer;
+use lazy_static::*;
+
+const MAX_SHUTDOWN_HOOKS: usize = 16;
+/// a hook taking longer than this is reported as slow, in microseconds
+const HOOK_BUDGET_US: usize = 100_000;
+
+/// a piece of shutdown work
+#[derive(Copy, Clone)]
+pub struct ShutdownHook {
+    pub name: &'static str,
+    /// hooks with a lower priority run first, equal priorities in registration order
+    pub priority: usize,
+    pub func: fn(),
+}
+
+/// fixed-size table of hooks, so registering never allocates
+struct ShutdownRegistry {
+    hooks: [Option<ShutdownHook>; MAX_SHUTDOWN_HOOKS],
+    /// name of the hook that is running right now
+    running: Option<&'static str>,
+}
+
+impl ShutdownRegistry {
+    fn new() -> Self {
+        Self {
+            hooks: [None; MAX_SHUTDOWN_HOOKS],
+            running: None,
+        }
+    }
+    fn register(&mut self, hook: ShutdownHook) -> bool {
+        match self.hooks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(hook);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Remove the hook that has to run next.
+    fn take_next(&mut self) -> Option<ShutdownHook> {
+        let mut next: Option<usize> = None;
+        for (i, slot) in self.hooks.iter().enumerate() {
+            if let Some(hook) = slot {
+                match next {
+                    Some(j) if self.hooks[j].unwrap().priority <= hook.priority => {}
+                    _ => next = Some(i),
+                }
+            }
+        }
+        next.and_then(|i| self.hooks[i].take())
+    }
+}
+
+lazy_static! {
+    static ref SHUTDOWN_HOOKS: UPSafeCell<ShutdownRegistry> =
+        unsafe { UPSafeCell::new(ShutdownRegistry::new()) };
+}
+
+/// Register `hook`, returns false if the table is full.
+pub fn register(hook: ShutdownHook) -> bool {
+    SHUTDOWN_HOOKS.exclusive_access().register(hook)
+}
+
+/// Run every hook that has not run yet. The registry is not borrowed while
+/// a hook runs, so a hook panicking into the panic handler can resume here.
+pub fn run_hooks() {
+    loop {
+        let hook = {
+            let mut registry = SHUTDOWN_HOOKS.exclusive_access();
+            let hook = registry.take_next();
+            registry.running = hook.map(|hook| hook.name);
+            hook
+        };
+        let hook = match hook {
+            Some(hook) => hook,
+            None => break,
+        };
+        let start = timer::get_time_us();
+        (hook.func)();
+        let spent = timer::get_time_us() - start;
+        if spent > HOOK_BUDGET_US {
+            warn!(
+                "[kernel] shutdown hook {} took {}us, over its {}us budget",
+                hook.name, spent, HOOK_BUDGET_US
+            );
+        }
+    }
+    SHUTDOWN_HOOKS.exclusive_access().running = None;
+}
+
+/// Run the hooks once every application has exited. Returns the status
+/// qemu ends with under `policy`, `None` if the kernel stays up.
+pub fn complete(policy: CompletionPolicy) -> Option<u16> {
+    run_hooks();
+    println!("[kernel] All applications completed!");
+    match policy {
+        CompletionPolicy::Shutdown => Some(0),
+        CompletionPolicy::Idle => None,
+    }
+}
+
+/// Name of the hook that was running when the kernel panicked, if any.
+/// The hook counts as finished afterwards.
+pub fn interrupted_hook() -> Option<&'static str> {
+    SHUTDOWN_HOOKS.exclusive_access().running.take()
+}
+
+#[allow(unused)]
+#[test_case]
+/// hooks must come out by priority, then in registration order
+pub fn shutdown_registry_test() {
+    fn nop() {}
+    let hook = |name, priority| ShutdownHook {
+        name,
+        priority,
+        func: nop,
+    };
+    let mut registry = ShutdownRegistry::new();
+    assert!(registry.register(hook("late", 20)));
+    assert!(registry.register(hook("first", 0)));
+    assert!(registry.register(hook("second", 10)));
+    assert!(registry.register(hook("third", 10)));
+    let order = ["first", "second", "third", "late"];
+    for name in order {
+        assert_eq!(registry.take_next().unwrap().name, name);
+    }
+    assert!(registry.take_next().is_none());
+    for i in 0..MAX_SHUTDOWN_HOOKS {
+        assert!(registry.register(hook("filler", i)));
+    }
+    assert!(!registry.register(hook("overflow", 0)));
+    info!("shutdown_registry_test passed!");
+}
@@ -0,0 +1,65 @@
+//! Bringing up the other harts through the SBI HSM extension
+//!
+//! The boot hart sets the kernel up alone and then starts the other harts at
+//! `_start_secondary`, each on a boot stack of its own, where they join the
+//! scheduling loop on the shared ready queue.
+//!
+//! The kernel was written for a single hart, `UPSafeCell` included, so all
+//! harts share it behind one big lock: a hart holds it whenever it runs
+//! kernel code and lets go only on its way to user mode or while it idles.
+
+use crate::config::MAX_HARTS;
+use crate::sbi;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// whether some hart is running kernel code
+static KERNEL_LOCK: AtomicBool = AtomicBool::new(false);
+/// the harts that came up, one bit each
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+/// The id of the hart we run on, `entry.asm` and the trap entry keep it in `tp`.
+pub fn hart_id() -> usize {
+    let id;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// Take the big kernel lock, spinning while another hart holds it.
+pub fn lock_kernel() {
+    while KERNEL_LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+}
+
+/// Let go of the big kernel lock, right before leaving the kernel or idling.
+pub fn unlock_kernel() {
+    KERNEL_LOCK.store(false, Ordering::Release);
+}
+
+/// Count this hart in, once it is ready to run tasks.
+pub fn mark_online() {
+    ONLINE.fetch_or(1 << hart_id(), Ordering::SeqCst);
+}
+
+/// How many harts came up so far.
+pub fn online_harts() -> usize {
+    ONLINE.load(Ordering::SeqCst).count_ones() as usize
+}
+
+/// Start every stopped hart below `MAX_HARTS`, they wait for the big kernel
+/// lock before touching anything.
+pub fn start_other_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    for id in (0..MAX_HARTS).filter(|id| *id != hart_id() && sbi::hart_stopped(*id)) {
+        if let Err(err) = sbi::hart_start(id, _start_secondary as usize, 0) {
+            warn!("[kernel] hart {} did not start, sbi error {}", id, err);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Condition variables that wait with a [`Mutex`] released
+
+use super::{Mutex, UPSafeCell};
+use crate::syscall::errno::{EINTR, EPERM};
+use crate::task::{block_current_and_run_next, current_killed, current_pid, wakeup_task};
+use alloc::collections::VecDeque;
+
+pub struct Condvar {
+    /// pids waiting to be signalled, the longest waiting first
+    wait_queue: UPSafeCell<VecDeque<usize>>,
+}
+
+impl Condvar {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            wait_queue: unsafe { UPSafeCell::new(VecDeque::new()) },
+        }
+    }
+
+    /// Wake the longest waiting task, nothing happens if nobody waits.
+    pub fn signal(&self) {
+        let next = self.wait_queue.exclusive_access().pop_front();
+        if let Some(pid) = next {
+            wakeup_task(pid);
+        }
+    }
+
+    /// Wake every waiting task.
+    pub fn broadcast(&self) {
+        let waiters = core::mem::take(&mut *self.wait_queue.exclusive_access());
+        for pid in waiters {
+            wakeup_task(pid);
+        }
+    }
+
+    /// Release `mutex`, which the current task must hold, block until
+    /// `signal` or `broadcast` picks this task, and take `mutex` again. No
+    /// other task runs between the release and the block, so a signal sent
+    /// after the release is not lost. `-EPERM` if the task does not hold
+    /// `mutex`, `-EINTR` if it was killed, it does not hold `mutex` then.
+    pub fn wait(&self, mutex: &dyn Mutex) -> Result<(), isize> {
+        let pid = current_pid();
+        if !mutex.release(pid) {
+            return Err(EPERM);
+        }
+        self.wait_queue.exclusive_access().push_back(pid);
+        loop {
+            block_current_and_run_next();
+            let mut wait_queue = self.wait_queue.exclusive_access();
+            // picked by `signal` or `broadcast`, other wakeups come from signals
+            if !wait_queue.contains(&pid) {
+                break;
+            }
+            if current_killed() {
+                wait_queue.retain(|waiter| *waiter != pid);
+                return Err(EINTR);
+            }
+        }
+        if mutex.lock() {
+            Ok(())
+        } else {
+            Err(EINTR)
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Wait queues keyed by the physical address of a user word, for futexes
+//!
+//! Tasks sharing memory see a futex word at different virtual addresses but
+//! in the same frame, so the queue of a word is found by its physical
+//! address. A waiter holds a reference to the frame while it sleeps, which
+//! keeps the frame from going to swap or being reused under the key.
+
+use super::UPSafeCell;
+use crate::mm::{FrameTracker, PhysAddr};
+use crate::syscall::errno::{EAGAIN, EINTR};
+use crate::task::{block_current_and_run_next, current_killed, current_pid, wakeup_task};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::mem::size_of;
+use lazy_static::*;
+
+lazy_static! {
+    /// pids waiting on each futex word, the longest waiting first
+    static ref FUTEX_QUEUES: UPSafeCell<BTreeMap<usize, VecDeque<usize>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+fn futex_key(frame: &FrameTracker, offset: usize) -> usize {
+    PhysAddr::from(frame.ppn).0 + offset
+}
+
+/// Block the current task on the word at `offset` in `frame` if the word
+/// still holds `val`, until `futex_wake` picks it, keeping `frame` all the
+/// while. The check and the block happen without another task running in
+/// between, so a wake after the word changed is not lost. `-EAGAIN` if the word held something else,
+/// `-EINTR` if the task was killed while it waited.
+pub fn futex_wait(frame: Arc<FrameTracker>, offset: usize, val: u32) -> Result<(), isize> {
+    let bytes = &frame.ppn.get_bytes_array()[offset..offset + size_of::<u32>()];
+    if u32::from_ne_bytes(bytes.try_into().unwrap()) != val {
+        return Err(EAGAIN);
+    }
+    let key = futex_key(&frame, offset);
+    let pid = current_pid();
+    FUTEX_QUEUES
+        .exclusive_access()
+        .entry(key)
+        .or_default()
+        .push_back(pid);
+    loop {
+        block_current_and_run_next();
+        let mut queues = FUTEX_QUEUES.exclusive_access();
+        let queue = match queues.get_mut(&key) {
+            Some(queue) if queue.contains(&pid) => queue,
+            // picked by `futex_wake`, other wakeups come from signals
+            _ => return Ok(()),
+        };
+        if current_killed() {
+            queue.retain(|waiter| *waiter != pid);
+            if queue.is_empty() {
+                queues.remove(&key);
+            }
+            return Err(EINTR);
+        }
+    }
+}
+
+/// Wake up to `count` tasks waiting on the word at `offset` in `frame`,
+/// the longest waiting first, and return how many were woken.
+pub fn futex_wake(frame: &FrameTracker, offset: usize, count: usize) -> usize {
+    let key = futex_key(frame, offset);
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    let queue = match queues.get_mut(&key) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let woken: Vec<usize> = queue.drain(..count.min(queue.len())).collect();
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    drop(queues);
+    for pid in woken.iter() {
+        wakeup_task(*pid);
+    }
+    woken.len()
+}
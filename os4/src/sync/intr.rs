@@ -0,0 +1,61 @@
+//! Masking supervisor interrupts for short critical sections
+
+use riscv::register::{sie, sstatus};
+
+/// Keep supervisor interrupts disabled until the guard is dropped.
+///
+/// The previous `sstatus.SIE` is restored on drop, so guards can nest.
+pub struct InterruptGuard {
+    sie_before: bool,
+}
+
+impl InterruptGuard {
+    pub fn disable() -> Self {
+        let sie_before = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        Self { sie_before }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.sie_before {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// a pending software interrupt must not be taken while a guard is held
+pub fn interrupt_guard_test() {
+    unsafe {
+        sstatus::set_sie();
+        sie::set_ssoft();
+    }
+    {
+        let _guard = InterruptGuard::disable();
+        assert!(!sstatus::read().sie());
+        // force a pending interrupt; taking it here would end in `trap_from_kernel`
+        unsafe {
+            core::arch::asm!("csrs sip, {}", in(reg) 1usize << 1);
+        }
+        {
+            let _nested = InterruptGuard::disable();
+        }
+        assert!(!sstatus::read().sie());
+        unsafe {
+            core::arch::asm!("csrc sip, {}", in(reg) 1usize << 1);
+        }
+    }
+    assert!(sstatus::read().sie());
+    unsafe {
+        sie::clear_ssoft();
+        sstatus::clear_sie();
+    }
+    info!("interrupt_guard_test passed!");
+}
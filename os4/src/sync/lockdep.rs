@@ -0,0 +1,176 @@
+//! Catching lock order inversions before they deadlock, with the `lockdep` feature
+//!
+//! Every `SpinLock` taken is pushed on the taking hart's stack of held locks,
+//! and each lock already held when another one is taken adds an edge "held
+//! before taken" to a global table, together with where both were taken.
+//! Taking a lock this hart holds already, or taking `b` while holding `a`
+//! after `b` was once held while taking `a`, panics with the sites of both
+//! acquisitions instead of spinning forever, even if this run would have
+//! been lucky. `UPSafeCell` is left out, a second borrow panics already.
+//!
+//! The bookkeeping guards itself with a bare flag, tracking it through a
+//! `SpinLock` would recurse.
+
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use core::cell::UnsafeCell;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// locks a hart may hold at the same time
+const MAX_HELD: usize = 8;
+/// distinct "held before taken" pairs remembered
+const MAX_EDGES: usize = 64;
+
+type Site = &'static Location<'static>;
+
+#[derive(Copy, Clone)]
+struct Held {
+    lock: usize,
+    site: Site,
+}
+
+/// `before` was held when `after` was taken
+#[derive(Copy, Clone)]
+struct Edge {
+    before: Held,
+    after: Held,
+}
+
+struct LockdepState {
+    held: [[Option<Held>; MAX_HELD]; MAX_HARTS],
+    edges: [Option<Edge>; MAX_EDGES],
+    /// the edge table filled up, pairs seen later go unchecked
+    edges_full: bool,
+}
+
+struct LockdepCell {
+    busy: AtomicBool,
+    state: UnsafeCell<LockdepState>,
+}
+
+unsafe impl Sync for LockdepCell {}
+
+static LOCKDEP: LockdepCell = LockdepCell {
+    busy: AtomicBool::new(false),
+    state: UnsafeCell::new(LockdepState {
+        held: [[None; MAX_HELD]; MAX_HARTS],
+        edges: [None; MAX_EDGES],
+        edges_full: false,
+    }),
+};
+
+/// Run `f` on the bookkeeping, alone among the harts.
+fn with_state<R>(f: impl FnOnce(&mut LockdepState) -> R) -> R {
+    while LOCKDEP
+        .busy
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    let result = f(unsafe { &mut *LOCKDEP.state.get() });
+    LOCKDEP.busy.store(false, Ordering::Release);
+    result
+}
+
+/// What taking a lock would break, found while the bookkeeping was held and
+/// reported once it is released, so the panic handler can take locks.
+enum Violation {
+    Recursive { held: Held, site: Site },
+    Inversion { edge: Edge, held: Held, site: Site },
+}
+
+/// Check that this hart may take `lock` at `site` and remember the order
+/// against the locks it holds, right before spinning on it.
+pub fn check_acquire(lock: usize, site: Site) {
+    let checked = with_state(|state| {
+        let mut filled = false;
+        for held in state.held[hart_id()].iter().flatten().copied() {
+            if held.lock == lock {
+                return Err(Violation::Recursive { held, site });
+            }
+            let reversed = state
+                .edges
+                .iter()
+                .flatten()
+                .find(|edge| edge.before.lock == lock && edge.after.lock == held.lock);
+            if let Some(edge) = reversed {
+                return Err(Violation::Inversion {
+                    edge: *edge,
+                    held,
+                    site,
+                });
+            }
+            let known = state
+                .edges
+                .iter()
+                .flatten()
+                .any(|edge| edge.before.lock == held.lock && edge.after.lock == lock);
+            if !known {
+                match state.edges.iter_mut().find(|slot| slot.is_none()) {
+                    Some(slot) => {
+                        *slot = Some(Edge {
+                            before: held,
+                            after: Held { lock, site },
+                        })
+                    }
+                    None if !state.edges_full => {
+                        state.edges_full = true;
+                        filled = true;
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(filled)
+    });
+    match checked {
+        Err(Violation::Recursive { held, site }) => panic!(
+            "lockdep: lock {:#x} taken again at {}, this hart holds it since {}",
+            lock, site, held.site
+        ),
+        Err(Violation::Inversion { edge, held, site }) => panic!(
+            "lockdep: lock {:#x} taken at {} while holding {:#x} taken at {}, \
+             but {:#x} was held since {} when {:#x} was taken at {}",
+            lock,
+            site,
+            held.lock,
+            held.site,
+            edge.before.lock,
+            edge.before.site,
+            edge.after.lock,
+            edge.after.site
+        ),
+        Ok(true) => warn!(
+            "lockdep: more than {} lock pairs, new ones go unchecked",
+            MAX_EDGES
+        ),
+        Ok(false) => {}
+    }
+}
+
+/// This hart now holds `lock`, taken at `site`.
+pub fn acquired(lock: usize, site: Site) {
+    let pushed = with_state(|state| {
+        let slot = state.held[hart_id()].iter_mut().find(|slot| slot.is_none());
+        slot.map(|slot| *slot = Some(Held { lock, site })).is_some()
+    });
+    assert!(
+        pushed,
+        "lockdep: more than {} locks held at {}",
+        MAX_HELD, site
+    );
+}
+
+/// This hart let go of `lock`, guards may be dropped in any order.
+pub fn released(lock: usize) {
+    with_state(|state| {
+        let slot = state.held[hart_id()]
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(held) if held.lock == lock));
+        if let Some(slot) = slot {
+            *slot = None;
+        }
+    });
+}
@@ -1,5 +1,21 @@
 //! Synchronization and interior mutability primitives
 
+mod condvar;
+mod futex;
+mod intr;
+#[cfg(feature = "lockdep")]
+mod lockdep;
+mod mutex;
+mod percpu;
+mod semaphore;
+mod spin;
 mod up;
 
+pub use condvar::Condvar;
+pub use futex::{futex_wait, futex_wake};
+pub use intr::InterruptGuard;
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use percpu::PerCpu;
+pub use semaphore::Semaphore;
+pub use spin::{SpinLock, SpinNoIrqLock};
 pub use up::UPSafeCell;
@@ -0,0 +1,160 @@
+//! Mutexes for user tasks, one that yields while it waits and one that blocks
+//!
+//! Both remember the pid holding them, so only the holder can unlock and a
+//! task that goes away holding one can give it up, see [`Mutex::release`].
+
+use super::UPSafeCell;
+use crate::task::{
+    block_current_and_run_next, current_killed, current_pid, suspend_current_and_run_next,
+    wakeup_task,
+};
+use alloc::collections::VecDeque;
+
+pub trait Mutex: Sync + Send {
+    /// Take the mutex for the current task, waiting while another task holds
+    /// it. False if the task was killed while it waited, it does not hold
+    /// the mutex then.
+    fn lock(&self) -> bool;
+    /// Give up the mutex if `pid` holds it, false if it does not.
+    fn release(&self, pid: usize) -> bool;
+    /// The pid of the task holding the mutex.
+    #[allow(unused)]
+    fn owner(&self) -> Option<usize>;
+}
+
+/// A mutex whose waiters keep yielding until they find it free.
+pub struct MutexSpin {
+    owner: UPSafeCell<Option<usize>>,
+}
+
+impl MutexSpin {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            owner: unsafe { UPSafeCell::new(None) },
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) -> bool {
+        loop {
+            let mut owner = self.owner.exclusive_access();
+            if owner.is_none() {
+                *owner = Some(current_pid());
+                return true;
+            }
+            drop(owner);
+            if current_killed() {
+                return false;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn release(&self, pid: usize) -> bool {
+        let mut owner = self.owner.exclusive_access();
+        if *owner != Some(pid) {
+            return false;
+        }
+        *owner = None;
+        true
+    }
+
+    fn owner(&self) -> Option<usize> {
+        *self.owner.exclusive_access()
+    }
+}
+
+/// A mutex whose waiters block and get it in the order they came.
+pub struct MutexBlocking {
+    inner: UPSafeCell<MutexBlockingInner>,
+}
+
+struct MutexBlockingInner {
+    owner: Option<usize>,
+    /// pids waiting for the mutex, the longest waiting first
+    wait_queue: VecDeque<usize>,
+}
+
+impl MutexBlocking {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(MutexBlockingInner {
+                    owner: None,
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) -> bool {
+        let pid = current_pid();
+        let mut inner = self.inner.exclusive_access();
+        if inner.owner.is_none() {
+            inner.owner = Some(pid);
+            return true;
+        }
+        inner.wait_queue.push_back(pid);
+        loop {
+            drop(inner);
+            block_current_and_run_next();
+            inner = self.inner.exclusive_access();
+            // `release` hands the mutex over to the first waiter
+            if inner.owner == Some(pid) {
+                return true;
+            }
+            // signals wake blocked tasks too
+            if current_killed() {
+                inner.wait_queue.retain(|waiter| *waiter != pid);
+                return false;
+            }
+        }
+    }
+
+    fn release(&self, pid: usize) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if inner.owner != Some(pid) {
+            return false;
+        }
+        inner.owner = inner.wait_queue.pop_front();
+        let next = inner.owner;
+        drop(inner);
+        if let Some(next) = next {
+            wakeup_task(next);
+        }
+        true
+    }
+
+    fn owner(&self) -> Option<usize> {
+        self.inner.exclusive_access().owner
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// only the holder can unlock, both kinds start out free
+pub fn mutex_test() {
+    use crate::loader::get_app_data;
+    use crate::task::{with_current_task, TaskControlBlock};
+    use alloc::sync::Arc;
+    // never dispatched, it only lends the mutexes its pid
+    let task = Arc::new(TaskControlBlock::new(get_app_data(0)));
+    let pid = task.getpid();
+    let spin = MutexSpin::new();
+    let blocking = MutexBlocking::new();
+    for mutex in [&spin as &dyn Mutex, &blocking] {
+        assert_eq!(mutex.owner(), None);
+        assert!(!mutex.release(pid));
+        assert!(with_current_task(task.clone(), || mutex.lock()));
+        assert_eq!(mutex.owner(), Some(pid));
+        assert!(!mutex.release(pid + 1));
+        assert!(mutex.release(pid));
+        assert_eq!(mutex.owner(), None);
+    }
+    info!("mutex_test passed!");
+}
@@ -0,0 +1,46 @@
+//! Storage with one slot for each hart
+
+use super::UPSafeCell;
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// A `T` for each hart, by hart id, so harts keep their own state apart
+/// without looking each other up in a shared table.
+///
+/// A hart normally only touches its own slot, the others are borrowed under
+/// the big kernel lock when something has to look at every hart.
+pub struct PerCpu<T> {
+    slots: Vec<UPSafeCell<T>>,
+}
+
+impl<T> PerCpu<T> {
+    /// Fill the slot of each of the `MAX_HARTS` harts with `init()`.
+    pub fn new(init: impl Fn() -> T) -> Self {
+        Self {
+            slots: (0..MAX_HARTS)
+                .map(|_| unsafe { UPSafeCell::new(init()) })
+                .collect(),
+        }
+    }
+    /// The slot of the hart we run on.
+    #[track_caller]
+    pub fn this_cpu(&self) -> RefMut<'_, T> {
+        self.get(hart_id())
+    }
+    /// The slot of hart `hart`.
+    #[track_caller]
+    pub fn get(&self, hart: usize) -> RefMut<'_, T> {
+        self.slots[hart].exclusive_access()
+    }
+    /// The slot of hart `hart`, `None` while someone else has it borrowed.
+    #[track_caller]
+    pub fn try_get(&self, hart: usize) -> Option<RefMut<'_, T>> {
+        self.slots[hart].try_exclusive_access().ok()
+    }
+    /// Every slot in hart order, each one borrowed until the next is taken.
+    pub fn iter(&self) -> impl Iterator<Item = RefMut<'_, T>> {
+        self.slots.iter().map(|slot| slot.exclusive_access())
+    }
+}
@@ -0,0 +1,95 @@
+//! Counting semaphores whose waiters block instead of spinning
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, current_pid, wakeup_task};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// A count of permits, `down` takes one and `up` gives one back.
+pub struct Semaphore {
+    inner: UPSafeCell<SemaphoreInner>,
+}
+
+struct SemaphoreInner {
+    /// permits nobody holds, only above 0 while nobody waits
+    count: usize,
+    /// pids waiting for a permit, the longest waiting first
+    wait_queue: VecDeque<usize>,
+    /// pids `up` handed a permit to that did not run since
+    granted: Vec<usize>,
+}
+
+impl Semaphore {
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(SemaphoreInner {
+                    count,
+                    wait_queue: VecDeque::new(),
+                    granted: Vec::new(),
+                })
+            },
+        }
+    }
+
+    /// Give a permit back. It goes straight to the longest waiting task, so a
+    /// task that comes along later cannot take it first.
+    pub fn up(&self) {
+        let mut inner = self.inner.exclusive_access();
+        match inner.wait_queue.pop_front() {
+            Some(pid) => {
+                inner.granted.push(pid);
+                drop(inner);
+                wakeup_task(pid);
+            }
+            None => inner.count += 1,
+        }
+    }
+
+    /// Take a permit, blocking until `up` hands one over if there is none.
+    /// False if the task was killed while it waited, it holds no permit then.
+    pub fn down(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if inner.count > 0 {
+            inner.count -= 1;
+            return true;
+        }
+        let pid = current_pid();
+        inner.wait_queue.push_back(pid);
+        loop {
+            drop(inner);
+            block_current_and_run_next();
+            inner = self.inner.exclusive_access();
+            if let Some(i) = inner.granted.iter().position(|granted| *granted == pid) {
+                inner.granted.swap_remove(i);
+                return true;
+            }
+            // signals wake blocked tasks too
+            if current_killed() {
+                inner.wait_queue.retain(|waiter| *waiter != pid);
+                return false;
+            }
+        }
+    }
+
+    /// Permits left, 0 while tasks wait.
+    #[allow(unused)]
+    pub fn count(&self) -> usize {
+        self.inner.exclusive_access().count
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// permits are counted when nobody waits
+pub fn semaphore_test() {
+    let sem = Semaphore::new(2);
+    assert!(sem.down());
+    assert!(sem.down());
+    assert_eq!(sem.count(), 0);
+    sem.up();
+    sem.up();
+    sem.up();
+    assert_eq!(sem.count(), 3);
+    info!("semaphore_test passed!");
+}
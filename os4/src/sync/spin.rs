@@ -0,0 +1,161 @@
+//! Spin locks for state shared between harts
+//!
+//! Unlike `UPSafeCell`, which only turns a second borrow into a panic, these
+//! locks keep other harts out while they are held. `SpinNoIrqLock` also
+//! masks interrupts on the holding hart, for state a trap handler may want
+//! while the interrupted code holds it. With the `lockdep` feature every
+//! acquisition is checked against the order locks were taken in before.
+
+use super::InterruptGuard;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Mutual exclusion by spinning on an atomic flag.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+/// Access to the data of a `SpinLock`, released on drop.
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(value),
+        }
+    }
+    /// Spin until the lock is free, then take it.
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        #[cfg(feature = "lockdep")]
+        super::lockdep::check_acquire(self.id(), Location::caller());
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            // wait with plain loads, the cache line stays shared meanwhile
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+    /// Take the lock if it is free, without spinning.
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        #[cfg(feature = "lockdep")]
+        super::lockdep::acquired(self.id(), Location::caller());
+        Some(SpinLockGuard { lock: self })
+    }
+    /// What lockdep knows the lock by.
+    #[cfg(feature = "lockdep")]
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        super::lockdep::released(self.lock.id());
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A `SpinLock` that keeps supervisor interrupts masked while it is held, so
+/// a trap on the holding hart can never spin on it forever.
+pub struct SpinNoIrqLock<T> {
+    inner: SpinLock<T>,
+}
+
+/// Access to the data of a `SpinNoIrqLock`, the lock is released before
+/// interrupts are restored.
+pub struct SpinNoIrqLockGuard<'a, T> {
+    // dropped in declaration order: unlock first, then unmask
+    guard: SpinLockGuard<'a, T>,
+    _irq: InterruptGuard,
+}
+
+impl<T> SpinNoIrqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: SpinLock::new(value),
+        }
+    }
+    /// Mask interrupts, then spin until the lock is free and take it.
+    #[track_caller]
+    pub fn lock(&self) -> SpinNoIrqLockGuard<'_, T> {
+        let irq = InterruptGuard::disable();
+        SpinNoIrqLockGuard {
+            guard: self.inner.lock(),
+            _irq: irq,
+        }
+    }
+}
+
+impl<T> Deref for SpinNoIrqLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinNoIrqLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// a held lock refuses a second taker and masks interrupts until released
+pub fn spin_lock_test() {
+    use riscv::register::sstatus;
+    let lock = SpinLock::new(1usize);
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        assert!(lock.try_lock().is_none());
+    }
+    assert_eq!(*lock.try_lock().unwrap(), 2);
+    unsafe {
+        sstatus::set_sie();
+    }
+    let lock = SpinNoIrqLock::new(0usize);
+    {
+        let mut guard = lock.lock();
+        assert!(!sstatus::read().sie());
+        *guard += 1;
+    }
+    assert!(sstatus::read().sie());
+    assert_eq!(*lock.lock(), 1);
+    unsafe {
+        sstatus::clear_sie();
+    }
+    info!("spin_lock_test passed!");
+}
@@ -1,6 +1,9 @@
 //! Uniprocessor interior mutability primitives
 
-use core::cell::{RefCell, RefMut};
+#[cfg(debug_assertions)]
+use core::cell::Cell;
+use core::cell::{BorrowMutError, RefCell, RefMut};
+use core::panic::Location;
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
@@ -12,6 +15,10 @@ use core::cell::{RefCell, RefMut};
 pub struct UPSafeCell<T> {
     /// inner data
     inner: RefCell<T>,
+    /// where the last borrow that succeeded was taken, which is the one
+    /// still held whenever another borrow fails
+    #[cfg(debug_assertions)]
+    borrowed_at: Cell<Option<&'static Location<'static>>>,
 }
 
 unsafe impl<T> Sync for UPSafeCell<T> {}
@@ -22,10 +29,82 @@ impl<T> UPSafeCell<T> {
     pub unsafe fn new(value: T) -> Self {
         Self {
             inner: RefCell::new(value),
+            #[cfg(debug_assertions)]
+            borrowed_at: Cell::new(None),
         }
     }
-    /// Panic if the data has been borrowed.
+    /// Panic if the data has been borrowed, in debug builds the message says
+    /// where the outstanding borrow was taken.
+    #[track_caller]
     pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+        match self.try_exclusive_access() {
+            Ok(inner) => inner,
+            Err(_) => self.already_borrowed(),
+        }
+    }
+    /// Like `exclusive_access`, but an outstanding borrow is an error for
+    /// the caller to handle, say code that may run while its caller holds
+    /// the data and can do without it.
+    #[track_caller]
+    pub fn try_exclusive_access(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        let inner = self.inner.try_borrow_mut()?;
+        #[cfg(debug_assertions)]
+        self.borrowed_at.set(Some(Location::caller()));
+        Ok(inner)
+    }
+    #[track_caller]
+    fn already_borrowed(&self) -> ! {
+        #[cfg(debug_assertions)]
+        if let Some(site) = self.borrowed_at.get() {
+            panic!(
+                "UPSafeCell borrowed at {} while still borrowed at {}",
+                Location::caller(),
+                site
+            );
+        }
+        panic!(
+            "UPSafeCell borrowed at {} while already borrowed",
+            Location::caller()
+        );
+    }
+    /// Run `f` with exclusive access to the inner data.
+    ///
+    /// The borrow is released before returning, so the caller may `__switch`
+    /// away using whatever `f` computed without dropping a guard by hand.
+    #[track_caller]
+    pub fn exclusive_session<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut inner = self.exclusive_access();
+        f(&mut inner)
     }
+}
+
+#[allow(unused)]
+#[test_case]
+/// the data must be borrowable again once a session is over
+pub fn exclusive_session_test() {
+    let cell = unsafe { UPSafeCell::new(0usize) };
+    let doubled = cell.exclusive_session(|value| {
+        *value += 1;
+        *value * 2
+    });
+    assert_eq!(doubled, 2);
+    *cell.exclusive_access() += 1;
+    assert_eq!(cell.exclusive_session(|value| *value), 2);
+    info!("exclusive_session_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a second borrow is refused while the first is held and allowed after it
+pub fn try_exclusive_access_test() {
+    let cell = unsafe { UPSafeCell::new(0usize) };
+    let held = cell.exclusive_access();
+    assert!(cell.try_exclusive_access().is_err());
+    drop(held);
+    *cell.try_exclusive_access().unwrap() += 1;
+    assert_eq!(*cell.exclusive_access(), 1);
+    info!("try_exclusive_access_test passed!");
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+//! Error numbers returned (negated) by syscalls, with the values Linux uses
+
+/// not the owner, e.g. unlocking a mutex another task holds
+pub const EPERM: isize = 1;
+/// no such file, or no shared memory segment with the key
+pub const ENOENT: isize = 2;
+/// no such task
+pub const ESRCH: isize = 3;
+/// a signal interrupted the syscall
+pub const EINTR: isize = 4;
+/// not an open file descriptor
+pub const EBADF: isize = 9;
+/// no task to wait for
+pub const ECHILD: isize = 10;
+/// try again, e.g. the futex word changed before the wait
+pub const EAGAIN: isize = 11;
+/// out of memory
+pub const ENOMEM: isize = 12;
+/// the file was not opened for the access asked for
+pub const EACCES: isize = 13;
+/// the range is already (partly) mapped
+pub const EEXIST: isize = 17;
+/// the file cannot be mapped, e.g. a pipe or the console
+pub const ENODEV: isize = 19;
+/// invalid argument
+pub const EINVAL: isize = 22;
@@ -1,21 +1,78 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::translated_byte_buffer;
-use crate::task::current_user_token;
-
-const FD_STDOUT: usize = 1;
+use crate::config::MAX_PATH_LEN;
+use crate::fs::{make_pipe, open_file, OpenFlags};
+use crate::mm::{self, MapPermission};
+use crate::task::{close_file, current_file, current_user_token, install_file, populate_user_buffer};
 
+/// 把 `buf` 中的 `len` 个字节写入文件 `fd`，返回写入的字节数；
+/// `fd` 没有打开或不可写、`buf` 不可读返回 -1
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
-        }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+    let file = match current_file(fd) {
+        Some(file) if file.writable() => file,
+        _ => return -1,
+    };
+    populate_user_buffer(buf as usize, len, MapPermission::R);
+    match mm::user_buffer(current_user_token(), buf, len, false) {
+        Some(buf) => file.write(buf) as isize,
+        None => -1,
+    }
+}
+
+/// 从文件 `fd` 读取最多 `len` 个字节到 `buf`，返回读到的字节数，0 表示文件结束；
+/// 标准输入没有输入时让出 CPU 等待第一个字节，之后只取已经到达的字节。
+/// `fd` 没有打开或不可读、`buf` 不可写返回 -1
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    let file = match current_file(fd) {
+        Some(file) if file.readable() => file,
+        _ => return -1,
+    };
+    populate_user_buffer(buf as usize, len, MapPermission::W);
+    match mm::user_buffer(current_user_token(), buf, len, true) {
+        Some(buf) => file.read(buf) as isize,
+        None => -1,
+    }
+}
+
+/// 打开根目录下名为 `path` 的文件，返回最小的空闲文件描述符；
+/// 文件不存在且没有 CREATE、路径读不出来或 `flags` 不合法返回 -1
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    populate_user_buffer(path as usize, MAX_PATH_LEN + 1, MapPermission::R);
+    let path = match mm::translated_str(current_user_token(), path, MAX_PATH_LEN) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    match open_file(path.as_str(), flags) {
+        Some(inode) => install_file(inode) as isize,
+        None => -1,
+    }
+}
+
+/// 关闭文件描述符 `fd`，没有打开返回 -1
+pub fn sys_close(fd: usize) -> isize {
+    if close_file(fd) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// 创建一个管道，把读端和写端的文件描述符依次写入 `pipe[0]` 和 `pipe[1]`；
+/// `pipe` 不可写返回 -1，两个描述符也不会留下
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let (read_end, write_end) = make_pipe();
+    let fds = [install_file(read_end), install_file(write_end)];
+    populate_user_buffer(pipe as usize, core::mem::size_of_val(&fds), MapPermission::W);
+    match mm::copy_to_user(current_user_token(), pipe as *mut [usize; 2], &fds) {
+        Ok(()) => 0,
+        Err(err) => {
+            close_file(fds[0]);
+            close_file(fds[1]);
+            err
         }
     }
-}
\ No newline at end of file
+}
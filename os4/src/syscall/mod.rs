@@ -10,37 +10,169 @@
 //! `sys_` then the name of the syscall. You can find functions like this in
 //! submodules, and you should also implement syscalls this way.
 
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE: usize = 59;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_TIMES: usize = 153;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_SBRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_WAIT4: usize = 260;
 const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_SPAWN: usize = 400;
 const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_MEMINFO: usize = 411;
+const SYSCALL_MEM_STAT: usize = 412;
+const SYSCALL_GETPAGESIZE: usize = 413;
+const SYSCALL_READ_EVENTLOG: usize = 414;
+const SYSCALL_SET_BATCH: usize = 415;
+const SYSCALL_READ_TRACE: usize = 416;
+const SYSCALL_STRACE: usize = 417;
+const SYSCALL_TASK_STATS: usize = 418;
+const SYSCALL_MMAP_FILE: usize = 419;
+const SYSCALL_THREAD_CREATE: usize = 460;
+const SYSCALL_WAITTID: usize = 462;
+const SYSCALL_MUTEX_CREATE: usize = 463;
+const SYSCALL_MUTEX_LOCK: usize = 464;
+const SYSCALL_MUTEX_UNLOCK: usize = 466;
+const SYSCALL_SEMAPHORE_CREATE: usize = 467;
+const SYSCALL_SEMAPHORE_UP: usize = 468;
+const SYSCALL_SEMAPHORE_DOWN: usize = 470;
+const SYSCALL_CONDVAR_CREATE: usize = 471;
+const SYSCALL_CONDVAR_SIGNAL: usize = 472;
+const SYSCALL_CONDVAR_WAIT: usize = 473;
+const SYSCALL_CONDVAR_BROADCAST: usize = 474;
 
+pub mod errno;
 mod fs;
 mod process;
+mod signal;
+mod strace;
+mod sync;
+mod thread;
 
-use crate::task;
+use crate::eventlog::Event;
+use crate::task::{self, SignalAction};
+use crate::trace::{self, TracePoint, TraceRecord};
 use fs::*;
 use process::*;
+use signal::*;
+use sync::*;
+use thread::*;
 
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     // LAB1: You may need to update syscall info here.
     //LAB1：您可能需要在此处更新系统调用信息。
-    task::update_syscall_times(syscall_id);
+    let traced = task::update_syscall_times(syscall_id);
+    let pid = task::current_pid();
+    trace::record(TracePoint::SyscallEnter, pid, [syscall_id, args[0]]);
+    let call = if traced {
+        Some(strace::decode(syscall_id, args))
+    } else {
+        None
+    };
+    if let (Some(call), SYSCALL_EXIT) = (&call, syscall_id) {
+        strace::print(pid, call, None);
+    }
 
-    match syscall_id {
+    let result = match syscall_id {
+        // openat: the dirfd in args[0] is ignored, there is only the root directory
+        SYSCALL_OPEN => sys_open(args[1] as *const u8, args[2] as u32),
+        SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
+        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2]),
+        SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_SCHED_SETSCHEDULER => sys_sched_setscheduler(args[0]),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_KILL => sys_kill(args[0], args[1]),
+        SYSCALL_SIGACTION => sys_sigaction(
+            args[0],
+            args[1] as *const SignalAction,
+            args[2] as *mut SignalAction,
+        ),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0] as u32),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
+        SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_GETTID => sys_gettid(),
         SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
+        SYSCALL_FORK => sys_fork(),
+        SYSCALL_EXEC => sys_exec(
+            args[0] as *const u8,
+            args[1] as *const usize,
+            args[2] as *const usize,
+        ),
+        SYSCALL_WAIT4 => sys_wait4(
+            args[0] as isize,
+            args[1] as *mut i32,
+            args[2],
+            args[3] as *mut Rusage,
+        ),
+        SYSCALL_SBRK => sys_sbrk(args[0] as isize),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
+        SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_MEMINFO => sys_meminfo(args[0] as *mut u8),
+        SYSCALL_MEM_STAT => sys_mem_stat(args[0] as *mut MemStat),
+        SYSCALL_GETPAGESIZE => sys_getpagesize(),
+        SYSCALL_READ_EVENTLOG => sys_read_eventlog(args[0] as *mut Event, args[1]),
+        SYSCALL_SET_BATCH => sys_set_batch(args[0] != 0),
+        SYSCALL_READ_TRACE => sys_read_trace(args[0] as *mut TraceRecord, args[1]),
+        SYSCALL_STRACE => sys_strace(args[0], args[1] != 0),
+        SYSCALL_TASK_STATS => sys_task_stats(args[0] as *mut TaskStats),
+        SYSCALL_MMAP_FILE => sys_mmap_file(args[0], args[1], args[2], args[3] as isize, args[4]),
+        SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
+        SYSCALL_WAITTID => sys_waittid(args[0]),
+        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] != 0),
+        SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
+        SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
+        SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
+        SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
+        SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
+        SYSCALL_CONDVAR_CREATE => sys_condvar_create(),
+        SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
+        SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_CONDVAR_BROADCAST => sys_condvar_broadcast(args[0]),
+        _ => {
+            warn!("[kernel] Unsupported syscall_id: {}", syscall_id);
+            -1
+        }
+    };
+    trace::record(TracePoint::SyscallExit, pid, [syscall_id, result as usize]);
+    if let Some(call) = call {
+        strace::print(pid, &call, Some(result));
     }
+    result
 }
\ No newline at end of file
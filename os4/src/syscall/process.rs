@@ -1,9 +1,16 @@
 //! Process management syscalls
 
-use crate::config::MAX_SYSCALL_NUM;
+use crate::config::{EVENT_LOG_LEN, MAX_APP_NAME_LEN, MAX_ARG_BYTES, MAX_HARTS, MAX_SYSCALL_NUM, MIN_PRIORITY, PAGE_SIZE, TRACE_LEN};
+use crate::loader::get_app_data_by_name;
 use crate::mm;
-use crate::task::{exit_current_and_run_next, suspend_current_and_run_next, TaskStatus, mmap, munmap, get_syscall_times, current_user_token, get_current_task_time};
+use crate::task::{exit_current_and_run_next, note_current_yield, sleep_current_and_run_next, suspend_current_and_run_next, TaskStatus, mmap, munmap, mprotect, sbrk, shmat, shmdt, current_user_token, populate_user_buffer, inspect_current_task, set_current_priority, set_current_batch, set_sched_policy, SchedPolicy, current_process_pid, parent_pid, wait_child, spawn, fork, exec, block_current_and_run_next, current_killed, args_size, set_strace};
+use crate::eventlog::{self, Event};
+use crate::trace::{self, TraceRecord};
 use crate::timer::get_time_us;
+use super::errno::{EEXIST, EINTR, EINVAL, ENOENT, ENOMEM, ESRCH};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -12,59 +19,492 @@ pub struct TimeVal {
     pub usec: usize,
 }
 
+#[repr(C)]
+#[derive(Debug)]
+pub struct MemInfo {
+    pub used_frames: usize,
+    pub total_frames: usize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct MemStat {
+    pub free_frames: usize,
+    /// frames owned by the current task's memory set, page tables included
+    pub task_frames: usize,
+    pub map_areas: usize,
+}
+
+/// what `sys_task_info` reports, the layout is fixed by the grader's user library
+#[repr(C)]
 #[derive(Clone, Copy)]
 pub struct TaskInfo {
     pub status: TaskStatus,
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// ms since the task was first scheduled
     pub time: usize,
 }
 
+/// time, memory and swap figures of the current task, filled in by `sys_task_stats`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TaskStats {
+    /// ms the task actually spent running, in user or kernel mode
+    pub cpu_time: usize,
+    /// times the task was preempted for spending too long in syscalls
+    pub churn_preemptions: usize,
+    /// writes that copied a shared page into a private frame
+    pub cow_copies: usize,
+    /// ms spent in user mode
+    pub user_time: usize,
+    /// ms spent in the kernel on behalf of the task
+    pub kernel_time: usize,
+    /// pages backed by a frame of their own right now
+    pub resident_pages: usize,
+    /// areas mapped in the address space, each mmap adds one
+    pub map_areas: usize,
+    /// the most pages that were ever resident at once
+    pub peak_resident_pages: usize,
+    /// pages sitting in swap right now
+    pub swapped_pages: usize,
+    /// pages written out to swap so far
+    pub swap_outs: usize,
+    /// pages read back from swap so far
+    pub swap_ins: usize,
+}
+
+/// resource usage of an exited task, filled in by `sys_wait4`
+#[repr(C)]
+#[derive(Debug)]
+pub struct Rusage {
+    /// time the task spent running, in user or kernel mode
+    pub cpu_time: TimeVal,
+    /// frames the task still held when it exited
+    pub frames: usize,
+    /// writes that copied a shared page into a private frame
+    pub cow_copies: usize,
+}
+
+/// user and kernel time of a task and of its children, filled in by `sys_times`
+#[repr(C)]
+#[derive(Debug)]
+pub struct Tms {
+    /// us the task spent in user mode
+    pub utime: usize,
+    /// us the task spent in the kernel
+    pub stime: usize,
+    /// us the waited-for children spent in user mode, their children included
+    pub cutime: usize,
+    /// us the waited-for children spent in the kernel, their children included
+    pub cstime: usize,
+}
+
+/// `sys_wait4` option: return 0 at once if the task has not exited yet
+pub const WNOHANG: usize = 1;
+
 pub fn sys_exit(exit_code: i32) -> ! {
     info!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
+    note_current_yield();
     suspend_current_and_run_next();
     0
 }
 
-// YOUR JOB: 引入虚地址后重写 sys_get_time
+/// 返回当前进程的 pid，也就是主线程的 pid，各个线程得到的都一样
+pub fn sys_getpid() -> isize {
+    current_process_pid() as isize
+}
+
+/// 返回父任务的 pid；启动时加载的应用和父任务已经退出的任务没有父任务，返回 -1
+pub fn sys_getppid() -> isize {
+    match parent_pid() {
+        Some(pid) => pid as isize,
+        None => -1,
+    }
+}
+
+/// 等待 `pid` 号子任务（为 -1 时任意子任务）退出并回收它，把退出码写入 `status`、
+/// 资源使用情况写入 `rusage`（为空则不写），返回子任务的编号。
+/// 没有符合条件的子任务返回 -ECHILD；带 WNOHANG 时子任务都还没退出立即返回 0
+pub fn sys_wait4(pid: isize, status: *mut i32, options: usize, rusage: *mut Rusage) -> isize {
+    if options & !WNOHANG != 0 {
+        return -EINVAL;
+    }
+    let (pid, (exit_code, usage)) = loop {
+        let exited = wait_child(pid, |task, process| {
+            let cpu_time = task.kernel_and_user_time;
+            let usage = Rusage {
+                cpu_time: TimeVal {
+                    sec: cpu_time / 1_000_000,
+                    usec: cpu_time % 1_000_000,
+                },
+                frames: process.memory_set.frame_count(),
+                cow_copies: process.memory_set.cow_copies(),
+            };
+            (task.exit_code, usage)
+        });
+        match exited {
+            Err(err) => return err,
+            Ok(Some(result)) => break result,
+            Ok(None) if options & WNOHANG != 0 => return 0,
+            Ok(None) if current_killed() => return -EINTR,
+            // the child's exit wakes us up
+            Ok(None) => block_current_and_run_next(),
+        }
+    };
+    let token = current_user_token();
+    if !status.is_null() {
+        populate_user_buffer(status as usize, size_of::<i32>(), mm::MapPermission::W);
+        if let Err(err) = mm::copy_to_user(token, status, &exit_code) {
+            return err;
+        }
+    }
+    if !rusage.is_null() {
+        populate_user_buffer(rusage as usize, size_of::<Rusage>(), mm::MapPermission::W);
+        if let Err(err) = mm::copy_to_user(token, rusage, &usage) {
+            return err;
+        }
+    }
+    pid as isize
+}
+
+/// 复制当前任务，父任务得到子任务的 id，子任务得到 0；
+/// 内存或交换区不够复制地址空间时返回 -1
+pub fn sys_fork() -> isize {
+    fork().map_or(-1, |pid| pid as isize)
+}
+
+/// 用名为 `path` 的应用替换当前任务的程序，成功后不会回到原来的程序。
+/// `args`、`envs` 是以空指针结尾的字符串指针数组，为空指针时当作空数组，
+/// 新程序的 main 从 a0-a2 拿到 argc、argv 和 envp，返回值就是 argc；
+/// 其他线程会先被杀死并等它们退出；找不到应用、读不出参数、参数超过
+/// `MAX_ARG_BYTES` 或者不是主线程调用返回 -1
+pub fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let token = current_user_token();
+    // the strings may sit on pages of the program never touched yet
+    populate_user_buffer(path as usize, MAX_APP_NAME_LEN + 1, mm::MapPermission::R);
+    let name = match mm::translated_str(token, path, MAX_APP_NAME_LEN) {
+        Some(name) => name,
+        None => return -1,
+    };
+    let (args, envs) = match (read_user_strs(token, args), read_user_strs(token, envs)) {
+        (Some(args), Some(envs)) => (args, envs),
+        _ => return -1,
+    };
+    if args_size(&args) + args_size(&envs) > MAX_ARG_BYTES {
+        return -1;
+    }
+    match get_app_data_by_name(&name) {
+        // the return value lands in a0, which holds argc now
+        Some(elf_data) if exec(elf_data, &args, &envs) => args.len() as isize,
+        _ => -1,
+    }
+}
+
+/// Read the strings of the null-terminated pointer array at `ptr` in user
+/// space, none if `ptr` is null. `None` if a pointer or string cannot be
+/// read or there are more than fit in `MAX_ARG_BYTES`.
+fn read_user_strs(token: usize, mut ptr: *const usize) -> Option<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Some(strs);
+    }
+    loop {
+        let mut str_ptr = 0usize;
+        populate_user_buffer(ptr as usize, size_of::<usize>(), mm::MapPermission::R);
+        mm::copy_from_user(token, ptr, &mut str_ptr).ok()?;
+        if str_ptr == 0 {
+            return Some(strs);
+        }
+        populate_user_buffer(str_ptr, MAX_ARG_BYTES + 1, mm::MapPermission::R);
+        strs.push(mm::translated_str(token, str_ptr as *const u8, MAX_ARG_BYTES)?);
+        if args_size(&strs) > MAX_ARG_BYTES {
+            return None;
+        }
+        ptr = ptr.wrapping_add(1);
+    }
+}
+
+/// 让当前任务睡眠至少 `ms` 毫秒，期间不占用 CPU
+pub fn sys_sleep(ms: usize) -> isize {
+    let wakeup_time = get_time_us().saturating_add(ms.saturating_mul(1000));
+    // a child exiting wakes us up early, go back to sleep for the rest
+    while get_time_us() < wakeup_time {
+        if current_killed() {
+            return -EINTR;
+        }
+        sleep_current_and_run_next(wakeup_time);
+    }
+    0
+}
+
+/// 获取当前时间。`ts` 不要求按 `TimeVal` 对齐：结果按字节写入用户空间，
+/// 只有 `ts` 覆盖的页未映射或不可写时才返回 -1
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     let us = get_time_us();
-    let ts_phy_ptr = mm::get_refmut(current_user_token(), ts);
-    *ts_phy_ptr = TimeVal {
+    let time_val = TimeVal {
         sec: us / 1_000_000,
         usec: us % 1_000_000,
     };
+    populate_user_buffer(ts as usize, size_of::<TimeVal>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), ts, &time_val) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
 
+/// 设置当前任务的 stride 优先级并返回它，小于 2 的优先级返回 -1；
+/// 再大的优先级 stride 也至少为 1，不会让任务一直占着 CPU
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio < MIN_PRIORITY as isize {
+        return -1;
+    }
+    set_current_priority(prio as usize);
+    prio
+}
+
+/// 按名字启动一个新的应用，它的 argv 只有应用名，返回新任务的 id；名字读不出来或找不到应用返回 -1
+pub fn sys_spawn(path: *const u8) -> isize {
+    populate_user_buffer(path as usize, MAX_APP_NAME_LEN + 1, mm::MapPermission::R);
+    let name = match mm::translated_str(current_user_token(), path, MAX_APP_NAME_LEN) {
+        Some(name) => name,
+        None => return -1,
+    };
+    match get_app_data_by_name(&name) {
+        Some(elf_data) => spawn(elf_data, &[name]) as isize,
+        None => -1,
+    }
+}
+
+/// 批处理模式下任务不会被时钟中断抢占，只能主动让出，进入批处理模式后
+/// 再用满 `BATCH_CPU_LIMIT_US` 的 CPU 时间会被杀死
+pub fn sys_set_batch(batch: bool) -> isize {
+    set_current_batch(batch);
     0
 }
 
-// CLUE: 从 ch4 开始不再对调度算法进行测试~
-pub fn sys_set_priority(_prio: isize) -> isize {
-    -1
+/// 返回页大小，与 mmap 对齐检查使用的是同一个 `PAGE_SIZE`
+pub fn sys_getpagesize() -> isize {
+    PAGE_SIZE as isize
+}
+
+/// 取出最早的内核事件写入 `buf`，`len` 为最多能放下的记录条数，返回实际写入的条数
+pub fn sys_read_eventlog(buf: *mut Event, len: usize) -> isize {
+    let mut events = [Event::empty(); EVENT_LOG_LEN];
+    let len = len.min(EVENT_LOG_LEN);
+    let token = current_user_token();
+    populate_user_buffer(buf as usize, len * size_of::<Event>(), mm::MapPermission::W);
+    // check the whole buffer before draining, so no event is lost to a bad pointer
+    if !mm::user_buffer_writable(token, buf as *const u8, len * size_of::<Event>()) {
+        return -1;
+    }
+    let count = eventlog::drain(&mut events[..len]);
+    let dst = core::ptr::slice_from_raw_parts_mut(buf, count);
+    match mm::copy_to_user(token, dst, &events[..count]) {
+        Ok(()) => count as isize,
+        Err(err) => err,
+    }
+}
+
+/// 取出各 hart 最早的跟踪记录写入 `buf`，`len` 为最多能放下的记录条数，返回实际写入的条数
+pub fn sys_read_trace(buf: *mut TraceRecord, len: usize) -> isize {
+    let len = len.min(TRACE_LEN * MAX_HARTS);
+    let token = current_user_token();
+    populate_user_buffer(buf as usize, len * size_of::<TraceRecord>(), mm::MapPermission::W);
+    // check the whole buffer before draining, so no record is lost to a bad pointer
+    if !mm::user_buffer_writable(token, buf as *const u8, len * size_of::<TraceRecord>()) {
+        return -1;
+    }
+    let mut records = alloc::vec![TraceRecord::empty(); len];
+    let count = trace::drain(&mut records);
+    let dst = core::ptr::slice_from_raw_parts_mut(buf, count);
+    match mm::copy_to_user(token, dst, &records[..count]) {
+        Ok(()) => count as isize,
+        Err(err) => err,
+    }
+}
+
+/// 打开（`enable` 为真）或关闭任务 `pid` 的系统调用跟踪，每个系统调用的名字、参数和返回值
+/// 打印到控制台；没有这个任务返回 -ESRCH
+pub fn sys_strace(pid: usize, enable: bool) -> isize {
+    if set_strace(pid, enable) {
+        0
+    } else {
+        -ESRCH
+    }
+}
+
+/// 切换所有任务的调度策略：0 为轮转，1 为 stride，2 为多级反馈队列，其他值返回 -EINVAL
+pub fn sys_sched_setscheduler(policy: usize) -> isize {
+    match SchedPolicy::from_raw(policy) {
+        Some(policy) => {
+            set_sched_policy(policy);
+            0
+        }
+        None => -EINVAL,
+    }
 }
 
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
+/// 匿名映射，只用三个参数，与测例的 mmap 一致；测例只认 -1，出错一律返回 -1。
+/// 需要区分错误码时用 `sys_mmap_file`（419 号），`fd` 传 -1
 pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
-    mmap(start, len, port)
+    match mmap(start, len, port, -1, 0) {
+        ret if ret < 0 => -1,
+        ret => ret,
+    }
+}
+
+/// 带 `fd` 和 `offset` 的 mmap，`fd` 为 -1 时是匿名映射，否则映射打开的文件 `fd`，
+/// 页面在第一次访问时从文件读入；MAP_SHARED 的映射在解除映射、exit 或 exec 时写回文件。
+/// 出错返回对应的错误码
+pub fn sys_mmap_file(start: usize, len: usize, port: usize, fd: isize, offset: usize) -> isize {
+    mmap(start, len, port, fd, offset)
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     munmap(start, len)
 }
 
+/// 按 `key` 找到或创建至少 `size` 字节的共享内存段，返回段号。`flags` 含
+/// IPC_CREAT 时找不到就创建，再含 IPC_EXCL 时已经存在返回 -EEXIST；找不到又
+/// 不创建返回 -ENOENT，`size` 为 0 或比已有的段大返回 -EINVAL，物理页不够
+/// 返回 -ENOMEM。`key` 为 IPC_PRIVATE 时总是创建新段
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    let pages = match size.checked_add(PAGE_SIZE - 1) {
+        Some(size) if size >= PAGE_SIZE => size / PAGE_SIZE,
+        _ => return -EINVAL,
+    };
+    match mm::shm_lookup(key) {
+        Some(_) if flags & mm::IPC_CREAT != 0 && flags & mm::IPC_EXCL != 0 => -EEXIST,
+        Some((_, existing)) if existing < pages => -EINVAL,
+        Some((id, _)) => id as isize,
+        None if flags & mm::IPC_CREAT == 0 && key != mm::IPC_PRIVATE => -ENOENT,
+        None => match mm::shm_create(key, pages) {
+            Some(id) => id as isize,
+            None => -ENOMEM,
+        },
+    }
+}
+
+/// 把共享内存段 `id` 挂到 `addr` 处（为 0 时由内核选址），返回挂上的地址；
+/// `flags` 含 SHM_RDONLY 时只读
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    shmat(id, addr, flags & mm::SHM_RDONLY != 0)
+}
+
+/// 卸下挂在 `addr` 处的共享内存段
+pub fn sys_shmdt(addr: usize) -> isize {
+    shmdt(addr)
+}
+
+/// 修改已映射区域的权限，`port` 的格式与 mmap 相同
+pub fn sys_mprotect(start: usize, len: usize, port: usize) -> isize {
+    mprotect(start, len, port)
+}
+
+/// 调整程序堆的大小，返回原来的 program break，increment 为 0 时只查询。
+/// 新增的堆页在第一次访问时才分配物理页
+pub fn sys_sbrk(increment: isize) -> isize {
+    sbrk(increment)
+}
+
+/// 获取物理页帧的使用情况，`buf` 可以跨页，不可写时返回 -1
+pub fn sys_meminfo(buf: *mut u8) -> isize {
+    let stats = mm::frame_stats();
+    let mem_info = MemInfo {
+        used_frames: stats.allocated,
+        total_frames: stats.total,
+    };
+    populate_user_buffer(buf as usize, size_of::<MemInfo>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), buf as *mut MemInfo, &mem_info) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// 获取空闲页帧数、当前任务占用的页帧数和映射区域数
+pub fn sys_mem_stat(buf: *mut MemStat) -> isize {
+    let free_frames = mm::frame_stats().free;
+    let mem_stat = inspect_current_task(|_, process| MemStat {
+        free_frames,
+        task_frames: process.memory_set.frame_count(),
+        map_areas: process.memory_set.area_count(),
+    });
+    populate_user_buffer(buf as usize, size_of::<MemStat>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), buf, &mem_stat) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
 // YOUR JOB: 引入虚地址后重写 sys_task_info
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    let ti_phy_ptr = mm::get_refmut(current_user_token(), ti);
-    *ti_phy_ptr = TaskInfo {
-        status: TaskStatus::Running,
-        syscall_times: get_syscall_times(),
-        time: get_current_task_time(),
-    };
+    // everything comes from one borrow of the task and one clock reading
+    let task_info = inspect_current_task(|task, _| TaskInfo {
+        status: task.task_status,
+        syscall_times: task.syscall_times_array(),
+        time: (get_time_us() - task.start_time) / 1000,
+    });
+    populate_user_buffer(ti as usize, size_of::<TaskInfo>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), ti, &task_info) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
 
-    0
-}
\ No newline at end of file
+/// 把当前任务的运行时间、内存与换页统计写入 `stats`，成功返回 0
+pub fn sys_task_stats(stats: *mut TaskStats) -> isize {
+    let task_stats = inspect_current_task(|task, process| {
+        let now = get_time_us();
+        TaskStats {
+            cpu_time: task.cpu_time_us(now) / 1000,
+            churn_preemptions: task.churn_preemptions,
+            cow_copies: process.memory_set.cow_copies(),
+            user_time: task.user_time_us / 1000,
+            // we are in the kernel right now, count this stretch too
+            kernel_time: (task.kernel_time_us + (now - task.mode_switched_at)) / 1000,
+            resident_pages: process.memory_set.resident_pages(),
+            map_areas: process.memory_set.area_count(),
+            peak_resident_pages: process.memory_set.peak_resident_pages(),
+            swapped_pages: process.memory_set.swapped_pages(),
+            swap_outs: process.memory_set.swap_outs(),
+            swap_ins: process.memory_set.swap_ins(),
+        }
+    });
+    populate_user_buffer(stats as usize, size_of::<TaskStats>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), stats, &task_stats) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// 把当前任务和已回收的子任务在用户态、内核态花的时间（微秒）写入 `tms`，成功返回 0
+pub fn sys_times(tms: *mut Tms) -> isize {
+    let times = inspect_current_task(|task, _| Tms {
+        utime: task.user_time_us,
+        stime: task.kernel_time_us + (get_time_us() - task.mode_switched_at),
+        cutime: task.children_user_time_us,
+        cstime: task.children_kernel_time_us,
+    });
+    populate_user_buffer(tms as usize, size_of::<Tms>(), mm::MapPermission::W);
+    match mm::copy_to_user(current_user_token(), tms, &times) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// the page size handed to user space must be the one mmap aligns to
+pub fn getpagesize_test() {
+    let page_size = sys_getpagesize();
+    assert_eq!(page_size as usize, PAGE_SIZE);
+    assert!(page_size > 0 && (page_size as usize).is_power_of_two());
+    info!("getpagesize_test passed!");
+}
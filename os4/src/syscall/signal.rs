@@ -0,0 +1,73 @@
+//! Signal-related syscalls
+
+use super::errno::{EINVAL, ESRCH};
+use crate::mm::{self, MapPermission};
+use crate::task::{
+    current_trap_cx, current_user_token, populate_user_buffer, send_signal, set_signal_action,
+    set_signal_mask, sigreturn, SignalAction, SignalFlags,
+};
+use core::mem::size_of;
+
+/// 向 `pid` 号任务发送 `signum` 号信号；没有这个任务或它已经退出返回 -ESRCH，
+/// 信号编号不合法返回 -EINVAL
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    if SignalFlags::from_signum(signum).is_none() {
+        return -EINVAL;
+    }
+    if send_signal(pid, signum) {
+        0
+    } else {
+        -ESRCH
+    }
+}
+
+/// 把 `signum` 号信号的处理方式设为 `action`（为空则不改），原来的处理方式写入
+/// `old_action`（为空则不写）。SIGKILL、SIGSTOP 和不合法的编号返回 -EINVAL，
+/// 指针不可访问返回 -1
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    match SignalFlags::from_signum(signum) {
+        Some(sig) if !SignalFlags::fixed().contains(sig) => {}
+        _ => return -EINVAL,
+    }
+    let token = current_user_token();
+    let new_action = if action.is_null() {
+        None
+    } else {
+        populate_user_buffer(action as usize, size_of::<SignalAction>(), MapPermission::R);
+        let mut new_action = SignalAction::DEFAULT;
+        if let Err(err) = mm::copy_from_user(token, action, &mut new_action) {
+            return err;
+        }
+        // the mask is user data, drop bits that are no signal
+        new_action.mask = SignalFlags::from_bits_truncate(new_action.mask.bits());
+        Some(new_action)
+    };
+    if !old_action.is_null() {
+        populate_user_buffer(old_action as usize, size_of::<SignalAction>(), MapPermission::W);
+        let old = set_signal_action(signum, None);
+        if let Err(err) = mm::copy_to_user(token, old_action, &old) {
+            return err;
+        }
+    }
+    set_signal_action(signum, new_action);
+    0
+}
+
+/// 把当前任务屏蔽的信号设为 `mask`，返回原来的屏蔽集合；SIGKILL 和 SIGSTOP 无法屏蔽
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    set_signal_mask(SignalFlags::from_bits_truncate(mask)).bits() as isize
+}
+
+/// 从信号处理函数返回，恢复被打断时的上下文和屏蔽集合；不在处理函数中返回 -1
+pub fn sys_sigreturn() -> isize {
+    if sigreturn() {
+        // the trap handler writes the return value into a0, keep the restored one
+        current_trap_cx().x[10] as isize
+    } else {
+        -1
+    }
+}
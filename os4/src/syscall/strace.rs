@@ -0,0 +1,142 @@
+//! Decoding syscalls for `sys_strace`
+//!
+//! Each syscall of a traced task is logged as one line, its name and
+//! arguments as far as their types are known, then what it returned. The
+//! arguments are decoded on entry, exec replaces the strings they point to,
+//! and the line is printed on return.
+
+use super::errno::{EAGAIN, EBADF, ECHILD, EEXIST, EINTR, EINVAL, ENOENT, ENOMEM, EPERM, ESRCH};
+use super::*;
+use crate::config::MAX_PATH_LEN;
+use crate::mm::translated_str;
+use crate::task::current_user_token;
+use alloc::format;
+use alloc::string::String;
+
+/// how an argument is shown
+#[derive(Copy, Clone)]
+enum Arg {
+    /// signed decimal: counts, descriptors, ids
+    Int,
+    /// hex: addresses and flags
+    Hex,
+    /// a path or app name in the task's memory, hex if it cannot be read
+    Str,
+}
+
+use Arg::{Hex, Int, Str};
+
+/// The name and argument types of syscall `id`.
+fn signature(id: usize) -> Option<(&'static str, &'static [Arg])> {
+    let signature: (&'static str, &'static [Arg]) = match id {
+        SYSCALL_OPEN => ("openat", &[Int, Str, Hex]),
+        SYSCALL_CLOSE => ("close", &[Int]),
+        SYSCALL_PIPE => ("pipe", &[Hex]),
+        SYSCALL_READ => ("read", &[Int, Hex, Int]),
+        SYSCALL_WRITE => ("write", &[Int, Hex, Int]),
+        SYSCALL_EXIT => ("exit", &[Int]),
+        SYSCALL_FUTEX => ("futex", &[Hex, Int, Int]),
+        SYSCALL_SLEEP => ("sleep", &[Int]),
+        SYSCALL_SCHED_SETSCHEDULER => ("sched_setscheduler", &[Int]),
+        SYSCALL_YIELD => ("sched_yield", &[]),
+        SYSCALL_KILL => ("kill", &[Int, Int]),
+        SYSCALL_SIGACTION => ("sigaction", &[Int, Hex, Hex]),
+        SYSCALL_SIGPROCMASK => ("sigprocmask", &[Hex]),
+        SYSCALL_SIGRETURN => ("sigreturn", &[]),
+        SYSCALL_TIMES => ("times", &[Hex]),
+        SYSCALL_GET_TIME => ("gettimeofday", &[Hex, Int]),
+        SYSCALL_GETPID => ("getpid", &[]),
+        SYSCALL_GETPPID => ("getppid", &[]),
+        SYSCALL_GETTID => ("gettid", &[]),
+        SYSCALL_SHMGET => ("shmget", &[Int, Int, Hex]),
+        SYSCALL_SHMAT => ("shmat", &[Int, Hex, Hex]),
+        SYSCALL_SHMDT => ("shmdt", &[Hex]),
+        SYSCALL_SBRK => ("sbrk", &[Int]),
+        SYSCALL_MUNMAP => ("munmap", &[Hex, Int]),
+        SYSCALL_FORK => ("fork", &[]),
+        SYSCALL_EXEC => ("exec", &[Str, Hex, Hex]),
+        SYSCALL_MMAP => ("mmap", &[Hex, Int, Hex]),
+        SYSCALL_MPROTECT => ("mprotect", &[Hex, Int, Hex]),
+        SYSCALL_WAIT4 => ("wait4", &[Int, Hex, Hex, Hex]),
+        SYSCALL_SET_PRIORITY => ("set_priority", &[Int]),
+        SYSCALL_SPAWN => ("spawn", &[Str]),
+        SYSCALL_TASK_INFO => ("task_info", &[Hex]),
+        SYSCALL_MEMINFO => ("meminfo", &[Hex]),
+        SYSCALL_MEM_STAT => ("mem_stat", &[Hex]),
+        SYSCALL_GETPAGESIZE => ("getpagesize", &[]),
+        SYSCALL_READ_EVENTLOG => ("read_eventlog", &[Hex, Int]),
+        SYSCALL_SET_BATCH => ("set_batch", &[Int]),
+        SYSCALL_READ_TRACE => ("read_trace", &[Hex, Int]),
+        SYSCALL_STRACE => ("strace", &[Int, Int]),
+        SYSCALL_TASK_STATS => ("task_stats", &[Hex]),
+        SYSCALL_MMAP_FILE => ("mmap_file", &[Hex, Int, Hex, Int, Int]),
+        SYSCALL_THREAD_CREATE => ("thread_create", &[Hex, Hex]),
+        SYSCALL_WAITTID => ("waittid", &[Int]),
+        SYSCALL_MUTEX_CREATE => ("mutex_create", &[Int]),
+        SYSCALL_MUTEX_LOCK => ("mutex_lock", &[Int]),
+        SYSCALL_MUTEX_UNLOCK => ("mutex_unlock", &[Int]),
+        SYSCALL_SEMAPHORE_CREATE => ("semaphore_create", &[Int]),
+        SYSCALL_SEMAPHORE_UP => ("semaphore_up", &[Int]),
+        SYSCALL_SEMAPHORE_DOWN => ("semaphore_down", &[Int]),
+        SYSCALL_CONDVAR_CREATE => ("condvar_create", &[]),
+        SYSCALL_CONDVAR_SIGNAL => ("condvar_signal", &[Int]),
+        SYSCALL_CONDVAR_WAIT => ("condvar_wait", &[Int, Int]),
+        SYSCALL_CONDVAR_BROADCAST => ("condvar_broadcast", &[Int]),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+fn decode_arg(arg: Arg, value: usize) -> String {
+    match arg {
+        Int => format!("{}", value as isize),
+        Hex => format!("{:#x}", value),
+        Str => match translated_str(current_user_token(), value as *const u8, MAX_PATH_LEN) {
+            Some(s) => format!("{:?}", s),
+            None => format!("{:#x}", value),
+        },
+    }
+}
+
+/// `name(arg, ...)` for syscall `id` with `args`, an unknown id shows as
+/// `syscall_<id>` with all six arguments in hex.
+pub fn decode(id: usize, args: [usize; 6]) -> String {
+    let (name, types) = match signature(id) {
+        Some((name, types)) => (String::from(name), types),
+        None => (format!("syscall_{}", id), &[Hex; 6][..]),
+    };
+    let args: alloc::vec::Vec<String> = types
+        .iter()
+        .zip(args.iter())
+        .map(|(arg, value)| decode_arg(*arg, *value))
+        .collect();
+    format!("{}({})", name, args.join(", "))
+}
+
+fn errno_name(result: isize) -> Option<&'static str> {
+    Some(match result.wrapping_neg() {
+        EPERM => "EPERM",
+        ENOENT => "ENOENT",
+        ESRCH => "ESRCH",
+        EINTR => "EINTR",
+        EBADF => "EBADF",
+        ECHILD => "ECHILD",
+        EAGAIN => "EAGAIN",
+        ENOMEM => "ENOMEM",
+        EEXIST => "EEXIST",
+        EINVAL => "EINVAL",
+        _ => return None,
+    })
+}
+
+/// Log that task `pid` made the syscall `call`, which returned `result`, or
+/// does not return when `None`.
+pub fn print(pid: usize, call: &str, result: Option<isize>) {
+    match result {
+        None => println!("[strace] task {}: {} = ?", pid, call),
+        Some(result) => match errno_name(result) {
+            Some(name) => println!("[strace] task {}: {} = {} {}", pid, call, result, name),
+            None => println!("[strace] task {}: {} = {}", pid, call, result),
+        },
+    }
+}
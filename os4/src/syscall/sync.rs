@@ -0,0 +1,149 @@
+//! Synchronization syscalls
+
+use super::errno::{EINTR, EINVAL, EPERM};
+use crate::config::PAGE_SIZE;
+use crate::sync::{futex_wait, futex_wake};
+use crate::task::{
+    create_condvar, create_mutex, create_semaphore, current_condvar, current_mutex, current_pid,
+    current_semaphore, current_user_frame,
+};
+use core::mem::size_of;
+
+/// futex op: wait while the word holds the value
+const FUTEX_WAIT: usize = 0;
+/// futex op: wake up to that many waiters
+const FUTEX_WAKE: usize = 1;
+/// futex op flag: the word is not shared with other processes, ignored since
+/// the queues are keyed by physical address anyway
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// 创建一个初始有 `res_count` 个资源的信号量，返回它的编号；
+/// 之后 fork 出的子进程共享这个信号量，exec 后全部失效
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    create_semaphore(res_count) as isize
+}
+
+/// 释放信号量 `sem_id` 的一个资源，有任务在等待时交给等得最久的那个；
+/// 没有这个信号量返回 -EINVAL
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    match current_semaphore(sem_id) {
+        Some(sem) => {
+            sem.up();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// 获取信号量 `sem_id` 的一个资源，没有资源时阻塞直到 up 把资源交给自己；
+/// 没有这个信号量返回 -EINVAL，等待时被杀死返回 -EINTR
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    let sem = match current_semaphore(sem_id) {
+        Some(sem) => sem,
+        None => return -EINVAL,
+    };
+    if sem.down() {
+        0
+    } else {
+        -EINTR
+    }
+}
+
+/// 创建一个互斥锁，返回它的编号；`blocking` 为真时等待的任务阻塞并按先来后到获得锁，
+/// 否则等待的任务不断让出 CPU 重试。任务退出或 exec 时仍持有的锁会被释放
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    create_mutex(blocking) as isize
+}
+
+/// 获取互斥锁 `mutex_id`，被其他任务持有时等待；
+/// 没有这个锁返回 -EINVAL，等待时被杀死返回 -EINTR
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    let mutex = match current_mutex(mutex_id) {
+        Some(mutex) => mutex,
+        None => return -EINVAL,
+    };
+    if mutex.lock() {
+        0
+    } else {
+        -EINTR
+    }
+}
+
+/// 释放互斥锁 `mutex_id`，有任务在等待时交给等得最久的那个；
+/// 没有这个锁返回 -EINVAL，锁不在当前任务手里返回 -EPERM
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    match current_mutex(mutex_id) {
+        Some(mutex) if mutex.release(current_pid()) => 0,
+        Some(_) => -EPERM,
+        None => -EINVAL,
+    }
+}
+
+/// 创建一个条件变量，返回它的编号
+pub fn sys_condvar_create() -> isize {
+    create_condvar() as isize
+}
+
+/// 唤醒在条件变量 `condvar_id` 上等得最久的一个任务，没有任务等待时什么也不做；
+/// 没有这个条件变量返回 -EINVAL
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    match current_condvar(condvar_id) {
+        Some(condvar) => {
+            condvar.signal();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// 唤醒在条件变量 `condvar_id` 上等待的所有任务；没有这个条件变量返回 -EINVAL
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    match current_condvar(condvar_id) {
+        Some(condvar) => {
+            condvar.broadcast();
+            0
+        }
+        None => -EINVAL,
+    }
+}
+
+/// 释放互斥锁 `mutex_id` 并在条件变量 `condvar_id` 上阻塞，两步之间不会错过唤醒；
+/// 被唤醒后重新获取互斥锁再返回。没有这个条件变量或互斥锁返回 -EINVAL，
+/// 当前任务没有持有互斥锁返回 -EPERM，等待时被杀死返回 -EINTR
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let (condvar, mutex) = match (current_condvar(condvar_id), current_mutex(mutex_id)) {
+        (Some(condvar), Some(mutex)) => (condvar, mutex),
+        _ => return -EINVAL,
+    };
+    match condvar.wait(mutex.as_ref()) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+/// 对 `addr` 处的 32 位字做 futex 操作。FUTEX_WAIT：这个字仍等于 `val` 时阻塞，
+/// 直到 FUTEX_WAKE 唤醒，返回 0，不等于 `val` 返回 -EAGAIN，等待时被杀死返回 -EINTR；
+/// FUTEX_WAKE：唤醒最多 `val` 个等在这个字上的任务，返回唤醒的个数。
+/// 等待队列按物理地址区分，共享内存的任务用各自的虚拟地址也能互相唤醒。
+/// `addr` 没有 4 字节对齐或操作不支持返回 -EINVAL，`addr` 不可访问返回 -1
+pub fn sys_futex(addr: usize, op: usize, val: usize) -> isize {
+    if addr % size_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let op = op & !FUTEX_PRIVATE_FLAG;
+    if op != FUTEX_WAIT && op != FUTEX_WAKE {
+        return -EINVAL;
+    }
+    let frame = match current_user_frame(addr) {
+        Some(frame) => frame,
+        None => return -1,
+    };
+    let offset = addr % PAGE_SIZE;
+    if op == FUTEX_WAKE {
+        return futex_wake(&frame, offset, val) as isize;
+    }
+    match futex_wait(frame, offset, val as u32) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
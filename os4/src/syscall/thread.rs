@@ -0,0 +1,20 @@
+//! Thread syscalls
+
+use crate::task::{current_tid, thread_create, waittid};
+
+/// 在当前进程中创建一个从 `entry` 开始执行的线程，a0 中是 `arg`，返回它的线程号；
+/// 新线程有自己的用户栈和内核栈，和其他线程共享地址空间；放不下它的栈返回 -ENOMEM
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    thread_create(entry, arg)
+}
+
+/// 返回当前线程在进程中的线程号，主线程为 0
+pub fn sys_gettid() -> isize {
+    current_tid() as isize
+}
+
+/// 回收当前进程中已退出的 `tid` 号线程并返回它的退出码；
+/// 线程不存在或是当前线程自己返回 -1，线程还没退出返回 -2
+pub fn sys_waittid(tid: usize) -> isize {
+    waittid(tid)
+}
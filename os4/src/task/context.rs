@@ -1,5 +1,6 @@
 //! Implementation of [`TaskContext`]
 use crate::trap::trap_return;
+use core::fmt::{self, Debug, Formatter};
 
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -25,4 +26,21 @@ impl TaskContext {
             s: [0; 12],
         }
     }
+    /// where `__switch` returns to when switching to this context
+    pub fn ra(&self) -> usize {
+        self.ra
+    }
+    /// the kernel stack pointer `__switch` restores
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+}
+
+impl Debug for TaskContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "TaskContext {{ ra: {:#x}, sp: {:#x}, s: {:x?} }}",
+            self.ra, self.sp, self.s
+        ))
+    }
 }
\ No newline at end of file
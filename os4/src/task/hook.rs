@@ -0,0 +1,76 @@
+//! One-shot hooks run right before a task first enters user mode
+
+const MAX_PENDING_HOOKS: usize = 16;
+
+/// Actions that can be armed for the first dispatch of a task.
+///
+/// Only plain data is stored so arming a hook never allocates.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hook {
+    /// log the task id together with the time of its first dispatch
+    Trace,
+    /// replace the stride priority of the task before it ever runs
+    SetPriority(usize),
+}
+
+/// fixed-size table of hooks waiting for their task to be dispatched
+pub struct HookRegistry {
+    pending: [Option<(usize, Hook)>; MAX_PENDING_HOOKS],
+}
+
+impl HookRegistry {
+    pub fn new() -> Self {
+        Self {
+            pending: [None; MAX_PENDING_HOOKS],
+        }
+    }
+    /// Arm `hook` for task `id`, returns false if the table is full.
+    pub fn register(&mut self, id: usize, hook: Hook) -> bool {
+        match self.pending.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((id, hook));
+                true
+            }
+            None => false,
+        }
+    }
+    /// Remove every hook armed for task `id` and pass it to `f`.
+    pub fn take(&mut self, id: usize, mut f: impl FnMut(Hook)) {
+        for slot in self.pending.iter_mut() {
+            if let Some((owner, hook)) = *slot {
+                if owner == id {
+                    *slot = None;
+                    f(hook);
+                }
+            }
+        }
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// hooks fire once for their own task and free their slot
+pub fn hook_registry_test() {
+    let mut registry = HookRegistry::new();
+    assert!(registry.register(1, Hook::Trace));
+    assert!(registry.register(2, Hook::SetPriority(4)));
+    assert!(registry.register(1, Hook::SetPriority(8)));
+    let mut fired = 0;
+    registry.take(1, |hook| {
+        assert!(hook == Hook::Trace || hook == Hook::SetPriority(8));
+        fired += 1;
+    });
+    assert_eq!(fired, 2);
+    registry.take(1, |_| panic!("hook fired twice"));
+    let mut fired = 0;
+    registry.take(2, |hook| {
+        assert_eq!(hook, Hook::SetPriority(4));
+        fired += 1;
+    });
+    assert_eq!(fired, 1);
+    for id in 0..MAX_PENDING_HOOKS {
+        assert!(registry.register(id, Hook::Trace));
+    }
+    assert!(!registry.register(0, Hook::Trace));
+    info!("hook_registry_test passed!");
+}
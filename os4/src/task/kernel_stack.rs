@@ -0,0 +1,85 @@
+//! Implementation of [`KernelStack`]
+
+use super::{pid_alloc, PidHandle};
+use crate::config::kernel_stack_position;
+use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use core::mem::{align_of, size_of};
+
+/// the kernel stack of one task, mapped in the kernel space for as long as it lives
+pub struct KernelStack {
+    pid: usize,
+    /// lowest address pushed so far, `top()` while nothing was pushed
+    sp: usize,
+}
+
+impl KernelStack {
+    /// Map the kernel stack of the task holding `pid_handle`.
+    pub fn new(pid_handle: &PidHandle) -> Self {
+        let pid = pid_handle.0;
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
+        KERNEL_SPACE.lock().insert_framed_area(
+            kernel_stack_bottom.into(),
+            kernel_stack_top.into(),
+            MapPermission::R | MapPermission::W,
+        );
+        Self {
+            pid,
+            sp: kernel_stack_top,
+        }
+    }
+    /// The address right above the stack.
+    pub fn top(&self) -> usize {
+        kernel_stack_position(self.pid).1
+    }
+    /// The stack pointer after everything pushed so far.
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+    /// Push `value` below everything pushed so far, aligned for `T`, and
+    /// return where it went.
+    pub fn push<T>(&mut self, value: T) -> *mut T {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        let sp = (self.sp - size_of::<T>()) & !(align_of::<T>() - 1);
+        assert!(sp >= kernel_stack_bottom, "kernel stack of task {} overflows", self.pid);
+        let ptr = sp as *mut T;
+        unsafe {
+            ptr.write(value);
+        }
+        self.sp = sp;
+        ptr
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .lock()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// pushing must move the stack pointer down by the size of the value
+pub fn kernel_stack_test() {
+    // a pid of its own, so the stack belongs to nobody else
+    let pid = pid_alloc();
+    let mut kernel_stack = KernelStack::new(&pid);
+    unsafe {
+        core::arch::asm!("sfence.vma");
+    }
+    let top = kernel_stack.top();
+    assert_eq!(kernel_stack.sp(), top);
+    let word = kernel_stack.push(0xdead_beef_usize);
+    assert_eq!(kernel_stack.sp(), top - size_of::<usize>());
+    assert_eq!(word as usize, kernel_stack.sp());
+    let pair = kernel_stack.push([1u32, 2u32]);
+    assert_eq!(kernel_stack.sp(), top - size_of::<usize>() - size_of::<[u32; 2]>());
+    unsafe {
+        assert_eq!(*word, 0xdead_beef);
+        assert_eq!(*pair, [1, 2]);
+    }
+    info!("kernel_stack_test passed!");
+}
@@ -5,42 +5,103 @@
 //看到[`__switch`]时要小心。围绕此函数的控制流可能不是您所期望的。
 
 mod context;
+mod hook;
+mod kernel_stack;
+mod pid;
+mod process;
+mod processor;
+mod scheduler;
+mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
 use crate::config;
-use crate::loader::{get_app_data, get_num_app};
-use crate::mm;
-use crate::sync::UPSafeCell;
+use crate::eventlog::{self, EventKind};
+use crate::fs::File;
+use crate::loader::{get_app_data, get_app_name, get_num_app};
+use crate::mm::{self, flush_tlb};
+use crate::smp;
+use crate::sync::{
+    Condvar, InterruptGuard, Mutex, MutexBlocking, MutexSpin, Semaphore, SpinNoIrqLock,
+};
+use crate::syscall::errno::{EACCES, EBADF, ECHILD, EEXIST, EINVAL, ENODEV, ENOMEM};
 use crate::timer;
+use crate::trace::{self, TracePoint};
 use crate::trap::TrapContext;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
+use hook::{Hook, HookRegistry};
 pub use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
+use alloc::boxed::Box;
+use processor::PROCESSORS;
+use scheduler::{new_scheduler, Scheduler, BOOT_POLICY};
+use task::{charge_mmap_quota, release_mutexes, tick_action};
+pub use task::{
+    args_size, SchedPolicy, TaskControlBlock, TaskControlBlockInner, TaskStatus, TickAction,
+};
 
 pub use context::TaskContext;
+pub use kernel_stack::KernelStack;
+pub use pid::{pid_alloc, PidHandle};
+pub use process::{ProcessControlBlock, ProcessControlBlockInner};
+pub use signal::{SignalAction, SignalDelivery, SignalFlags, SignalState};
 
 //任务管理器，用于管理所有任务。
 //在“TaskManager”上实现的函数处理所有任务状态转换和任务上下文切换。
 //为了方便起见，您可以在模块级别中找到围绕它的包装器。
-//大多数`TaskManager`都隐藏在“内部”字段后面，以将借用检查推迟到运行时。
+//大多数`TaskManager`都隐藏在“内部”字段后面，由自旋锁让各个 hart 互斥访问。
 //您可以在`TaskManager`上的现有函数中看到如何使用`inner`的示例。
 
 pub struct TaskManager {
-    /// 任务总数
-    num_app: usize,
     /// 使用内部值获取可变访问
-    inner: UPSafeCell<TaskManagerInner>,
+    inner: SpinNoIrqLock<TaskManagerInner>,
 }
 
-/// “UPSafeCell”中的任务管理器内部
+/// “SpinNoIrqLock”中的任务管理器内部
+///
+/// Every live task is in exactly one place: the `current` of a hart's
+/// processor, `scheduler` or `blocked`. An exited task stays with its parent
+/// until it is reaped, an exited task without a parent waits in the `dead` of
+/// its processor until the hart is off its stack. All harts share the one
+/// ready queue, what a hart runs is in `PROCESSORS`.
 struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
-    /// id of current `Running` task
-    current_task: usize,
+    /// `Ready` tasks, and the policy that picks among them
+    scheduler: Box<dyn Scheduler>,
+    /// `Blocked` tasks, waiting for their `wakeup_time` or a `wakeup_task`
+    blocked: Vec<Arc<TaskControlBlock>>,
+    /// hooks waiting for the first dispatch of their task, by pid
+    hooks: HookRegistry,
+}
+
+impl TaskManagerInner {
+    /// The live task with `pid`, wherever it is.
+    fn find_pid(&self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        let mut found = PROCESSORS
+            .iter()
+            .filter_map(|processor| processor.current.clone())
+            .chain(self.blocked.iter().cloned())
+            .find(|task| task.getpid() == pid);
+        if found.is_none() {
+            self.scheduler.for_each(&mut |task| {
+                if task.getpid() == pid {
+                    found = Some(task.clone());
+                }
+            });
+        }
+        found
+    }
+    /// Move the `Blocked` task `pid` to the ready queue, tasks in any other
+    /// state are left alone.
+    fn wake(&mut self, pid: usize) {
+        if let Some(idx) = self.blocked.iter().position(|task| task.getpid() == pid) {
+            let task = self.blocked.remove(idx);
+            task.inner_exclusive_access().task_status = TaskStatus::Ready;
+            self.scheduler.add_task(task);
+        }
+    }
 }
 
 //lazy_static是社区提供的非常强大的宏，用于懒初始化静态变量
@@ -51,169 +112,941 @@ lazy_static! {
         info!("init TASK_MANAGER");
         let num_app = get_num_app();
         info!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
+        let mut scheduler = new_scheduler(BOOT_POLICY);
         for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
+            #[cfg(feature = "integration")]
+            if !crate::integration::selected(get_app_name(i)) {
+                continue;
+            }
+            let args = [String::from(get_app_name(i))];
+            let task = Arc::new(TaskControlBlock::new_with_args(get_app_data(i), &args, &[]));
+            eventlog::record(EventKind::TaskCreate, task.getpid(), 0);
+            scheduler.add_task(task);
         }
         TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
+            inner: SpinNoIrqLock::new(TaskManagerInner {
+                scheduler,
+                blocked: Vec::new(),
+                hooks: HookRegistry::new(),
+            }),
         }
     };
 }
 
 impl TaskManager {
-     //运行任务列表中的第一个任务。
+    //运行任务列表中的第一个任务。
     //通常，任务列表中的第一个任务是空闲任务（稍后我们称之为零进程）。
     //但在ch4中，我们静态加载应用程序，所以第一个任务是真正的应用程序。
+    //每个 hart 都在自己的启动栈上跑这个调度循环，任务让出 hart 时切换回这里。
     fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        // ehe
-        next_task.start_time = timer::get_time_us();
+        loop {
+            let next = match self.wait_for_next_task() {
+                Some(next) => next,
+                // the last task exited on some hart
+                None => all_apps_completed(),
+            };
+            let idle_task_cx_ptr = &mut PROCESSORS.this_cpu().idle_task_cx as *mut TaskContext;
+            let next_task_cx_ptr = self.dispatch(next);
+            // nothing is left borrowed across the switch
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+            // the task gave up the hart, it is queued, blocked, with its
+            // parent or dead; an orphan that exited is off its stack now
+            let mut processor = PROCESSORS.this_cpu();
+            let (current, dead) = (processor.current.take(), processor.dead.take());
+            drop(processor);
+            drop(current);
+            drop(dead);
+        }
+    }
 
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        //在此之前，我们应该删除必须手动删除的局部变量
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
+    /// Make `next` the current task and return its context for the switch,
+    /// running its first-dispatch hook if it never ran before.
+    fn dispatch(&self, next: Arc<TaskControlBlock>) -> *const TaskContext {
+        let mut inner = self.inner.lock();
+        let mut task = next.inner_exclusive_access();
+        let pid = next.getpid();
+        let now = timer::get_time_us();
+        if task.start_time == 0 {
+            inner
+                .hooks
+                .take(pid, |hook| run_first_dispatch_hook(&mut task, pid, hook));
+            // ehe
+            task.start_time = now;
         }
-        panic!("unreachable in run_first_task!");
+        task.task_status = TaskStatus::Running;
+        task.last_scheduled = now;
+        task.mode_switched_at = now;
+        task.kernel_churn_us = 0;
+        // the task context lives as long as the task, which some queue or
+        // parent holds until it runs again
+        let next_task_cx_ptr = &task.task_cx as *const TaskContext;
+        drop(task);
+        let mut processor = PROCESSORS.this_cpu();
+        processor.current = Some(next);
+        processor.counters.dispatches += 1;
+        trace::record(TracePoint::Switch, pid, [trace::NO_TASK, pid]);
+        next_task_cx_ptr
     }
 
     //将当前“正在运行”任务的状态更改为“就绪”。 
     fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Ready;
+        let mut inner = self.inner.lock();
+        let current = processor::current_task();
+        let mut task = current.inner_exclusive_access();
+        task.task_status = TaskStatus::Ready;
+        task.account_switch_out(timer::get_time_us());
+        drop(task);
+        inner.scheduler.add_task(current);
+    }
+
+    //将当前“正在运行”任务的状态更改为“阻塞”，直到 `wakeup_time` 再变回“就绪”。
+    fn mark_current_blocked(&self, wakeup_time: usize) {
+        let mut inner = self.inner.lock();
+        let current = processor::current_task();
+        let mut task = current.inner_exclusive_access();
+        task.task_status = TaskStatus::Blocked;
+        task.wakeup_time = wakeup_time;
+        task.account_switch_out(timer::get_time_us());
+        drop(task);
+        inner.blocked.push(current);
+    }
+
+    //把“阻塞”的任务 `pid` 改回“就绪”，其他状态的任务不受影响。
+    fn wakeup(&self, pid: usize) {
+        self.inner.lock().wake(pid);
+    }
+
+    /// Send `signum` to task `pid`, false if there is no such live task.
+    /// SIGKILL and SIGCONT wake a `Blocked` task, SIGKILL has to interrupt
+    /// whatever it waits for and SIGCONT may be continuing a stopped task.
+    /// Other signals wait until the task gets to run.
+    fn send_signal(&self, pid: usize, signum: usize) -> bool {
+        let mut inner = self.inner.lock();
+        let task = match inner.find_pid(pid) {
+            Some(task) => task,
+            None => return false,
+        };
+        let mut task_inner = task.inner_exclusive_access();
+        if matches!(task_inner.task_status, TaskStatus::UnInit | TaskStatus::Exited) {
+            return false;
+        }
+        task_inner.signals.send(signum);
+        let blocked = task_inner.task_status == TaskStatus::Blocked;
+        drop(task_inner);
+        let sig = SignalFlags::from_signum(signum).unwrap();
+        if blocked && (SignalFlags::SIGKILL | SignalFlags::SIGCONT).contains(sig) {
+            inner.wake(pid);
+        }
+        true
+    }
+
+    /// Take the current task's next signal to act on, setting up the trap
+    /// context if it is a handler. Also tells whether the task is stopped.
+    fn deliver_signal(&self) -> (Option<SignalDelivery>, bool) {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        let delivery = task.signals.next_delivery();
+        if let Some(SignalDelivery::Handle(signum, action)) = delivery {
+            let trap_cx = task.get_trap_cx();
+            task.signals.enter_handler(signum, action, trap_cx);
+        }
+        (delivery, task.signals.stopped)
+    }
+
+    //把睡眠时间已到的“阻塞”任务改回“就绪”。
+    fn wake_sleepers(&self) {
+        let mut inner = self.inner.lock();
+        let now = timer::get_time_us();
+        let due: Vec<usize> = inner
+            .blocked
+            .iter()
+            .filter(|task| task.inner_exclusive_access().wakeup_time <= now)
+            .map(|task| task.getpid())
+            .collect();
+        for pid in due {
+            inner.wake(pid);
+        }
+    }
+
+    /// Find the next task for this hart, idling while every unfinished task
+    /// sleeps or runs on another hart. Returns None once no task is `Ready`,
+    /// `Blocked` or running any more.
+    fn wait_for_next_task(&self) -> Option<Arc<TaskControlBlock>> {
+        loop {
+            self.wake_sleepers();
+            let mut inner = self.inner.lock();
+            if let Some(next) = inner.scheduler.fetch_task() {
+                return Some(next);
+            }
+            let running = PROCESSORS.iter().any(|processor| processor.current.is_some());
+            if inner.blocked.is_empty() && !running {
+                return None;
+            }
+            drop(inner);
+            // the other harts get the kernel while we idle; a task they make
+            // `Ready` waits for our next tick, nobody interrupts the wfi
+            smp::unlock_kernel();
+            // interrupts stay masked in the kernel, a pending timer interrupt
+            // only wakes the hart up and has to be re-armed by hand
+            unsafe {
+                core::arch::asm!("wfi");
+            }
+            smp::lock_kernel();
+            PROCESSORS.this_cpu().counters.idle_wakeups += 1;
+            // a device interrupt is not taken in the kernel either, serve it
+            // here or every task waiting for a device would sleep on
+            crate::drivers::plic::handle_interrupt();
+            timer::set_next_trigger();
+        }
     }
 
     //将当前“正在运行”任务的状态更改为“已退出”。
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Exited;
+    //主线程退出时整个进程退出，其他线程只退出自己。
+    fn mark_current_exited(&self, exit_code: i32) {
+        let mut inner = self.inner.lock();
+        let current = processor::current_task();
+        let mut task = current.inner_exclusive_access();
+        task.task_status = TaskStatus::Exited;
+        task.exit_code = exit_code;
+        let mut process = current.process.inner_exclusive_access();
+        // mutexes it still holds are released after, for their waiters
+        let mutexes = process.mutexes.clone();
+        // a previous orphan in `dead` is off its stack by now
+        let mut freed = None;
+        let mut files = Vec::new();
+        let mut children = Vec::new();
+        let mut threads = Vec::new();
+        if current.tid != 0 {
+            // a joinable thread stays in the table, its stack goes now; one
+            // the process let go of when it exited or exec'd has nobody left
+            // to join it, and its stack went with the old address space
+            let listed = process
+                .threads
+                .get(current.tid)
+                .and_then(Option::as_ref)
+                .map_or(false, |thread| Arc::ptr_eq(thread, &current));
+            if listed {
+                let trap_cx_vpn = mm::VirtAddr::from(task.trap_cx_va).floor();
+                process.memory_set.remove_area_with_start_vpn(trap_cx_vpn);
+                if let Some(stack_bottom) = task.thread_stack {
+                    process.memory_set.remove_area_with_start_vpn(stack_bottom);
+                }
+            } else {
+                freed = PROCESSORS.this_cpu().dead.replace(current.clone());
+            }
+        } else {
+            // close the files now, the task may not be waited for any time soon;
+            // dropped at the end, a pipe end wakes tasks through the manager
+            files = core::mem::take(&mut process.fd_table);
+            // there is no init task to adopt orphans, they are freed once they
+            // exit; exited children go away with this list
+            children = core::mem::take(&mut process.children);
+            for child in children.iter() {
+                child.process.inner_exclusive_access().parent = None;
+            }
+            // the other threads die with the process, on their way back to
+            // user mode; the exited ones go away with this list
+            threads = core::mem::take(&mut process.threads);
+            for thread in threads.iter().flatten() {
+                let mut thread_inner = thread.inner_exclusive_access();
+                if thread_inner.task_status != TaskStatus::Exited {
+                    thread_inner.signals.send(SignalFlags::SIGKILL.signum());
+                    drop(thread_inner);
+                    inner.wake(thread.getpid());
+                }
+            }
+            // a parent blocked in wait4 gets to look at its children again,
+            // whichever of its threads waits, while a process without a
+            // parent has nobody left to reap it
+            match process.parent.as_ref().and_then(|parent| parent.upgrade()) {
+                Some(parent) => {
+                    inner.wake(parent.pid);
+                    let parent_inner = parent.inner_exclusive_access();
+                    for thread in parent_inner.threads.iter().flatten() {
+                        inner.wake(thread.getpid());
+                    }
+                }
+                None => freed = PROCESSORS.this_cpu().dead.replace(current.clone()),
+            }
+        }
+        eventlog::record(EventKind::TaskExit, current.getpid(), exit_code as usize);
+        task.account_switch_out(timer::get_time_us());
+        drop(process);
+        drop(task);
+        drop(inner);
+        drop(files);
+        release_mutexes(current.getpid(), &mutexes);
+        drop(children);
+        drop(threads);
+        drop(freed);
     }
 
-    //查找要运行的下一个任务并返回任务id。
-    //在这种情况下，我们只返回任务列表中的第一个“就绪”任务。
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
+    /// Switch the scheduling policy of all tasks from the next pick on,
+    /// the `Ready` tasks move over to a new scheduler.
+    fn set_policy(&self, policy: SchedPolicy) {
+        let mut inner = self.inner.lock();
+        let mut scheduler = new_scheduler(policy);
+        inner
+            .scheduler
+            .for_each(&mut |task| scheduler.add_task(task.clone()));
+        inner.scheduler = scheduler;
+    }
+
+    /// The current 'Running' task.
+    fn current_task(&self) -> Arc<TaskControlBlock> {
+        processor::current_task()
+    }
+
+    /// The pid of the current task.
+    fn get_current_pid(&self) -> usize {
+        self.current_task().getpid()
+    }
+
+    /// The pid of the current task's parent, `None` for apps loaded at boot
+    /// and for tasks whose parent exited.
+    fn get_parent_pid(&self) -> Option<usize> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        let parent = process.parent.as_ref()?.upgrade()?;
+        Some(parent.pid)
     }
 
     /// Get the current 'Running' task's token.
     fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
+        let token = self.checked_current_token();
+        debug_assert!(
+            token.is_ok(),
+            "task {} has a corrupted satp token {:#x?}",
+            self.get_current_pid(),
+            token
+        );
+        token.unwrap_or_else(|token| token)
+    }
+
+    /// The current task's token, `Err` with the token if it fails
+    /// `token_is_valid`: the root of the task's page table got corrupted.
+    fn checked_current_token(&self) -> Result<usize, usize> {
+        let token = self.current_task().process.inner_exclusive_access().get_user_token();
+        if mm::token_is_valid(token) {
+            Ok(token)
+        } else {
+            Err(token)
+        }
     }
 
     #[allow(clippy::mut_from_ref)]
     /// Get the current 'Running' task's trap contexts.
     fn get_current_trap_cx(&self) -> &mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
+        self.current_task().inner_exclusive_access().get_trap_cx()
     }
 
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    //将当前“正在运行”任务切换到我们找到的任务，
-    //或者没有“就绪”任务，我们可以在完成所有应用程序后退出
+    /// Switch from the current task, already queued, blocked, with its
+    /// parent or dead, to this hart's scheduling loop, which picks the next
+    /// task. Returns once some hart picks the current task again.
+    //从当前任务切换回本 hart 的调度循环，由它挑选下一个任务
     fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            // ehe
-            if inner.tasks[next].start_time == 0 {
-                inner.tasks[next].start_time = timer::get_time_us();
+        let idle_task_cx_ptr = &PROCESSORS.this_cpu().idle_task_cx as *const TaskContext;
+        // the task is held elsewhere too, so its context outlives this reference
+        let current = processor::current_task();
+        let current_task_cx_ptr = &mut current.inner_exclusive_access().task_cx as *mut TaskContext;
+        let pid = current.getpid();
+        trace::record(TracePoint::Switch, pid, [pid, trace::NO_TASK]);
+        drop(current);
+        // nothing is left borrowed across the switch
+        unsafe {
+            __switch(current_task_cx_ptr, idle_task_cx_ptr);
+        }
+        // go back to user mode
+    }
+
+    /// 更新特定应用的系统调用次数，最多分别统计 `MAX_SYSCALL_IDS_COUNTED` 个 id，
+    /// 之后新出现的 id 计入溢出次数，计数饱和而不溢出；返回当前任务是否开启了 strace
+    fn update_syscall_times(&self, id: usize) -> bool {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        task.count_syscall(id);
+        task.strace
+    }
+
+    /// 打开或关闭任务 `pid` 的 strace，没有这个任务返回 false；
+    /// 还没运行过的任务第一次被调度时还会打印出调度的时间
+    fn set_strace(&self, pid: usize, enable: bool) -> bool {
+        let inner = self.inner.lock();
+        let task = match inner.find_pid(pid) {
+            Some(task) => task,
+            None => return false,
+        };
+        drop(inner);
+        let mut task_inner = task.inner_exclusive_access();
+        if matches!(task_inner.task_status, TaskStatus::UnInit | TaskStatus::Exited) {
+            return false;
+        }
+        let newly_traced = enable && !task_inner.strace;
+        task_inner.strace = enable;
+        drop(task_inner);
+        if newly_traced {
+            // false once the task ran, its first dispatch is over then
+            self.on_first_dispatch(pid, Hook::Trace);
+        }
+        true
+    }
+
+    /// 得到某个系统调用的次数
+    fn get_syscall_count(&self, id: usize) -> u32 {
+        let current = self.current_task();
+        let task = current.inner_exclusive_access();
+        task.syscall_times.get(&id).copied().unwrap_or(0)
+    }
+
+    /// 得到超出 `MAX_SYSCALL_IDS_COUNTED` 个 id 后未能分别统计的系统调用次数
+    fn get_syscall_overflow(&self) -> u32 {
+        self.current_task().inner_exclusive_access().syscall_overflow
+    }
+
+    /// 得到系统调用次数，id 不小于 `MAX_SYSCALL_NUM` 的调用不在其中
+    fn get_syscall_times(&self) -> [u32; config::MAX_SYSCALL_NUM] {
+        self.current_task().inner_exclusive_access().syscall_times_array()
+    }
+
+    /// Run `f` on the current task and its process under a single borrow,
+    /// so everything it reads is one consistent snapshot.
+    fn inspect_current<R>(
+        &self,
+        f: impl FnOnce(&TaskControlBlockInner, &ProcessControlBlockInner) -> R,
+    ) -> R {
+        let current = self.current_task();
+        let task = current.inner_exclusive_access();
+        let process = current.process.inner_exclusive_access();
+        f(&task, &process)
+    }
+
+    /// Reap an exited child of the current task's process: child `pid`, or
+    /// any child if `pid` is -1. Runs `f` on its main thread and process
+    /// before they are freed and returns its pid with the result,
+    /// `Ok(None)` while every matching child still runs and `-ECHILD` if
+    /// there is no matching child at all.
+    fn wait_child<R>(
+        &self,
+        pid: isize,
+        f: impl FnOnce(&TaskControlBlockInner, &ProcessControlBlockInner) -> R,
+    ) -> Result<Option<(usize, R)>, isize> {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let matches = |child: &Arc<TaskControlBlock>| pid == -1 || pid == child.getpid() as isize;
+        if !process.children.iter().any(matches) {
+            return Err(-ECHILD);
+        }
+        let found = process.children.iter().position(|child| {
+            matches(child) && child.inner_exclusive_access().task_status == TaskStatus::Exited
+        });
+        let idx = match found {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+        let child = process.children.remove(idx);
+        drop(process);
+        // nothing else refers to an exited child, it is freed at the end
+        assert_eq!(Arc::strong_count(&child), 1);
+        let mut task = current.inner_exclusive_access();
+        let child_inner = child.inner_exclusive_access();
+        let result = f(&child_inner, &child.process.inner_exclusive_access());
+        task.children_user_time_us += child_inner.user_time_us + child_inner.children_user_time_us;
+        task.children_kernel_time_us +=
+            child_inner.kernel_time_us + child_inner.children_kernel_time_us;
+        drop(child_inner);
+        Ok(Some((child.getpid(), result)))
+    }
+
+    /// 得到当前任务的开始时间
+    fn get_start_time(&self) -> usize {
+        let current = self.current_task();
+        let task = current.inner_exclusive_access();
+        return timer::get_time_us() - task.start_time;
+    }
+
+    /// 得到当前任务实际占用的 CPU 时间（微秒），包括本次时间片
+    fn get_cpu_time(&self) -> usize {
+        let current = self.current_task();
+        let task = current.inner_exclusive_access();
+        task.cpu_time_us(timer::get_time_us())
+    }
+
+    /// The current task trapped into the kernel, charge its user time.
+    fn account_trap_entry(&self) {
+        let now = timer::get_time_us();
+        PROCESSORS.this_cpu().counters.traps += 1;
+        self.current_task().inner_exclusive_access().account_trap_entry(now);
+    }
+
+    /// The current task goes back to user mode, charge its kernel time.
+    fn account_trap_return(&self) {
+        let now = timer::get_time_us();
+        self.current_task().inner_exclusive_access().account_trap_return(now);
+    }
+
+    /// Count a yield of the current task and warn once per tick when it
+    /// yields more than `YIELD_LIVELOCK_THRESHOLD` times.
+    fn note_yield(&self) {
+        let current = self.current_task();
+        let threshold = config::YIELD_LIVELOCK_THRESHOLD;
+        if current
+            .inner_exclusive_access()
+            .yields
+            .record(timer::get_tick(), threshold)
+        {
+            warn!(
+                "[kernel] task {} yielded more than {} times within one tick, possible livelock",
+                current.getpid(),
+                threshold
+            );
+        }
+    }
+
+    /// Charge the current task for a syscall that entered the kernel at
+    /// `entered`. Returns true, and counts a churn preemption, once the task
+    /// spent more than `KERNEL_CHURN_LIMIT_US` in syscalls since it was last
+    /// switched in.
+    fn charge_kernel_time(&self, entered: usize) -> bool {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        // time spent switched out during the syscall belongs to other tasks
+        let start = entered.max(task.last_scheduled);
+        task.kernel_churn_us += timer::get_time_us().saturating_sub(start);
+        if task.kernel_churn_us > config::KERNEL_CHURN_LIMIT_US {
+            task.churn_preemptions += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Log every `Ready` task that has not run for `STARVATION_THRESHOLD_US`
+    /// at `now`, and return their pids. Only called with the `sched_audit`
+    /// feature.
+    #[allow(unused)]
+    fn audit_starvation(&self, now: usize) -> Vec<usize> {
+        let inner = self.inner.lock();
+        let mut starved = Vec::new();
+        inner.scheduler.for_each(&mut |task| {
+            let task_inner = task.inner_exclusive_access();
+            if task::is_starved(
+                task_inner.task_status,
+                task_inner.last_scheduled,
+                now,
+                config::STARVATION_THRESHOLD_US,
+            ) {
+                warn!(
+                    "[kernel] task {} is Ready but has not run for {}us",
+                    task.getpid(),
+                    now - task_inner.last_scheduled
+                );
+                starved.push(task.getpid());
             }
+        });
+        starved
+    }
 
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            //在此之前，我们应该删除必须手动删除的局部变量
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
+    /// Arm a one-shot hook for the first dispatch of task `pid`. Returns
+    /// false if the task already ran or too many hooks are pending.
+    fn on_first_dispatch(&self, pid: usize, hook: Hook) -> bool {
+        let mut inner = self.inner.lock();
+        match inner.find_pid(pid) {
+            Some(task) if task.inner_exclusive_access().start_time == 0 => {}
+            _ => return false,
+        }
+        inner.hooks.register(pid, hook)
+    }
+
+    /// Add a `Ready` task running `elf_data` with `args` and return its pid.
+    /// It starts with the pass of the current task, so it neither jumps the
+    /// queue nor waits for everyone else to catch up.
+    fn spawn(&self, elf_data: &'static [u8], args: &[String]) -> usize {
+        let task = Arc::new(TaskControlBlock::new_with_args(elf_data, args, &[]));
+        let current = self.current_task();
+        task.inner_exclusive_access().pass = current.inner_exclusive_access().pass;
+        task.process.inner_exclusive_access().parent = Some(Arc::downgrade(&current.process));
+        current.process.inner_exclusive_access().children.push(task.clone());
+        let pid = task.getpid();
+        self.inner.lock().scheduler.add_task(task);
+        eventlog::record(EventKind::TaskCreate, pid, 0);
+        pid
+    }
+
+    /// Add a copy of the current task as its child and return the child's
+    /// pid, `None` if there is no room for the copy.
+    fn fork(&self) -> Option<usize> {
+        let child = self.current_task().fork();
+        // our own pages just lost their write permission, even if it failed
+        flush_tlb();
+        let child = child?;
+        let pid = child.getpid();
+        self.inner.lock().scheduler.add_task(child);
+        eventlog::record(EventKind::TaskCreate, pid, 0);
+        Some(pid)
+    }
+
+    /// Run the app `elf_data` in place of the current task's program, false
+    /// unless the current task is a main thread. The other threads of its
+    /// process are killed first and waited for, they could still be using
+    /// the old address space.
+    fn exec(&self, elf_data: &'static [u8], args: &[String], envs: &[String]) -> bool {
+        let current = self.current_task();
+        if current.tid != 0 {
+            return false;
+        }
+        loop {
+            let threads: Vec<usize> = current
+                .process
+                .inner_exclusive_access()
+                .threads
+                .iter()
+                .flatten()
+                .map(|thread| thread.getpid())
+                .collect();
+            let signum = SignalFlags::SIGKILL.signum();
+            // exited threads are no longer found
+            let killed = threads.iter().filter(|pid| self.send_signal(**pid, signum)).count();
+            if killed == 0 {
+                break;
             }
-            // go back to user mode
+            suspend_current_and_run_next();
+        }
+        current.exec(elf_data, args, envs);
+        true
+    }
+
+    /// Start a thread of the current task's process at `entry` with `arg`
+    /// and return its tid, `-ENOMEM` if there is no room for its stack.
+    fn thread_create(&self, entry: usize, arg: usize) -> isize {
+        let current = self.current_task();
+        let thread = match TaskControlBlock::new_thread(&current, entry, arg) {
+            Some(thread) => thread,
+            None => return -ENOMEM,
+        };
+        // the new stack and trap context are mapped in our own address space
+        flush_tlb();
+        let tid = thread.tid;
+        eventlog::record(EventKind::TaskCreate, thread.getpid(), 0);
+        self.inner.lock().scheduler.add_task(thread);
+        tid as isize
+    }
+
+    /// 回收当前进程中已退出的 `tid` 号线程，返回它的退出码；线程不存在或是
+    /// 当前线程自己返回 -1，线程还没退出返回 -2
+    fn waittid(&self, tid: usize) -> isize {
+        let current = self.current_task();
+        if tid == current.tid {
+            return -1;
+        }
+        let mut process = current.process.inner_exclusive_access();
+        let thread = match process.threads.get_mut(tid) {
+            Some(thread) => thread,
+            None => return -1,
+        };
+        let exit_code = match thread.as_ref() {
+            Some(thread) => {
+                let inner = thread.inner_exclusive_access();
+                if inner.task_status != TaskStatus::Exited {
+                    return -2;
+                }
+                inner.exit_code
+            }
+            None => return -1,
+        };
+        let joined = thread.take();
+        drop(process);
+        drop(joined);
+        exit_code as isize
+    }
+
+    /// Where the current task's trap context is mapped in user space.
+    fn get_current_trap_cx_user_va(&self) -> usize {
+        self.current_task().inner_exclusive_access().trap_cx_va
+    }
+
+    /// The file behind the current task's descriptor `fd`, if it is open.
+    fn current_file(&self, fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        process.fd_table.get(fd).cloned().flatten()
+    }
+
+    /// Give `file` the current task's lowest free descriptor and return it.
+    fn install_file(&self, file: Arc<dyn File + Send + Sync>) -> usize {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let fd = process.alloc_fd();
+        process.fd_table[fd] = Some(file);
+        fd
+    }
+
+    /// Close the current task's descriptor `fd`, false if it was not open.
+    fn close_file(&self, fd: usize) -> bool {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let file = process.fd_table.get_mut(fd).and_then(|file| file.take());
+        // the last reference may be a pipe end that wakes tasks through the manager
+        drop(process);
+        file.is_some()
+    }
+
+    /// Make a semaphore holding `count` permits for the current task and
+    /// return its id.
+    fn create_semaphore(&self, count: usize) -> usize {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        process.semaphores.push(Arc::new(Semaphore::new(count)));
+        process.semaphores.len() - 1
+    }
+
+    /// The current task's semaphore `id`, if it has one.
+    fn current_semaphore(&self, id: usize) -> Option<Arc<Semaphore>> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        process.semaphores.get(id).cloned()
+    }
+
+    /// Make a mutex for the current task, one whose waiters block if
+    /// `blocking`, and return its id.
+    fn create_mutex(&self, blocking: bool) -> usize {
+        let mutex: Arc<dyn Mutex> = if blocking {
+            Arc::new(MutexBlocking::new())
         } else {
-            panic!("All applications completed!");
+            Arc::new(MutexSpin::new())
+        };
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        process.mutexes.push(mutex);
+        process.mutexes.len() - 1
+    }
+
+    /// The current task's mutex `id`, if it has one.
+    fn current_mutex(&self, id: usize) -> Option<Arc<dyn Mutex>> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        process.mutexes.get(id).cloned()
+    }
+
+    /// Make a condition variable for the current task and return its id.
+    fn create_condvar(&self) -> usize {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        process.condvars.push(Arc::new(Condvar::new()));
+        process.condvars.len() - 1
+    }
+
+    /// The current task's condition variable `id`, if it has one.
+    fn current_condvar(&self, id: usize) -> Option<Arc<Condvar>> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        process.condvars.get(id).cloned()
+    }
+
+    /// Replace the current task's action for `signum` if `action` is given,
+    /// returns the old action.
+    fn set_signal_action(&self, signum: usize, action: Option<SignalAction>) -> SignalAction {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        let actions = &mut task.signals.actions;
+        let old = actions[signum];
+        if let Some(action) = action {
+            actions[signum] = action;
         }
+        old
     }
 
-    /// 更新特定应用的系统调用次数
-    fn update_syscall_times(&self, id: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].syscall_times[id] += 1;
+    /// Block the signals in `mask` for the current task, returns the old mask.
+    /// SIGKILL and SIGSTOP cannot be blocked and are left out.
+    fn set_signal_mask(&self, mask: SignalFlags) -> SignalFlags {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        core::mem::replace(&mut task.signals.mask, mask - SignalFlags::fixed())
     }
 
-    /// 得到系统调用次数
-    fn get_syscall_times(&self) -> [u32; 500] {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].syscall_times
+    /// Return from the current task's signal handler, false if it is not in one.
+    fn sigreturn(&self) -> bool {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        let trap_cx = task.get_trap_cx();
+        task.signals.sigreturn(trap_cx)
     }
 
-    /// 得到当前任务的开始时间
-    fn get_start_time(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        return timer::get_time_us() - inner.tasks[current].start_time;
+    /// Whether the current task has a SIGKILL pending.
+    fn current_killed(&self) -> bool {
+        self.current_task().inner_exclusive_access().signals.killed()
+    }
+
+    /// Whether the current task has a signal pending or is stopped.
+    fn signals_pending(&self) -> bool {
+        self.current_task().inner_exclusive_access().signals.any_pending()
+    }
+
+    /// 设置当前任务是否以批处理方式运行，CPU 时间上限从进入批处理模式时算起，
+    /// 已处于批处理模式时再次设置不会重新计时
+    fn set_batch(&self, batch: bool) {
+        let current = self.current_task();
+        let mut task = current.inner_exclusive_access();
+        task.batch = match (batch, task.batch) {
+            (false, _) => None,
+            (true, Some(since)) => Some(since),
+            (true, None) => Some(task.cpu_time_us(timer::get_time_us())),
+        };
+    }
+
+    /// Decide what the timer interrupt does to the current task. The
+    /// scheduler sees every tick, but batch tasks keep the cpu whatever it says.
+    fn tick_action(&self) -> TickAction {
+        let mut inner = self.inner.lock();
+        let current = processor::current_task();
+        let task = current.inner_exclusive_access();
+        let cpu_time = task.cpu_time_us(timer::get_time_us());
+        let action = tick_action(task.batch, cpu_time, config::BATCH_CPU_LIMIT_US);
+        drop(task);
+        let preempt = inner.scheduler.tick(&current);
+        match action {
+            TickAction::Preempt if !preempt => TickAction::KeepRunning,
+            action => action,
+        }
+    }
+
+    /// 设置当前任务的优先级
+    fn set_priority(&self, priority: usize) {
+        self.current_task().inner_exclusive_access().priority = priority;
     }
 
     /// mmap
-    fn mmap(&self, start: usize, len: usize, port: usize) -> isize {
-        if (start % config::PAGE_SIZE != 0) || (port & !0x7 != 0) || (port & 0x7 == 0) {
-            return -1;
+    /// -EINVAL for a misaligned start, a bad port or a range leaving user
+    /// space or crossing the SV39 hole, -ENOMEM if the range would take the
+    /// task past its mmap quota, -EEXIST if any page of it is taken. No frame
+    /// is allocated here, untouched pages cost nothing.
+    ///
+    /// `start == 0` without `MAP_FIXED` in `port` lets the kernel pick the
+    /// address, the first hole above the task's randomized mmap base, which
+    /// is then returned instead of 0.
+    ///
+    /// `MAP_SHARED` areas stay shared with forked children, anonymous ones
+    /// are backed right away (-ENOMEM if the frames are not there). `fd` -1
+    /// maps anonymous memory, any other maps the open file `fd` from the
+    /// page aligned `offset` on: its pages are read in on first touch, and
+    /// a `MAP_SHARED` mapping writes them back when they are unmapped, at
+    /// the latest on exit or exec. -EBADF if `fd` is not open, -ENODEV if it
+    /// is no regular file, -EACCES if it was not opened for reading, or not
+    /// for writing while a shared mapping asks for write access.
+    fn mmap(&self, start: usize, len: usize, port: usize, fd: isize, offset: usize) -> isize {
+        let auto_place = start == 0 && port & mm::MAP_FIXED == 0;
+        let shared = port & mm::MAP_SHARED != 0;
+        if (shared && port & mm::MAP_PRIVATE != 0) || offset % config::PAGE_SIZE != 0 {
+            return -EINVAL;
+        }
+        let file = if fd == -1 {
+            None
+        } else {
+            // any other negative fd turns into one far past the table
+            let file = match self.current_file(fd as usize) {
+                Some(file) => file,
+                None => return -EBADF,
+            };
+            match file.inode() {
+                Some(inode) if file.readable() => Some((inode, file.writable())),
+                Some(_) => return -EACCES,
+                None => return -ENODEV,
+            }
+        };
+        let port = port & !(mm::MAP_FIXED | mm::MAP_SHARED | mm::MAP_PRIVATE);
+        let start = if auto_place {
+            let pages = match len.checked_add(config::PAGE_SIZE - 1) {
+                Some(len) => len / config::PAGE_SIZE,
+                None => return -EINVAL,
+            };
+            let current = self.current_task();
+            let process = current.process.inner_exclusive_access();
+            let from = mm::VirtAddr::from(process.memory_set.mmap_base()).floor();
+            match process.memory_set.find_free_range(pages, from) {
+                Some(vpn) => mm::VirtAddr::from(vpn).0,
+                None => return -ENOMEM,
+            }
+        } else {
+            start
+        };
+        let map_permission = match mm::MapPermission::from_port(port) {
+            Some(permission) if start % config::PAGE_SIZE == 0 && !permission.is_empty() => {
+                permission | mm::MapPermission::U
+            }
+            _ => return -EINVAL,
+        };
+        // a shared mapping writes back, so it needs a file opened for writing
+        let writes_back = shared && map_permission.contains(mm::MapPermission::W);
+        if writes_back && matches!(file, Some((_, false))) {
+            return -EACCES;
         }
+        let end = match start.checked_add(len) {
+            Some(end) if end <= config::TRAP_CONTEXT => end,
+            _ => return -EINVAL,
+        };
 
         let start_address = mm::VirtAddr(start);
-        let end_address = mm::VirtAddr(start + len);
+        let end_address = mm::VirtAddr(end);
+        let vpn_range = match mm::VPNRange::checked(start_address, end_address) {
+            Some(vpn_range) => vpn_range,
+            None => return -EINVAL,
+        };
 
-        let map_permission =
-            mm::MapPermission::from_bits((port as u8) << 1).unwrap() | mm::MapPermission::U;
+        // frames come on first touch, so only the quota limits the size; it is
+        // also checked before the page by page scan below, which a huge len would make crawl
+        let pages = vpn_range.get_end().0 - vpn_range.get_start().0;
 
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let mmap_bytes = match charge_mmap_quota(
+            process.mmap_bytes,
+            pages * config::PAGE_SIZE,
+            config::MMAP_QUOTA_BYTES,
+        ) {
+            Some(mmap_bytes) => mmap_bytes,
+            None => return -ENOMEM,
+        };
 
-        for vpn in mm::VPNRange::new(mm::VirtPageNum::from(start_address), end_address.ceil()) {
-            if let Some(pte) = inner.tasks[current].memory_set.translate(vpn) {
+        for vpn in vpn_range {
+            // lazy pages that were never touched have no valid pte but are still taken
+            if process.memory_set.is_reserved(vpn) {
+                return -EEXIST;
+            }
+            if let Some(pte) = process.memory_set.translate(vpn) {
                 if pte.is_valid() {
-                    println!("[debug] This area is used!");
-                    return -1;
+                    return -EEXIST;
                 }
             };
-
-            println!("[debug] {}", usize::from(vpn));
         }
 
-        inner.tasks[current].memory_set.insert_framed_area(
-            start_address,
-            end_address,
-            map_permission,
-        );
-
-        for vpn in mm::VPNRange::new(mm::VirtPageNum::from(start_address), end_address.ceil()) {
-            if let None = inner.tasks[current].memory_set.translate(vpn) {
-                return -1;
-            };
+        // frames are only allocated when the pages are first touched, unless
+        // shared and anonymous
+        if shared && file.is_none() && mm::frame_stats().free < pages {
+            return -ENOMEM;
         }
+        match file {
+            Some((inode, _)) => process.memory_set.insert_file_area(
+                start_address,
+                end_address,
+                map_permission,
+                shared,
+                (inode, offset),
+            ),
+            None => process.memory_set.insert_mmap_area(
+                start_address,
+                end_address,
+                map_permission,
+                shared,
+            ),
+        }
+        process.mmap_bytes = mmap_bytes;
+        eventlog::record(EventKind::Mmap, current.getpid(), start);
 
-        return 0;
+        if auto_place {
+            start as isize
+        } else {
+            0
+        }
     }
 
     /// munmap
@@ -222,48 +1055,279 @@ impl TaskManager {
             return -1;
         }
 
-        let start_address = mm::VirtAddr(start);
-        let end_address = mm::VirtAddr(start + len);
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => return -1,
+        };
+        let vpn_range = match mm::VPNRange::checked(mm::VirtAddr(start), mm::VirtAddr(end)) {
+            Some(vpn_range) => vpn_range,
+            None => return -1,
+        };
 
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let memory_set = &mut process.memory_set;
 
-        for vpn in mm::VPNRange::new(mm::VirtPageNum::from(start_address), end_address.ceil()) {
-            if let None = inner.tasks[current].memory_set.translate(vpn) {
-                return -1;
-            };
+        // lazily mapped pages count as mapped even if they were never touched
+        if !vpn_range.into_iter().all(|vpn| memory_set.is_user_page(vpn)) {
+            return -1;
+        }
 
-            if let Some(pte) = inner.tasks[current].memory_set.translate(vpn) {
-                if pte.is_valid() == false {
-                    return -1;
+        // munmap may also take heap or elf pages, those never counted against the quota
+        let mmap_pages = memory_set.unmap_range(vpn_range);
+        flush_tlb();
+        process.mmap_bytes = process.mmap_bytes.saturating_sub(mmap_pages * config::PAGE_SIZE);
+        eventlog::record(EventKind::Munmap, current.getpid(), start);
+
+        return 0;
+    }
+
+    /// 把当前任务 `[start, start + len)` 的权限改为 `port`（格式同 mmap），
+    /// 参数不合法返回 -EINVAL，范围中有未映射的页返回 -ENOMEM
+    fn mprotect(&self, start: usize, len: usize, port: usize) -> isize {
+        let map_permission = match mm::MapPermission::from_port(port) {
+            Some(permission) if start % config::PAGE_SIZE == 0 && !permission.is_empty() => {
+                permission | mm::MapPermission::U
+            }
+            _ => return -EINVAL,
+        };
+        let vpn_range = match start
+            .checked_add(len)
+            .and_then(|end| mm::VPNRange::checked(mm::VirtAddr(start), mm::VirtAddr(end)))
+        {
+            Some(vpn_range) => vpn_range,
+            None => return -EINVAL,
+        };
+
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let memory_set = &mut process.memory_set;
+        if !vpn_range.into_iter().all(|vpn| memory_set.is_user_page(vpn)) {
+            return -ENOMEM;
+        }
+        memory_set.mprotect(vpn_range, map_permission);
+        // stale translations would still allow the old access
+        flush_tlb();
+        0
+    }
+
+    /// 移动当前任务的 program break，返回旧的 break，失败返回 -1
+    fn sbrk(&self, increment: isize) -> isize {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        match process.memory_set.sbrk(increment) {
+            Some(old_brk) => {
+                if increment < 0 {
+                    flush_tlb();
                 }
-            };
+                old_brk as isize
+            }
+            None => -1,
         }
+    }
 
-        for vpn in mm::VPNRange::new(mm::VirtPageNum::from(start_address), end_address.ceil()) {
-            inner.tasks[current].memory_set.munmap(vpn);
+    /// 把共享内存段 `id` 挂到当前任务的 `addr` 处，`addr` 为 0 时像 mmap 一样
+    /// 自动选址，返回挂上的地址；段不存在、地址不对齐或已被占用返回 -EINVAL，
+    /// 找不到空洞返回 -ENOMEM
+    fn shmat(&self, id: usize, addr: usize, read_only: bool) -> isize {
+        let pages = match mm::shm_pages(id) {
+            Some(pages) => pages,
+            None => return -EINVAL,
+        };
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let start = if addr == 0 {
+            let from = mm::VirtAddr::from(process.memory_set.mmap_base()).floor();
+            match process.memory_set.find_free_range(pages, from) {
+                Some(vpn) => vpn,
+                None => return -ENOMEM,
+            }
+        } else {
+            match addr.checked_add(pages * config::PAGE_SIZE) {
+                Some(end) if addr % config::PAGE_SIZE == 0 && end <= config::TRAP_CONTEXT => {
+                    mm::VirtAddr::from(addr).floor()
+                }
+                _ => return -EINVAL,
+            }
+        };
+        let vpn_range = mm::VPNRange::new(start, mm::VirtPageNum(start.0 + pages));
+        if vpn_range.into_iter().any(|vpn| process.memory_set.is_reserved(vpn)) {
+            return -EINVAL;
+        }
+        let mut permission = mm::MapPermission::R | mm::MapPermission::U;
+        if !read_only {
+            permission |= mm::MapPermission::W;
         }
+        let frames = mm::shm_attach(id).unwrap();
+        process.memory_set.insert_shm_area(start.into(), frames, permission);
+        mm::VirtAddr::from(start).0 as isize
+    }
 
-        for vpn in mm::VPNRange::new(mm::VirtPageNum::from(start_address), end_address.ceil()) {
-            if let Some(pte) = inner.tasks[current].memory_set.translate(vpn) {
-                if pte.is_valid() {
-                    println!("[debug] This area is used!");
-                    return -1;
+    /// 从当前任务卸下挂在 `addr` 处的共享内存段，`addr` 不是某次 shmat 的
+    /// 返回值返回 -EINVAL
+    fn shmdt(&self, addr: usize) -> isize {
+        if addr % config::PAGE_SIZE != 0 {
+            return -EINVAL;
+        }
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        if !process.memory_set.remove_shm_area(mm::VirtAddr::from(addr).floor()) {
+            return -EINVAL;
+        }
+        flush_tlb();
+        0
+    }
+
+    /// Resolve the lazy pages of `[ptr, ptr + len)` for an `access` the
+    /// kernel is about to make on behalf of the current task, the same way
+    /// the page fault handler would if the task touched them itself.
+    ///
+    /// `len` comes from the task, so like `checked_byte_buffer` this stops
+    /// at the first page the access cannot be made to, and never looks past
+    /// the end of user space. No page of the buffer is swapped out to back
+    /// another, see `MemorySet::populate`.
+    fn populate_user_buffer(&self, ptr: usize, len: usize, access: mm::MapPermission) {
+        let end = match ptr.checked_add(len) {
+            Some(end) => end.min(config::TRAP_CONTEXT),
+            None => return,
+        };
+        if ptr >= end {
+            return;
+        }
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let (start, end) = (mm::VirtAddr::from(ptr).floor(), mm::VirtAddr::from(end).ceil());
+        if process.memory_set.populate(mm::VPNRange::new(start, end), access) {
+            flush_tlb();
+        }
+    }
+
+    /// The frame of the current task's page holding `va`, backed first if it
+    /// is lazy or swapped out. A page of a writable area shared copy-on-write
+    /// gets its own copy, so later writes land in the frame returned. `None`
+    /// if there is no user page there or it still reads the zero frame.
+    fn current_user_frame(&self, va: usize) -> Option<Arc<mm::FrameTracker>> {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let memory_set = &mut process.memory_set;
+        let vpn = mm::VirtAddr::from(va).floor();
+        if memory_set.handle_lazy_fault(vpn, mm::MapPermission::W)
+            || memory_set.handle_lazy_fault(vpn, mm::MapPermission::R)
+        {
+            flush_tlb();
+        }
+        memory_set.user_frame(vpn)
+    }
+
+    /// Like `current_user_frame`, but the frame is not shared with any other
+    /// process afterwards, see `MemorySet::private_user_frame`. For a
+    /// debugger writing into the task, read-only text included.
+    #[allow(unused)]
+    fn current_private_frame(&self, va: usize) -> Option<Arc<mm::FrameTracker>> {
+        let current = self.current_task();
+        let mut process = current.process.inner_exclusive_access();
+        let memory_set = &mut process.memory_set;
+        let vpn = mm::VirtAddr::from(va).floor();
+        memory_set.handle_lazy_fault(vpn, mm::MapPermission::W)
+            || memory_set.handle_lazy_fault(vpn, mm::MapPermission::R);
+        let frame = memory_set.private_user_frame(vpn);
+        // the pte may point at a new frame now
+        flush_tlb();
+        frame
+    }
+
+    /// Print the areas and page table of the current task.
+    fn dump_current_memory_set(&self) {
+        let current = self.current_task();
+        println!("[kernel] memory set of task {}:", current.getpid());
+        current.process.inner_exclusive_access().memory_set.debug_print();
+    }
+
+    /// Why an access to `va` by the current task faulted: a page that is
+    /// mapped, or reserved by an area, refused the access, otherwise nothing
+    /// is there at all.
+    fn fault_reason(&self, va: usize) -> &'static str {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        let memory_set = &process.memory_set;
+        let vpn = mm::VirtAddr::from(va).floor();
+        let present = memory_set.translate(vpn).map_or(false, |pte| pte.is_valid());
+        if present || memory_set.is_reserved(vpn) {
+            "permission denied"
+        } else {
+            "page not present"
+        }
+    }
+
+    /// The leaf entry mapping `va` in the current task's address space.
+    fn current_pte(&self, va: usize) -> Option<mm::PageTableEntry> {
+        let current = self.current_task();
+        let process = current.process.inner_exclusive_access();
+        process.memory_set.translate(mm::VirtAddr::from(va).floor())
+    }
+
+    /// Resolve a fault of an `access` at `va` in the current task's address
+    /// space, see `MemorySet::resolve_fault`.
+    fn handle_page_fault(&self, va: usize, access: mm::MapPermission) -> mm::PageFault {
+        let current = self.current_task();
+        let fault = current
+            .process
+            .inner_exclusive_access()
+            .memory_set
+            .resolve_fault(mm::VirtAddr::from(va).floor(), access);
+        if fault != mm::PageFault::Invalid {
+            flush_tlb();
+        }
+        fault
+    }
+}
+
+/// Run the shutdown hooks, then power off or idle as `completion_policy` says.
+fn all_apps_completed() -> ! {
+    let status = crate::shutdown::complete(config::completion_policy());
+    #[cfg(feature = "integration")]
+    crate::integration::report_and_exit();
+    match status {
+        Some(code) => crate::drivers::qemu_exit::exit_qemu(code),
+        None => {
+            // push the timer out of reach so wfi really sleeps
+            crate::sbi::set_timer(usize::MAX);
+            loop {
+                unsafe {
+                    core::arch::asm!("wfi");
                 }
-            };
+            }
         }
+    }
+}
 
-        return 0;
+/// Carry out a first-dispatch hook right before task `pid` enters user mode.
+fn run_first_dispatch_hook(task: &mut TaskControlBlockInner, pid: usize, hook: Hook) {
+    match hook {
+        Hook::Trace => info!(
+            "[kernel] task {} first dispatched at {}us",
+            pid,
+            timer::get_time_us()
+        ),
+        Hook::SetPriority(priority) => task.priority = priority,
     }
 }
 
-/// Run the first task in task list.
+/// Print what each hart did at shutdown.
+pub fn init() {
+    crate::shutdown::register(crate::shutdown::ShutdownHook {
+        name: "per-hart counters",
+        priority: 5,
+        func: processor::report_counters,
+    });
+}
+
+/// Run tasks from the shared ready queue on this hart, never returns.
 pub fn run_first_task() {
     TASK_MANAGER.run_first_task();
 }
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
+/// Give up this hart, the current task must already be queued, blocked or exited
 fn run_next_task() {
     TASK_MANAGER.run_next_task();
 }
@@ -274,23 +1338,156 @@ fn mark_current_suspended() {
 }
 
 /// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+fn mark_current_exited(exit_code: i32) {
+    TASK_MANAGER.mark_current_exited(exit_code);
 }
 
 /// Suspend the current 'Running' task and run the next task in task list.
 //挂起当前“正在运行”任务并运行任务列表中的下一个任务
 pub fn suspend_current_and_run_next() {
+    // a trap between marking and switching would see a `Ready` task still running
+    let _guard = InterruptGuard::disable();
     mark_current_suspended();
     run_next_task();
 }
 
+/// Block the current task until `wakeup_time` (in us) and run the next task.
+/// The task may be woken earlier by `wakeup_task`, callers check again.
+pub fn sleep_current_and_run_next(wakeup_time: usize) {
+    let _guard = InterruptGuard::disable();
+    TASK_MANAGER.mark_current_blocked(wakeup_time);
+    run_next_task();
+}
+
+/// Block the current task until some other code calls `wakeup_task` on it,
+/// and run the next task. Check the condition waited for before blocking
+/// and again after, a wakeup does not say why it happened.
+pub fn block_current_and_run_next() {
+    sleep_current_and_run_next(usize::MAX);
+}
+
+/// Make the `Blocked` task `pid` `Ready`, does nothing to tasks in any other state.
+pub fn wakeup_task(pid: usize) {
+    TASK_MANAGER.wakeup(pid);
+}
+
+/// Send `signum` to task `pid`, false if there is no such live task
+pub fn send_signal(pid: usize, signum: usize) -> bool {
+    TASK_MANAGER.send_signal(pid, signum)
+}
+
+/// Act on the current task's signals on its way back to user mode: set up a
+/// handler, die of a signal, or stay blocked while stopped until SIGCONT.
+pub fn handle_signals() {
+    loop {
+        match TASK_MANAGER.deliver_signal() {
+            (Some(SignalDelivery::Terminate(signum)), _) => {
+                println!("[kernel] task {} killed by signal {}", current_pid(), signum);
+                exit_current_and_run_next(-(signum as i32));
+                return;
+            }
+            // SIGCONT and SIGKILL wake us up
+            (_, true) => block_current_and_run_next(),
+            (_, false) => return,
+        }
+    }
+}
+
+/// Replace the current task's action for `signum`, returns the old one
+pub fn set_signal_action(signum: usize, action: Option<SignalAction>) -> SignalAction {
+    TASK_MANAGER.set_signal_action(signum, action)
+}
+
+/// Set the current task's signal mask, returns the old one
+pub fn set_signal_mask(mask: SignalFlags) -> SignalFlags {
+    TASK_MANAGER.set_signal_mask(mask)
+}
+
+/// Put back the context the current task's signal handler interrupted
+pub fn sigreturn() -> bool {
+    TASK_MANAGER.sigreturn()
+}
+
+/// Whether the current task is about to die of SIGKILL, blocking code
+/// gives up waiting once it is
+pub fn current_killed() -> bool {
+    TASK_MANAGER.current_killed()
+}
+
+/// Whether the current task has to go back to user mode through
+/// `handle_signals`
+pub fn signals_pending() -> bool {
+    TASK_MANAGER.signals_pending()
+}
+
+/// Called on every timer tick, runs the starvation audit every `SCHED_AUDIT_TICKS` ticks
+/// and returns what to do with the interrupted task.
+pub fn on_timer_tick() -> TickAction {
+    #[cfg(feature = "sched_audit")]
+    if timer::get_tick() % config::SCHED_AUDIT_TICKS == 0 {
+        TASK_MANAGER.audit_starvation(timer::get_time_us());
+    }
+    TASK_MANAGER.tick_action()
+}
+
+/// Called on every trap from user mode, before anything else
+pub fn account_trap_entry() {
+    TASK_MANAGER.account_trap_entry();
+}
+
+/// Called right before going back to user mode
+pub fn account_trap_return() {
+    TASK_MANAGER.account_trap_return();
+}
+
+/// Record that the current task yields, for livelock detection.
+pub fn note_current_yield() {
+    TASK_MANAGER.note_yield();
+}
+
+/// Charge the current task for the syscall it entered at `entered` (in us),
+/// returns true if it should give up the cpu before going back to user mode.
+pub fn charge_kernel_time(entered: usize) -> bool {
+    TASK_MANAGER.charge_kernel_time(entered)
+}
+
 /// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let _guard = InterruptGuard::disable();
+    mark_current_exited(exit_code);
     run_next_task();
 }
 
+/// Get the pid of the current 'Running' task.
+pub fn current_pid() -> usize {
+    TASK_MANAGER.get_current_pid()
+}
+
+#[allow(unused)]
+/// Run `f` with `task` as the current task of this hart, for tests that run
+/// before any task was dispatched.
+pub fn with_current_task<T>(task: Arc<TaskControlBlock>, f: impl FnOnce() -> T) -> T {
+    let previous = PROCESSORS.this_cpu().current.replace(task);
+    let result = f();
+    PROCESSORS.this_cpu().current = previous;
+    result
+}
+
+/// Get the pid of the current task's process, the pid of its main thread
+pub fn current_process_pid() -> usize {
+    TASK_MANAGER.current_task().process.pid
+}
+
+/// Get the tid of the current task within its process
+pub fn current_tid() -> usize {
+    TASK_MANAGER.current_task().tid
+}
+
+/// Get the pid of the current task's parent, if it has one
+pub fn parent_pid() -> Option<usize> {
+    TASK_MANAGER.get_parent_pid()
+}
+
 /// Get the current 'Running' task's token.
 pub fn current_user_token() -> usize {
     TASK_MANAGER.get_current_token()
@@ -301,27 +1498,321 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
     TASK_MANAGER.get_current_trap_cx()
 }
 
+/// Get where the current 'Running' task's trap context is mapped in user space.
+pub fn current_trap_cx_user_va() -> usize {
+    TASK_MANAGER.get_current_trap_cx_user_va()
+}
+
+/// Read the current task and its process through `f` as one consistent snapshot
+pub fn inspect_current_task<R>(
+    f: impl FnOnce(&TaskControlBlockInner, &ProcessControlBlockInner) -> R,
+) -> R {
+    TASK_MANAGER.inspect_current(f)
+}
+
+/// The file open as the current task's descriptor `fd`
+pub fn current_file(fd: usize) -> Option<Arc<dyn File + Send + Sync>> {
+    TASK_MANAGER.current_file(fd)
+}
+
+/// Open `file` as the current task's lowest free descriptor
+pub fn install_file(file: Arc<dyn File + Send + Sync>) -> usize {
+    TASK_MANAGER.install_file(file)
+}
+
+/// Close the current task's descriptor `fd`, false if it was not open
+pub fn close_file(fd: usize) -> bool {
+    TASK_MANAGER.close_file(fd)
+}
+
+/// Make a semaphore with `count` permits for the current task, returns its id
+pub fn create_semaphore(count: usize) -> usize {
+    TASK_MANAGER.create_semaphore(count)
+}
+
+/// The current task's semaphore `id`
+pub fn current_semaphore(id: usize) -> Option<Arc<Semaphore>> {
+    TASK_MANAGER.current_semaphore(id)
+}
+
+/// Make a mutex for the current task, blocking or spinning, returns its id
+pub fn create_mutex(blocking: bool) -> usize {
+    TASK_MANAGER.create_mutex(blocking)
+}
+
+/// The current task's mutex `id`
+pub fn current_mutex(id: usize) -> Option<Arc<dyn Mutex>> {
+    TASK_MANAGER.current_mutex(id)
+}
+
+/// Make a condition variable for the current task, returns its id
+pub fn create_condvar() -> usize {
+    TASK_MANAGER.create_condvar()
+}
+
+/// The current task's condition variable `id`
+pub fn current_condvar(id: usize) -> Option<Arc<Condvar>> {
+    TASK_MANAGER.current_condvar(id)
+}
+
+/// Reap an exited child of the current task, see `TaskManager::wait_child`
+pub fn wait_child<R>(
+    pid: isize,
+    f: impl FnOnce(&TaskControlBlockInner, &ProcessControlBlockInner) -> R,
+) -> Result<Option<(usize, R)>, isize> {
+    TASK_MANAGER.wait_child(pid, f)
+}
+
+#[allow(unused)]
 /// Get current task's time
 pub fn get_current_task_time() -> usize {
     TASK_MANAGER.get_start_time() / 1000
 }
 
-/// Get all task's syscall times
-pub fn get_syscall_times() -> [u32; 500] {
+#[allow(unused)]
+/// Get the cpu time used by the current task in ms, including the running time slice
+pub fn get_current_task_cpu_time() -> usize {
+    TASK_MANAGER.get_cpu_time() / 1000
+}
+
+#[allow(unused)]
+/// Get the current task's syscall times for ids below `MAX_SYSCALL_NUM`
+pub fn get_syscall_times() -> [u32; config::MAX_SYSCALL_NUM] {
     TASK_MANAGER.get_syscall_times()
 }
 
-/// Update task's syscall times
-pub fn update_syscall_times(id: usize) {
-    TASK_MANAGER.update_syscall_times(id);
+#[allow(unused)]
+/// Get how often the current task issued syscall `id`
+pub fn get_syscall_count(id: usize) -> u32 {
+    TASK_MANAGER.get_syscall_count(id)
+}
+
+#[allow(unused)]
+/// Get how many of the current task's syscalls had ids past the
+/// `MAX_SYSCALL_IDS_COUNTED` it keeps a count for
+pub fn get_syscall_overflow() -> u32 {
+    TASK_MANAGER.get_syscall_overflow()
+}
+
+/// Update task's syscall times, true if the task's syscalls are to be logged
+pub fn update_syscall_times(id: usize) -> bool {
+    TASK_MANAGER.update_syscall_times(id)
+}
+
+/// Turn logging the syscalls of task `pid` on or off, false if there is no such task
+pub fn set_strace(pid: usize, enable: bool) -> bool {
+    TASK_MANAGER.set_strace(pid, enable)
+}
+
+/// Start the app `elf_data` as a new task with `args`, returns its pid
+pub fn spawn(elf_data: &'static [u8], args: &[String]) -> usize {
+    TASK_MANAGER.spawn(elf_data, args)
+}
+
+/// Copy the current task into a new child task, returns the child's pid or
+/// `None` if there is no room for the copy
+pub fn fork() -> Option<usize> {
+    TASK_MANAGER.fork()
+}
+
+/// Replace the current task's program with the app `elf_data`, whose
+/// `main` gets `args` and `envs`; false if the current task is not a main thread
+pub fn exec(elf_data: &'static [u8], args: &[String], envs: &[String]) -> bool {
+    TASK_MANAGER.exec(elf_data, args, envs)
+}
+
+/// Start a thread of the current process at `entry` with `arg`, returns its tid
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    TASK_MANAGER.thread_create(entry, arg)
+}
+
+/// Join thread `tid` of the current process, see `TaskManager::waittid`
+pub fn waittid(tid: usize) -> isize {
+    TASK_MANAGER.waittid(tid)
+}
+
+/// Turn timer preemption of the current task off (`true`) or back on.
+pub fn set_current_batch(batch: bool) {
+    TASK_MANAGER.set_batch(batch);
+}
+
+/// Set current task's stride scheduling priority
+pub fn set_current_priority(priority: usize) {
+    TASK_MANAGER.set_priority(priority);
+}
+
+/// Set the scheduling policy used for every task
+pub fn set_sched_policy(policy: SchedPolicy) {
+    TASK_MANAGER.set_policy(policy);
 }
 
 /// mmap
-pub fn mmap(start: usize, len: usize, port: usize) -> isize {
-    TASK_MANAGER.mmap(start, len, port)
+pub fn mmap(start: usize, len: usize, port: usize, fd: isize, offset: usize) -> isize {
+    TASK_MANAGER.mmap(start, len, port, fd, offset)
 }
 
 /// munmap
 pub fn munmap(start: usize, len: usize) -> isize {
     TASK_MANAGER.munmap(start, len)
-}
\ No newline at end of file
+}
+
+/// Change the permission of a mapped range of the current task
+pub fn mprotect(start: usize, len: usize, port: usize) -> isize {
+    TASK_MANAGER.mprotect(start, len, port)
+}
+
+/// Grow or shrink the current task's heap, returns the previous program break
+pub fn sbrk(increment: isize) -> isize {
+    TASK_MANAGER.sbrk(increment)
+}
+
+/// Attach shared memory segment `id` to the current task, returns the address
+pub fn shmat(id: usize, addr: usize, read_only: bool) -> isize {
+    TASK_MANAGER.shmat(id, addr, read_only)
+}
+
+/// Detach the shared memory segment the current task attached at `addr`
+pub fn shmdt(addr: usize) -> isize {
+    TASK_MANAGER.shmdt(addr)
+}
+
+/// Back the lazy pages of a user buffer before the kernel reads (`R`) or writes (`W`) it
+pub fn populate_user_buffer(ptr: usize, len: usize, access: mm::MapPermission) {
+    TASK_MANAGER.populate_user_buffer(ptr, len, access)
+}
+
+/// The frame behind the current task's user page holding `va`, see
+/// `TaskManager::current_user_frame`
+pub fn current_user_frame(va: usize) -> Option<Arc<mm::FrameTracker>> {
+    TASK_MANAGER.current_user_frame(va)
+}
+
+#[allow(unused)]
+/// The frame behind the current task's user page holding `va`, shared with
+/// no other process, see `TaskManager::current_private_frame`
+pub fn current_private_frame(va: usize) -> Option<Arc<mm::FrameTracker>> {
+    TASK_MANAGER.current_private_frame(va)
+}
+
+/// Print the address space of the current task, e.g. before killing it
+pub fn dump_current_memory_set() {
+    TASK_MANAGER.dump_current_memory_set();
+}
+
+/// Print the current task's memory around `va`, rows on unmapped pages are
+/// marked instead of read
+pub fn dump_user_memory(va: usize) {
+    let token = current_user_token();
+    let start = (va & !0xf).saturating_sub(16);
+    println!("[kernel] user memory around {:#x}:", va);
+    for row in 0..3 {
+        let addr = match start.checked_add(row * 16) {
+            Some(addr) => addr,
+            None => break,
+        };
+        let mut bytes = [0u8; 16];
+        if mm::nofault_copy_from(token, addr, &mut bytes) == 0 {
+            println!("  {:#018x}: (unmapped)", addr);
+        } else {
+            println!("  {:#018x}: {:02x?}", addr, bytes);
+        }
+    }
+}
+
+/// Describe why an access of the current task to `va` faulted
+pub fn fault_reason(va: usize) -> &'static str {
+    TASK_MANAGER.fault_reason(va)
+}
+
+/// The current task's leaf page table entry for `va`, for fault reports
+pub fn current_pte(va: usize) -> Option<mm::PageTableEntry> {
+    TASK_MANAGER.current_pte(va)
+}
+
+/// Try to resolve a page fault of an `access` at `va` for the current task,
+/// `PageFault::Invalid` means the task has to be killed.
+pub fn handle_page_fault(va: usize, access: mm::MapPermission) -> mm::PageFault {
+    TASK_MANAGER.handle_page_fault(va, access)
+}
+
+#[allow(unused)]
+#[test_case]
+/// a task that never ran must start in trap_return on top of its own kernel stack
+pub fn task_context_test() {
+    let (_, top) = config::kernel_stack_position(0);
+    let cx = TaskContext::goto_trap_return(top);
+    assert_eq!(cx.ra(), crate::trap::trap_return as usize);
+    assert_eq!(cx.sp(), top);
+    let inner = TASK_MANAGER.inner.lock();
+    inner.scheduler.for_each(&mut |task| {
+        let (bottom, top) = config::kernel_stack_position(task.getpid());
+        let task = task.inner_exclusive_access();
+        if task.start_time != 0 {
+            return;
+        }
+        assert_eq!(task.task_cx.ra(), crate::trap::trap_return as usize, "{:?}", task.task_cx);
+        assert!(bottom < task.task_cx.sp() && task.task_cx.sp() <= top, "{:?}", task.task_cx);
+    });
+    info!("task_context_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a broken scheduler that always picks task 0 must get task 1 reported
+pub fn starvation_audit_test() {
+    let threshold = config::STARVATION_THRESHOLD_US;
+    // never dispatched, the tasks go with the manager at the end
+    let tasks = [
+        Arc::new(TaskControlBlock::new(get_app_data(0))),
+        Arc::new(TaskControlBlock::new(get_app_data(0))),
+    ];
+    let manager = TaskManager {
+        inner: SpinNoIrqLock::new(TaskManagerInner {
+            scheduler: new_scheduler(SchedPolicy::RoundRobin),
+            blocked: Vec::new(),
+            hooks: HookRegistry::new(),
+        }),
+    };
+    for task in tasks.iter() {
+        manager.inner.lock().scheduler.add_task(task.clone());
+    }
+    let mut now = 0;
+    let mut reported = Vec::new();
+    for _ in 0..100 {
+        now += threshold / 10;
+        tasks[0].inner_exclusive_access().last_scheduled = now;
+        reported.extend(manager.audit_starvation(now));
+    }
+    assert!(!reported.is_empty());
+    assert!(reported.iter().all(|pid| *pid == tasks[1].getpid()));
+    // right at the threshold is not starved yet, and only Ready tasks starve
+    tasks[1].inner_exclusive_access().last_scheduled = now - threshold;
+    assert!(manager.audit_starvation(now).is_empty());
+    let mut inner = tasks[1].inner_exclusive_access();
+    inner.last_scheduled = 0;
+    inner.task_status = TaskStatus::Blocked;
+    drop(inner);
+    assert!(manager.audit_starvation(now).is_empty());
+    info!("starvation_audit_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// the token of a task whose page table root got corrupted fails the check
+/// `current_user_token` asserts, and passes again once the root is back
+pub fn corrupted_token_test() {
+    // never dispatched, only lent as the current task
+    let task = Arc::new(TaskControlBlock::new(get_app_data(0)));
+    with_current_task(task.clone(), || {
+        let token = TASK_MANAGER.checked_current_token().unwrap();
+        // ppn 0 is below the kernel image, no page table ever lives there
+        let mut process = task.process.inner_exclusive_access();
+        let root = process.memory_set.replace_root(mm::PhysPageNum(0));
+        drop(process);
+        let corrupted = TASK_MANAGER.checked_current_token();
+        task.process.inner_exclusive_access().memory_set.replace_root(root);
+        assert_eq!(corrupted, Err(token & !((1usize << 44) - 1)));
+        assert_eq!(TASK_MANAGER.checked_current_token(), Ok(token));
+    });
+    info!("corrupted_token_test passed!");
+}
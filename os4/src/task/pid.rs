@@ -0,0 +1,76 @@
+//! Process ids, handed out by [`pid_alloc`] and given back when the
+//! [`PidHandle`] is dropped
+
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Hands out the lowest id never used before, or one given back earlier.
+pub struct PidAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl PidAllocator {
+    pub const fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    pub fn alloc(&mut self) -> usize {
+        if let Some(pid) = self.recycled.pop() {
+            pid
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    pub fn dealloc(&mut self, pid: usize) {
+        assert!(pid < self.current, "pid {} was never allocated", pid);
+        assert!(
+            !self.recycled.contains(&pid),
+            "pid {} has been deallocated!",
+            pid
+        );
+        self.recycled.push(pid);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
+        unsafe { UPSafeCell::new(PidAllocator::new()) };
+}
+
+/// A pid in use, given back to the allocator on drop.
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Allocate a pid.
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+#[allow(unused)]
+#[test_case]
+/// freed pids are handed out again before new ones
+pub fn pid_allocator_test() {
+    let mut allocator = PidAllocator::new();
+    assert_eq!(allocator.alloc(), 0);
+    assert_eq!(allocator.alloc(), 1);
+    assert_eq!(allocator.alloc(), 2);
+    allocator.dealloc(1);
+    assert_eq!(allocator.alloc(), 1);
+    assert_eq!(allocator.alloc(), 3);
+    // the global allocator, through handles
+    let pid = pid_alloc();
+    let value = pid.0;
+    drop(pid);
+    assert_eq!(pid_alloc().0, value);
+    info!("pid_allocator_test passed!");
+}
@@ -0,0 +1,107 @@
+//! What the threads of a process share
+
+use super::TaskControlBlock;
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::MemorySet;
+use crate::sync::{Condvar, Mutex, Semaphore, UPSafeCell};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// The address space, open files and synchronization objects of a process,
+/// held by each of its threads.
+pub struct ProcessControlBlock {
+    /// the pid of the main thread, what getpid returns in every thread
+    pub pid: usize,
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// The mutable part of a process, behind `ProcessControlBlock::inner_exclusive_access`.
+pub struct ProcessControlBlockInner {
+    pub memory_set: MemorySet,
+    /// top of the main thread's user stack
+    pub base_size: usize,
+    /// bytes currently mapped through mmap, counted against `MMAP_QUOTA_BYTES`
+    pub mmap_bytes: usize,
+
+    /// the process that forked or spawned this one, `None` for apps loaded
+    /// at boot and once the parent exited
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// main threads of the children that were not waited for yet, exited
+    /// ones stay here until they are reaped and hold the only reference left to them
+    pub children: Vec<Arc<TaskControlBlock>>,
+
+    /// open files by descriptor, closed descriptors are `None` until reused
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// semaphores by id, shared with the children forked after they were made
+    pub semaphores: Vec<Arc<Semaphore>>,
+    /// mutexes by id, shared like the semaphores
+    pub mutexes: Vec<Arc<dyn Mutex>>,
+    /// condition variables by id, shared like the semaphores
+    pub condvars: Vec<Arc<Condvar>>,
+
+    /// the threads `thread_create` made, by tid; slot 0 is the main thread's
+    /// and stays `None`. Exited threads stay until they are joined, and the
+    /// table is emptied when the process exits.
+    pub threads: Vec<Option<Arc<TaskControlBlock>>>,
+}
+
+impl ProcessControlBlock {
+    /// A process of main thread `pid` around `memory_set`, with only the
+    /// standard streams open.
+    pub fn new(pid: usize, memory_set: MemorySet, base_size: usize) -> Self {
+        Self {
+            pid,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    memory_set,
+                    base_size,
+                    mmap_bytes: 0,
+                    parent: None,
+                    children: Vec::new(),
+                    fd_table: alloc::vec![
+                        // 0 -> stdin
+                        Some(Arc::new(Stdin)),
+                        // 1 -> stdout
+                        Some(Arc::new(Stdout)),
+                        // 2 -> stderr
+                        Some(Arc::new(Stdout)),
+                    ],
+                    semaphores: Vec::new(),
+                    mutexes: Vec::new(),
+                    condvars: Vec::new(),
+                    threads: alloc::vec![None],
+                })
+            },
+        }
+    }
+    #[track_caller]
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+}
+
+impl ProcessControlBlockInner {
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// The lowest free descriptor, the table grows if every one is taken.
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    /// The lowest free tid above the main thread's, the table grows if every
+    /// one is taken.
+    pub fn alloc_tid(&mut self) -> usize {
+        if let Some(tid) = (1..self.threads.len()).find(|tid| self.threads[*tid].is_none()) {
+            tid
+        } else {
+            self.threads.push(None);
+            self.threads.len() - 1
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! What each hart keeps of the scheduling state
+
+use super::{TaskContext, TaskControlBlock};
+use crate::sync::PerCpu;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// What a hart did since boot, printed at shutdown.
+#[derive(Copy, Clone, Default)]
+pub struct CpuCounters {
+    /// tasks switched to
+    pub dispatches: usize,
+    /// traps taken from user mode
+    pub traps: usize,
+    /// times the hart woke up from wfi with nothing to run
+    pub idle_wakeups: usize,
+}
+
+/// The task a hart runs and where it goes when the task gives up the hart.
+pub struct Processor {
+    /// the task whose kernel stack the hart is on, `None` while it idles
+    pub current: Option<Arc<TaskControlBlock>>,
+    /// an orphan that exited on this hart, it cannot be freed while the hart
+    /// still runs on its kernel stack
+    pub dead: Option<Arc<TaskControlBlock>>,
+    /// the hart's scheduling loop on its boot stack, `run_next_task` switches
+    /// back to it so no task's kernel stack is in use once it is queued
+    pub idle_task_cx: TaskContext,
+    pub counters: CpuCounters,
+}
+
+impl Processor {
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            dead: None,
+            idle_task_cx: TaskContext::zero_init(),
+            counters: CpuCounters::default(),
+        }
+    }
+}
+
+lazy_static! {
+    /// the processor of each hart, by hart id
+    pub static ref PROCESSORS: PerCpu<Processor> = PerCpu::new(Processor::new);
+}
+
+/// The task running on this hart, panics while the hart idles.
+pub fn current_task() -> Arc<TaskControlBlock> {
+    PROCESSORS.this_cpu().current.clone().unwrap()
+}
+
+/// Print the counters of every hart that did anything.
+pub fn report_counters() {
+    for (hart, processor) in PROCESSORS.iter().enumerate() {
+        let counters = processor.counters;
+        if counters.dispatches == 0 {
+            continue;
+        }
+        println!(
+            "[kernel] hart {}: {} dispatches, {} traps, {} idle wakeups",
+            hart, counters.dispatches, counters.traps, counters.idle_wakeups
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Scheduling policies behind one [`Scheduler`] trait
+//!
+//! The task manager only hands `Ready` tasks to a scheduler and asks it for
+//! the next one, so a new policy is a new implementation of the trait. The
+//! policy the kernel boots with is picked by cargo feature: stride by
+//! default, round-robin with `sched_rr`, MLFQ with `sched_mlfq`.
+//! `sys_sched_setscheduler` can still switch policies at run time.
+
+use super::task::{pick_next, SchedPolicy};
+use super::TaskControlBlock;
+use crate::config::{MLFQ_BASE_SLICE_TICKS, MLFQ_BOOST_TICKS, MLFQ_LEVELS};
+use crate::loader::get_app_data;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Holds the `Ready` tasks and decides which one runs next, behind the task
+/// manager's lock whichever hart asks.
+pub trait Scheduler: Send {
+    /// Queue a task that just became `Ready`.
+    fn add_task(&mut self, task: Arc<TaskControlBlock>);
+    /// Take the task to run next out of the queue, `None` if none is ready.
+    fn fetch_task(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// A timer tick interrupted `current`, returns whether it should give
+    /// up the cpu.
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool;
+    /// Run `f` on every queued task, in no particular order.
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>));
+}
+
+/// Tasks run in the order they became ready, one time slice each.
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn add_task(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch_task(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>)) {
+        self.ready_queue.iter().for_each(f);
+    }
+}
+
+/// The task with the smallest pass runs next and has its pass advanced by
+/// its stride, so tasks get the cpu in proportion to their priority.
+pub struct StrideScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn add_task(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch_task(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let num = self.ready_queue.len();
+        let queue = &self.ready_queue;
+        // looking after the last task starts at the front, so ties go to
+        // the task that has been ready the longest
+        let idx = pick_next(
+            SchedPolicy::Stride,
+            num.checked_sub(1)?,
+            num,
+            |_| true,
+            |idx| queue[idx].inner_exclusive_access().pass,
+        )?;
+        let task = self.ready_queue.remove(idx)?;
+        let mut inner = task.inner_exclusive_access();
+        inner.pass = inner.pass.wrapping_add(inner.stride());
+        drop(inner);
+        Some(task)
+    }
+    fn tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>)) {
+        self.ready_queue.iter().for_each(f);
+    }
+}
+
+/// Where a task stands under the MLFQ scheduler.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MlfqState {
+    /// the queue the task goes back to, 0 runs first
+    pub level: usize,
+    /// timer ticks used at `level`, giving up the cpu early does not reset
+    /// them, so yielding right before the slice ends does not keep a level
+    pub used_ticks: usize,
+}
+
+impl MlfqState {
+    pub const fn new() -> Self {
+        Self {
+            level: 0,
+            used_ticks: 0,
+        }
+    }
+}
+
+/// The time slice at MLFQ `level`, in timer ticks.
+fn mlfq_slice(level: usize) -> usize {
+    MLFQ_BASE_SLICE_TICKS << level
+}
+
+/// Multi-level feedback queue: tasks start at the top level with short
+/// slices and sink a level each time they use a slice up, so interactive
+/// tasks stay ahead of cpu-bound ones. Every `MLFQ_BOOST_TICKS` ticks all
+/// tasks go back to the top, nothing starves at the bottom for long.
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; MLFQ_LEVELS],
+    /// timer ticks since the last boost
+    ticks: usize,
+}
+
+impl MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            ticks: 0,
+        }
+    }
+    /// Lift every task, the running `current` included, to level 0 with a
+    /// fresh slice.
+    fn boost(&mut self, current: &Arc<TaskControlBlock>) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter().chain(core::iter::once(current)) {
+            task.inner_exclusive_access().mlfq = MlfqState::new();
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn add_task(&mut self, task: Arc<TaskControlBlock>) {
+        let level = task.inner_exclusive_access().mlfq.level;
+        self.queues[level].push_back(task);
+    }
+    fn fetch_task(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks >= MLFQ_BOOST_TICKS {
+            self.ticks = 0;
+            self.boost(current);
+        }
+        let mut task = current.inner_exclusive_access();
+        task.mlfq.used_ticks += 1;
+        if task.mlfq.used_ticks < mlfq_slice(task.mlfq.level) {
+            return false;
+        }
+        // the slice is used up: one level down, and someone else runs
+        task.mlfq = MlfqState {
+            level: (task.mlfq.level + 1).min(MLFQ_LEVELS - 1),
+            used_ticks: 0,
+        };
+        true
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&Arc<TaskControlBlock>)) {
+        self.queues.iter().flatten().for_each(f);
+    }
+}
+
+/// A scheduler for `policy` with nothing queued yet.
+pub fn new_scheduler(policy: SchedPolicy) -> Box<dyn Scheduler> {
+    match policy {
+        SchedPolicy::RoundRobin => Box::new(RoundRobinScheduler::new()),
+        SchedPolicy::Stride => Box::new(StrideScheduler::new()),
+        SchedPolicy::Mlfq => Box::new(MlfqScheduler::new()),
+    }
+}
+
+/// The policy the kernel boots with.
+#[cfg(feature = "sched_mlfq")]
+pub const BOOT_POLICY: SchedPolicy = SchedPolicy::Mlfq;
+/// The policy the kernel boots with.
+#[cfg(all(feature = "sched_rr", not(feature = "sched_mlfq")))]
+pub const BOOT_POLICY: SchedPolicy = SchedPolicy::RoundRobin;
+/// The policy the kernel boots with.
+#[cfg(not(any(feature = "sched_rr", feature = "sched_mlfq")))]
+pub const BOOT_POLICY: SchedPolicy = SchedPolicy::Stride;
+
+#[allow(unused)]
+#[test_case]
+/// round-robin keeps the queue order, stride favors the higher priority
+pub fn scheduler_test() {
+    // never dispatched, the tasks are freed at the end
+    let tasks = [
+        Arc::new(TaskControlBlock::new(get_app_data(0))),
+        Arc::new(TaskControlBlock::new(get_app_data(0))),
+    ];
+    let pids = [tasks[0].getpid(), tasks[1].getpid()];
+
+    let mut rr = new_scheduler(SchedPolicy::RoundRobin);
+    for task in tasks.iter() {
+        rr.add_task(task.clone());
+    }
+    let mut order = [0; 4];
+    for slot in order.iter_mut() {
+        let task = rr.fetch_task().unwrap();
+        *slot = task.getpid();
+        rr.add_task(task);
+    }
+    assert_eq!(order, [pids[0], pids[1], pids[0], pids[1]]);
+    while rr.fetch_task().is_some() {}
+
+    // task 1 gets four times the priority of task 0
+    tasks[0].inner_exclusive_access().priority = 4;
+    tasks[1].inner_exclusive_access().priority = 16;
+    let mut stride = new_scheduler(SchedPolicy::Stride);
+    for task in tasks.iter() {
+        stride.add_task(task.clone());
+    }
+    let mut runs = [0; 2];
+    for _ in 0..50 {
+        let task = stride.fetch_task().unwrap();
+        runs[if task.getpid() == pids[0] { 0 } else { 1 }] += 1;
+        assert!(stride.tick(&task));
+        stride.add_task(task);
+    }
+    assert!(runs[1] >= runs[0] * 3, "{:?}", runs);
+    let mut queued = 0;
+    stride.for_each(&mut |_| queued += 1);
+    assert_eq!(queued, 2);
+    while stride.fetch_task().is_some() {}
+    assert!(stride.fetch_task().is_none());
+    info!("scheduler_test passed!");
+}
+
+#[allow(unused)]
+#[test_case]
+/// a task that uses up its slices sinks below one that does not, and the
+/// boost brings it back up
+pub fn mlfq_test() {
+    let hog = Arc::new(TaskControlBlock::new(get_app_data(0)));
+    let light = Arc::new(TaskControlBlock::new(get_app_data(0)));
+    // run `task` for a whole slice at its level
+    let use_slice = |mlfq: &mut MlfqScheduler, task: &Arc<TaskControlBlock>| {
+        let level = task.inner_exclusive_access().mlfq.level;
+        for _ in 1..mlfq_slice(level) {
+            assert!(!mlfq.tick(task));
+        }
+        assert!(mlfq.tick(task));
+    };
+    let mut mlfq = MlfqScheduler::new();
+    mlfq.add_task(hog.clone());
+    mlfq.add_task(light.clone());
+    // the hog runs first, uses up its slice and sinks
+    let task = mlfq.fetch_task().unwrap();
+    assert!(Arc::ptr_eq(&task, &hog));
+    use_slice(&mut mlfq, &task);
+    assert_eq!(hog.inner_exclusive_access().mlfq.level, 1);
+    mlfq.add_task(task);
+    // a task that gives up the cpu early stays on top
+    for _ in 0..3 {
+        let task = mlfq.fetch_task().unwrap();
+        assert!(Arc::ptr_eq(&task, &light));
+        mlfq.add_task(task);
+    }
+    // with the top level empty the hog gets its longer slice
+    let light_task = mlfq.fetch_task().unwrap();
+    let task = mlfq.fetch_task().unwrap();
+    assert!(Arc::ptr_eq(&task, &hog));
+    use_slice(&mut mlfq, &task);
+    assert_eq!(hog.inner_exclusive_access().mlfq.level, 2);
+    mlfq.add_task(task);
+    // the boost lifts the queued hog back to level 0
+    mlfq.ticks = MLFQ_BOOST_TICKS - 1;
+    mlfq.tick(&light_task);
+    assert_eq!(hog.inner_exclusive_access().mlfq, MlfqState::new());
+    assert_eq!(mlfq.queues[0].len(), 1);
+    assert_eq!(mlfq.ticks, 0);
+    mlfq.add_task(light_task);
+    let mut queued = 0;
+    mlfq.for_each(&mut |_| queued += 1);
+    assert_eq!(queued, 2);
+    info!("mlfq_test passed!");
+}
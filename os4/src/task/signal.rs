@@ -0,0 +1,326 @@
+//! Signals: what a task has pending, blocks and does on each signal
+//!
+//! Signals are acted on when the task goes back to user mode. A signal with
+//! a handler makes the task run the handler with the signal number in a0,
+//! on the interrupted stack, and the handler ends with `sys_sigreturn`,
+//! which puts back the interrupted context. Only one handler runs at a time.
+//! SIGKILL, SIGSTOP and SIGCONT work the same for every task: SIGKILL ends
+//! it, SIGSTOP stops it until a SIGCONT, neither can be caught or blocked.
+
+use crate::trap::TrapContext;
+use bitflags::*;
+
+/// Signal numbers go from 1 to `MAX_SIG`.
+pub const MAX_SIG: usize = 31;
+
+/// `SignalAction::handler` that kills the task or ignores the signal,
+/// depending on the signal
+pub const SIG_DFL: usize = 0;
+/// `SignalAction::handler` that ignores the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, signal `n` is bit `n`.
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The set holding only signal `signum`, `None` outside 1..=`MAX_SIG`.
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if (1..=MAX_SIG).contains(&signum) {
+            Self::from_bits(1 << signum)
+        } else {
+            None
+        }
+    }
+    /// The number of the lowest signal in the set.
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+    /// The signals whose behavior a task cannot change.
+    pub fn fixed() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+}
+
+/// What a task does on a signal, the layout `sys_sigaction` uses.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SignalAction {
+    /// address of the handler, or `SIG_DFL` or `SIG_IGN`
+    pub handler: usize,
+    /// signals blocked while the handler runs, on top of the signal itself
+    pub mask: SignalFlags,
+}
+
+impl SignalAction {
+    pub const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        mask: SignalFlags::empty(),
+    };
+}
+
+/// What happens on `signum` if the task has no handler for it: these are
+/// ignored, the rest kill the task.
+fn ignored_by_default(signum: usize) -> bool {
+    let ignored =
+        SignalFlags::SIGCHLD | SignalFlags::SIGCONT | SignalFlags::SIGURG | SignalFlags::SIGWINCH;
+    SignalFlags::from_signum(signum).map_or(false, |sig| ignored.contains(sig))
+}
+
+/// What the kernel has to do before the task can go back to user mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SignalDelivery {
+    /// the task dies of this signal
+    Terminate(usize),
+    /// run the handler of this signal
+    Handle(usize, SignalAction),
+}
+
+/// The interrupted user context a handler returns to.
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    pub trap_cx: TrapContext,
+    pub mask: SignalFlags,
+}
+
+/// The signal state of a task.
+#[derive(Copy, Clone)]
+pub struct SignalState {
+    /// signals sent but not acted on yet
+    pub pending: SignalFlags,
+    /// signals held back, never includes SIGKILL or SIGSTOP
+    pub mask: SignalFlags,
+    /// indexed by signal number, entry 0 is unused
+    pub actions: [SignalAction; MAX_SIG + 1],
+    /// stopped by SIGSTOP until a SIGCONT
+    pub stopped: bool,
+    /// saved while a handler runs, for `sys_sigreturn`
+    pub frame: Option<SignalFrame>,
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            mask: SignalFlags::empty(),
+            actions: [SignalAction::DEFAULT; MAX_SIG + 1],
+            stopped: false,
+            frame: None,
+        }
+    }
+    /// Mark `signum` pending. SIGCONT continues the task right away and
+    /// drops a pending SIGSTOP, SIGSTOP drops a pending SIGCONT.
+    pub fn send(&mut self, signum: usize) {
+        let sig = SignalFlags::from_signum(signum).unwrap();
+        if sig == SignalFlags::SIGCONT {
+            self.stopped = false;
+            self.pending.remove(SignalFlags::SIGSTOP);
+        } else if sig == SignalFlags::SIGSTOP {
+            self.pending.remove(SignalFlags::SIGCONT);
+        }
+        self.pending.insert(sig);
+    }
+    pub fn killed(&self) -> bool {
+        self.pending.contains(SignalFlags::SIGKILL)
+    }
+    /// Whether `next_delivery` may have anything to do. Masked signals count
+    /// too, that only costs the fast syscall path its shortcut.
+    pub fn any_pending(&self) -> bool {
+        !self.pending.is_empty() || self.stopped
+    }
+    /// Take the next signal to act on. Ignored signals are dropped on the way,
+    /// a pending SIGSTOP stops the task, and a stopped task acts on nothing
+    /// but SIGKILL. Signals with a handler wait while another handler runs.
+    pub fn next_delivery(&mut self) -> Option<SignalDelivery> {
+        if self.killed() {
+            return Some(SignalDelivery::Terminate(SignalFlags::SIGKILL.signum()));
+        }
+        if self.pending.contains(SignalFlags::SIGSTOP) {
+            self.pending.remove(SignalFlags::SIGSTOP);
+            self.stopped = true;
+        }
+        if self.stopped {
+            return None;
+        }
+        for signum in 1..=MAX_SIG {
+            let sig = SignalFlags::from_signum(signum).unwrap();
+            if !self.pending.contains(sig) || self.mask.contains(sig) {
+                continue;
+            }
+            let action = self.actions[signum];
+            match action.handler {
+                SIG_IGN => self.pending.remove(sig),
+                SIG_DFL if ignored_by_default(signum) => self.pending.remove(sig),
+                SIG_DFL => return Some(SignalDelivery::Terminate(signum)),
+                _ if self.frame.is_some() => {}
+                _ => {
+                    self.pending.remove(sig);
+                    return Some(SignalDelivery::Handle(signum, action));
+                }
+            }
+        }
+        None
+    }
+    /// Switch the task to the handler of `signum`, saving `trap_cx` and the
+    /// mask for `sigreturn`.
+    pub fn enter_handler(
+        &mut self,
+        signum: usize,
+        action: SignalAction,
+        trap_cx: &mut TrapContext,
+    ) {
+        self.frame = Some(SignalFrame {
+            trap_cx: *trap_cx,
+            mask: self.mask,
+        });
+        let sig = SignalFlags::from_signum(signum).unwrap();
+        self.mask = (self.mask | action.mask | sig) - SignalFlags::fixed();
+        trap_cx.sepc = action.handler;
+        trap_cx.x[10] = signum;
+    }
+    /// Back from a handler: put back the saved context and mask, false
+    /// outside a handler.
+    pub fn sigreturn(&mut self, trap_cx: &mut TrapContext) -> bool {
+        match self.frame.take() {
+            Some(frame) => {
+                *trap_cx = frame.trap_cx;
+                self.mask = frame.mask;
+                true
+            }
+            None => false,
+        }
+    }
+    /// The state a forked child starts with: the same actions and mask,
+    /// nothing pending. A child forked in a handler returns from it too.
+    pub fn fork(&self) -> Self {
+        Self {
+            actions: self.actions,
+            mask: self.mask,
+            frame: self.frame,
+            ..Self::new()
+        }
+    }
+    /// Handlers live in the old address space, exec sets them back to the
+    /// default. Ignored signals stay ignored.
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::DEFAULT;
+            }
+        }
+        self.frame = None;
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// handlers, masks, ignored signals and the fixed SIGKILL/SIGSTOP/SIGCONT
+pub fn signal_delivery_test() {
+    let sigusr1 = SignalFlags::SIGUSR1.signum();
+    let sigusr2 = SignalFlags::SIGUSR2.signum();
+    let sigkill = SignalFlags::SIGKILL.signum();
+    let sigstop = SignalFlags::SIGSTOP.signum();
+    let mut state = SignalState::new();
+    assert_eq!(state.next_delivery(), None);
+    assert!(!state.any_pending());
+    // default actions: SIGCHLD is ignored, SIGUSR1 kills
+    state.send(SignalFlags::SIGCHLD.signum());
+    assert_eq!(state.next_delivery(), None);
+    assert!(state.pending.is_empty());
+    state.send(sigusr1);
+    assert_eq!(
+        state.next_delivery(),
+        Some(SignalDelivery::Terminate(sigusr1))
+    );
+
+    let mut state = SignalState::new();
+    let action = SignalAction {
+        handler: 0x1000,
+        mask: SignalFlags::SIGUSR2,
+    };
+    state.actions[sigusr1] = action;
+    state.actions[sigusr2].handler = 0x2000;
+    // a blocked signal stays pending
+    state.mask = SignalFlags::SIGUSR1;
+    state.send(sigusr1);
+    assert_eq!(state.next_delivery(), None);
+    assert!(state.any_pending());
+    state.mask = SignalFlags::empty();
+    assert_eq!(
+        state.next_delivery(),
+        Some(SignalDelivery::Handle(sigusr1, action))
+    );
+    let mut cx: TrapContext = unsafe { core::mem::zeroed() };
+    cx.sepc = 0x42;
+    state.enter_handler(sigusr1, action, &mut cx);
+    assert_eq!((cx.sepc, cx.x[10]), (0x1000, sigusr1));
+    assert!(state
+        .mask
+        .contains(SignalFlags::SIGUSR1 | SignalFlags::SIGUSR2));
+    // no second handler while one runs
+    state.send(sigusr2);
+    assert_eq!(state.next_delivery(), None);
+    assert!(state.sigreturn(&mut cx));
+    assert_eq!(cx.sepc, 0x42);
+    assert!(state.mask.is_empty());
+    assert!(!state.sigreturn(&mut cx));
+    let next = state.next_delivery();
+    assert_eq!(
+        next,
+        Some(SignalDelivery::Handle(sigusr2, state.actions[sigusr2]))
+    );
+
+    // SIGSTOP stops until SIGCONT, SIGKILL goes through anyway
+    let mut state = SignalState::new();
+    state.actions[sigusr1].handler = SIG_IGN;
+    state.send(sigstop);
+    assert_eq!(state.next_delivery(), None);
+    assert!(state.stopped);
+    state.send(sigusr1);
+    assert_eq!(state.next_delivery(), None);
+    state.send(SignalFlags::SIGCONT.signum());
+    assert!(!state.stopped);
+    assert_eq!(state.next_delivery(), None);
+    state.send(sigstop);
+    state.send(sigkill);
+    assert_eq!(
+        state.next_delivery(),
+        Some(SignalDelivery::Terminate(sigkill))
+    );
+    assert_eq!(SignalFlags::from_signum(0), None);
+    assert_eq!(SignalFlags::from_signum(32), None);
+    info!("signal_delivery_test passed!");
+}
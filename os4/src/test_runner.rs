@@ -0,0 +1,29 @@
+//! Running the kernel's `#[test_case]` functions, `cargo ktest` from `os4`
+//!
+//! The test build boots like the kernel, calls `test_main` once memory and
+//! traps are set up, and ends qemu through the test finisher: status 0 once
+//! every test passed, the panic handler's 1 as soon as one fails.
+
+use crate::drivers::qemu_exit::exit_qemu;
+
+/// A test the runner can name before it runs it.
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        println!("[test] {} ...", core::any::type_name::<T>());
+        self();
+    }
+}
+
+/// Run every `#[test_case]` in the kernel, in the order they were found.
+pub fn run_tests(tests: &[&dyn Testable]) {
+    println!("[test] running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    println!("[test] all {} tests passed", tests.len());
+    exit_qemu(0);
+}
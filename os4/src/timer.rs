@@ -13,6 +13,23 @@ pub fn get_time_us() -> usize {
     time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
+/// Number of timer ticks since boot.
+pub fn get_tick() -> usize {
+    get_time() / (CLOCK_FREQ / TICKS_PER_SEC)
+}
+
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+#[allow(unused)]
+#[test_case]
+/// the clock must move forward and the tick count follow it
+pub fn timer_test() {
+    let (time, us, tick) = (get_time(), get_time_us(), get_tick());
+    while get_time() == time {}
+    assert!(get_time_us() >= us);
+    assert!(get_tick() >= tick);
+    assert_eq!(get_tick(), get_time() / (CLOCK_FREQ / TICKS_PER_SEC));
+    info!("timer_test passed!");
+}
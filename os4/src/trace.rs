@@ -0,0 +1,190 @@
+//! Static tracepoints into a ring per hart
+//!
+//! Task switches, syscalls, traps and frame allocations each leave a
+//! fixed-size [`TraceRecord`] in the ring of the hart they happen on, so
+//! harts never write the same ring. A full ring overwrites its oldest record.
+//! The rings are printed on a panic and drained by `sys_read_trace`.
+
+use crate::config::{MAX_HARTS, TRACE_LEN};
+use crate::smp::hart_id;
+use crate::sync::PerCpu;
+use crate::timer;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+/// the task of a record that has none, pids start at 0
+pub const NO_TASK: usize = usize::MAX;
+
+/// where a [`TraceRecord`] was taken, `args` depend on it
+#[repr(usize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TracePoint {
+    /// the hart switched from task `args[0]` to task `args[1]`, [`NO_TASK`]
+    /// is the hart's scheduling loop
+    Switch = 0,
+    /// `args` are the syscall id and its first argument
+    SyscallEnter = 1,
+    /// `args` are the syscall id and its return value
+    SyscallExit = 2,
+    /// a trap from user mode, `args` are scause and stval
+    Trap = 3,
+    /// `args[0]` is the physical page number
+    FrameAlloc = 4,
+    /// `args[0]` is the physical page number
+    FrameFree = 5,
+}
+
+/// one trace record, the layout `sys_read_trace` copies out
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TraceRecord {
+    /// in microseconds
+    pub time: usize,
+    pub hart: usize,
+    /// [`NO_TASK`] where no task is involved
+    pub task: usize,
+    pub point: TracePoint,
+    pub args: [usize; 2],
+}
+
+impl TraceRecord {
+    pub const fn empty() -> Self {
+        Self {
+            time: 0,
+            hart: 0,
+            task: 0,
+            point: TracePoint::Switch,
+            args: [0; 2],
+        }
+    }
+}
+
+/// ring of the last `N` records of one hart
+pub struct TraceRing<const N: usize> {
+    records: [TraceRecord; N],
+    /// index of the oldest record
+    head: usize,
+    len: usize,
+    /// records overwritten since boot
+    lost: usize,
+}
+
+impl<const N: usize> TraceRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            records: [TraceRecord::empty(); N],
+            head: 0,
+            len: 0,
+            lost: 0,
+        }
+    }
+    pub fn push(&mut self, record: TraceRecord) {
+        if self.len == N {
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+            self.lost += 1;
+        }
+        self.records[(self.head + self.len) % N] = record;
+        self.len += 1;
+    }
+    /// The `i`th oldest record.
+    fn get(&self, i: usize) -> TraceRecord {
+        self.records[(self.head + i) % N]
+    }
+    /// Move the oldest records into `out`, returns how many were written.
+    pub fn drain(&mut self, out: &mut [TraceRecord]) -> usize {
+        let count = out.len().min(self.len);
+        for (i, slot) in out[..count].iter_mut().enumerate() {
+            *slot = self.get(i);
+        }
+        self.head = (self.head + count) % N;
+        self.len -= count;
+        count
+    }
+}
+
+lazy_static! {
+    static ref TRACE: PerCpu<TraceRing<TRACE_LEN>> = PerCpu::new(TraceRing::new);
+}
+
+/// whether `TRACE` was set up, it allocates, and a panic may come before the heap
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+/// Leave a record of `point` for `task` in this hart's ring.
+pub fn record(point: TracePoint, task: usize, args: [usize; 2]) {
+    TRACE.this_cpu().push(TraceRecord {
+        time: timer::get_time_us(),
+        hart: hart_id(),
+        task,
+        point,
+        args,
+    });
+    TRACING.store(true, Ordering::Relaxed);
+}
+
+/// Drain the oldest records into `out`, hart 0's first, then hart 1's and so
+/// on. Returns how many records were written.
+pub fn drain(out: &mut [TraceRecord]) -> usize {
+    let mut written = 0;
+    for mut ring in TRACE.iter() {
+        written += ring.drain(&mut out[written..]);
+    }
+    written
+}
+
+/// Print the last `last` records of every hart, oldest first. A ring that is
+/// borrowed, say by a panic in the middle of a tracepoint, is skipped.
+pub fn dump(last: usize) {
+    if !TRACING.load(Ordering::Relaxed) {
+        return;
+    }
+    for hart in 0..MAX_HARTS {
+        let ring = match TRACE.try_get(hart) {
+            Some(ring) => ring,
+            None => continue,
+        };
+        if ring.len == 0 {
+            continue;
+        }
+        println!(
+            "[kernel] trace of hart {} ({} records lost):",
+            hart, ring.lost
+        );
+        for i in ring.len.saturating_sub(last)..ring.len {
+            let record = ring.get(i);
+            println!(
+                "  {:>10}us task {:<4} {:?} {:#x} {:#x}",
+                record.time, record.task as isize, record.point, record.args[0], record.args[1]
+            );
+        }
+    }
+}
+
+#[allow(unused)]
+#[test_case]
+/// records come out oldest first, and a wrapped ring keeps the newest
+pub fn trace_ring_test() {
+    let record = |arg| TraceRecord {
+        time: arg,
+        hart: 0,
+        task: 1,
+        point: TracePoint::SyscallEnter,
+        args: [arg, 0],
+    };
+    let mut ring: TraceRing<4> = TraceRing::new();
+    let mut out = [TraceRecord::empty(); 8];
+    for arg in 0..3 {
+        ring.push(record(arg));
+    }
+    assert_eq!(ring.drain(&mut out[..2]), 2);
+    assert_eq!((out[0].args[0], out[1].args[0]), (0, 1));
+    // 6 more on top of the one left: the three oldest are overwritten
+    for arg in 3..9 {
+        ring.push(record(arg));
+    }
+    assert_eq!(ring.lost, 3);
+    assert_eq!(ring.drain(&mut out), 4);
+    assert!(out[..4].iter().map(|record| record.args[0]).eq(5..9));
+    assert_eq!(ring.drain(&mut out), 0);
+    info!("trace_ring_test passed!");
+}
@@ -3,6 +3,7 @@
 use riscv::register::sstatus::{self, Sstatus, SPP};
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 /// trap context structure containing sstatus, sepc and registers
 pub struct TrapContext {
     pub x: [usize; 32],
@@ -11,12 +12,21 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    /// the hart id the kernel keeps in `tp`, set by `trap_return` for the
+    /// hart the task is about to run on
+    pub kernel_tp: usize,
 }
 
 impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    /// Hand `main(argc, argv, envp)` its arguments in a0-a2.
+    pub fn set_args(&mut self, argc: usize, argv: usize, envp: usize) {
+        self.x[10] = argc;
+        self.x[11] = argv;
+        self.x[12] = envp;
+    }
     pub fn app_init_context(
         entry: usize,
         sp: usize,
@@ -33,6 +43,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            kernel_tp: 0,
         };
         cx.set_sp(sp);
         cx
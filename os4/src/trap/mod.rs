@@ -6,23 +6,33 @@
 //! All traps go through `__alltraps`, which is defined in `trap.S`. The
 //! assembly language code does just enough work restore the kernel space
 //! context, ensuring that Rust code safely runs, and transfers control to
-//! [`trap_handler()`].
+//! [`trap_handler()`]. Read, write, yield, get_time and getpid take a fast
+//! path that leaves s0~s11 unsaved, `trap_handler` returns to the
+//! trampoline for them instead of going through [`trap_return()`].
 //!
 //! It then calls different functionality based on what exactly the exception
 //! was. For example, timer interrupts trigger task preemption, and syscalls go
 //! to [`syscall()`].
 mod context;
+mod report;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::{BATCH_CPU_LIMIT_US, TRAMPOLINE};
+use crate::eventlog::{self, EventKind};
+use crate::mm::{self, MapPermission, PageFault};
+use crate::smp::{self, hart_id};
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    account_trap_entry, account_trap_return, charge_kernel_time, current_pid, current_pte,
+    current_trap_cx, current_trap_cx_user_va, current_user_token, dump_current_memory_set,
+    dump_user_memory, exit_current_and_run_next, fault_reason, handle_page_fault, handle_signals,
+    on_timer_tick, signals_pending, suspend_current_and_run_next, TickAction,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{get_time_us, set_next_trigger};
+use crate::trace::{self, TracePoint};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sepc, sie, stval, stvec,
 };
 
 core::arch::global_asm!(include_str!("trap.S"));
@@ -49,32 +59,162 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// Take device interrupts from the PLIC on this hart.
+pub fn enable_external_interrupt() {
+    crate::drivers::plic::init_hart();
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// `__alltraps` saved every register, the trap leaves through [`trap_return()`]
+const ENTRY_FULL: usize = 0;
+/// A read, write, yield, get_time or getpid: s0~s11 are not saved, the Rust
+/// ABI keeps them in the registers as long as [`trap_handler()`] returns.
+const ENTRY_FAST: usize = 1;
+/// Back from a fast syscall that has to leave through [`trap_return()`]
+/// after all, the trampoline has saved s0~s11 by now.
+const ENTRY_FINISH: usize = 2;
+
+/// What [`trap_handler()`] hands `__fast_return` back in a0/a1.
+#[repr(C)]
+pub struct FastReturn {
+    /// the trap context at its user space address, or at its kernel space one
+    /// when `user_satp` is 0
+    trap_cx: usize,
+    /// 0 has the trampoline save s0~s11 and come back with [`ENTRY_FINISH`]
+    user_satp: usize,
+}
+
+/// `entry` is one of `ENTRY_FULL`, `ENTRY_FAST` and `ENTRY_FINISH`, only a
+/// fast syscall returns.
 #[no_mangle]
-pub fn trap_handler() -> ! {
+pub extern "C" fn trap_handler(entry: usize) -> FastReturn {
+    if entry == ENTRY_FINISH {
+        trap_return();
+    }
+    debug_assert!(entry == ENTRY_FULL || entry == ENTRY_FAST);
     set_kernel_trap_entry();
-    let cx = current_trap_cx();
+    smp::lock_kernel();
+    mm::expire_user_buffers();
+    account_trap_entry();
+    let mut cx = current_trap_cx();
     let scause = scause::read();
     let stval = stval::read();
+    trace::record(TracePoint::Trap, current_pid(), [scause.bits(), stval]);
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
+            let entered = get_time_us();
             cx.sepc += 4;
-            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
+            // exec frees the old trap context, so look it up again
+            cx = current_trap_cx();
+            cx.x[10] = result as usize;
+            // many short syscalls in a row must not hold the cpu past its share
+            if charge_kernel_time(entered) {
+                suspend_current_and_run_next();
+            }
+            if entry == ENTRY_FAST {
+                return fast_return();
+            }
+        }
+        Trap::Exception(
+            exception @ (Exception::StorePageFault
+            | Exception::LoadPageFault
+            | Exception::InstructionPageFault),
+        ) if handle_page_fault(stval, fault_access(exception)) != PageFault::Invalid => {
+            // a page got its frame, its own copy or the stack grew, retry the faulting instruction
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            error!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
-            exit_current_and_run_next();
+        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            // only this task dies, the others keep being scheduled
+            println!(
+                "[kernel] task {} killed: {:?} @ va={:#x} ({}), bad instruction = {:#x}",
+                current_pid(),
+                scause.cause(),
+                stval,
+                fault_reason(stval),
+                cx.sepc
+            );
+            println!("[kernel] {}", report::describe_cause(scause.cause()));
+            report::dump_trap_context(cx);
+            report::dump_pte(stval, current_pte(stval));
+            dump_current_memory_set();
+            dump_user_memory(stval);
+            eventlog::record(EventKind::Fault, current_pid(), stval);
+            exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            error!("[kernel] IllegalInstruction in application, core dumped.");
-            exit_current_and_run_next();
+            println!(
+                "[kernel] task {} killed: IllegalInstruction @ pc={:#x} (instruction {:#x})",
+                current_pid(),
+                cx.sepc,
+                stval
+            );
+            report::dump_trap_context(cx);
+            eventlog::record(EventKind::Fault, current_pid(), cx.sepc);
+            exit_current_and_run_next(-3);
         }
+        Trap::Exception(Exception::InstructionMisaligned)
+        | Trap::Exception(Exception::StoreMisaligned) => {
+            println!(
+                "[kernel] task {} killed: {:?} @ va={:#x}, bad instruction = {:#x}",
+                current_pid(),
+                scause.cause(),
+                stval,
+                cx.sepc
+            );
+            report::dump_trap_context(cx);
+            eventlog::record(EventKind::Fault, current_pid(), stval);
+            exit_current_and_run_next(-2);
+        }
+        #[cfg(feature = "gdbstub")]
+        Trap::Exception(Exception::Breakpoint) if crate::gdbstub::handle_breakpoint(cx) => {
+            // gdb resumed the task, at the pc it left in the trap context
+        }
+        Trap::Exception(exception) => {
+            // ebreak, a misaligned load or anything else not handled above; in
+            // user mode it is the app's doing, not the kernel's
+            println!(
+                "[kernel] task {} killed: {:?} ({}), stval = {:#x}, pc = {:#x}",
+                current_pid(),
+                exception,
+                report::describe_cause(scause.cause()),
+                stval,
+                cx.sepc
+            );
+            report::dump_trap_context(cx);
+            eventlog::record(EventKind::Fault, current_pid(), cx.sepc);
+            exit_current_and_run_next(-3);
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => crate::drivers::plic::handle_interrupt(),
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            #[cfg(feature = "gdbstub")]
+            crate::gdbstub::poll(cx);
+            match on_timer_tick() {
+                TickAction::Preempt => suspend_current_and_run_next(),
+                TickAction::KeepRunning => {}
+                TickAction::Kill => {
+                    println!(
+                        "[kernel] batch task {} killed: used more than {}us of cpu in batch mode",
+                        current_pid(),
+                        BATCH_CPU_LIMIT_US
+                    );
+                    exit_current_and_run_next(-4);
+                }
+            }
         }
         _ => {
+            println!("[kernel] {}", report::describe_cause(scause.cause()));
+            report::dump_trap_context(cx);
             panic!(
                 "Unsupported trap {:?}, stval = {:#x}!",
                 scause.cause(),
@@ -85,16 +225,58 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// Leave a fast syscall. `__fast_return` only restores the registers a call
+/// may clobber; a signal to act on needs the whole trap context, so then the
+/// trampoline saves s0~s11 and the trap leaves through [`trap_return()`].
+fn fast_return() -> FastReturn {
+    let cx = current_trap_cx();
+    if signals_pending() {
+        return FastReturn {
+            trap_cx: cx as *mut TrapContext as usize,
+            user_satp: 0,
+        };
+    }
+    account_trap_return();
+    set_user_trap_entry();
+    // the task may have moved to another hart while it yielded
+    cx.kernel_tp = hart_id();
+    let ret = FastReturn {
+        trap_cx: current_trap_cx_user_va(),
+        user_satp: current_user_token(),
+    };
+    // only this task's kernel stack is touched from here to user mode
+    smp::unlock_kernel();
+    unsafe {
+        core::arch::asm!("fence.i");
+    }
+    ret
+}
+
+/// The permission a page must grant for the access that raised `exception`.
+fn fault_access(exception: Exception) -> MapPermission {
+    match exception {
+        Exception::StorePageFault => MapPermission::W,
+        Exception::InstructionPageFault => MapPermission::X,
+        _ => MapPermission::R,
+    }
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // also on the first return, a thread may be killed before it ever ran
+    handle_signals();
+    account_trap_return();
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
+    current_trap_cx().kernel_tp = hart_id();
     extern "C" {
         fn __alltraps();
         fn __restore();
     }
     let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
+    // nothing of the kernel is touched from here to user mode
+    smp::unlock_kernel();
     unsafe {
         core::arch::asm!(
             "fence.i",
@@ -109,7 +291,14 @@ pub fn trap_return() -> ! {
 
 #[no_mangle]
 pub fn trap_from_kernel() -> ! {
-    panic!("a trap from kernel!");
+    let cause = scause::read().cause();
+    panic!(
+        "a trap from kernel: {:?}, {}, stval = {:#x}, sepc = {:#x}",
+        cause,
+        report::describe_cause(cause),
+        stval::read(),
+        sepc::read()
+    );
 }
 
 pub use context::TrapContext;
\ No newline at end of file
@@ -0,0 +1,88 @@
+//! Explaining a trap the kernel did not expect, for whoever reads the console
+
+use super::TrapContext;
+use crate::mm::PageTableEntry;
+use riscv::register::scause::{Exception, Interrupt, Trap};
+use riscv::register::sstatus::SPP;
+
+/// ABI names of x0-x31
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// What `cause` means, and what `stval` holds for it.
+pub fn describe_cause(cause: Trap) -> &'static str {
+    match cause {
+        Trap::Exception(exception) => match exception {
+            Exception::InstructionMisaligned => "jump to a misaligned address, stval = target",
+            Exception::InstructionFault => "instruction fetch not allowed by PMP, stval = pc",
+            Exception::IllegalInstruction => "illegal instruction, stval = its encoding",
+            Exception::Breakpoint => "ebreak, stval = pc",
+            Exception::LoadFault => "load not allowed by PMP, stval = address",
+            Exception::StoreMisaligned => "misaligned store or AMO, stval = address",
+            Exception::StoreFault => "store or AMO not allowed by PMP, stval = address",
+            Exception::UserEnvCall => "ecall from user mode",
+            Exception::InstructionPageFault => {
+                "instruction page fault: page missing or not executable, stval = pc"
+            }
+            Exception::LoadPageFault => {
+                "load page fault: page missing or not readable, stval = address"
+            }
+            Exception::StorePageFault => {
+                "store or AMO page fault: page missing or not writable, stval = address"
+            }
+            _ => "exception the kernel does not know",
+        },
+        Trap::Interrupt(interrupt) => match interrupt {
+            Interrupt::SupervisorSoft => "supervisor software interrupt",
+            Interrupt::SupervisorTimer => "supervisor timer interrupt",
+            Interrupt::SupervisorExternal => "supervisor external interrupt",
+            _ => "interrupt the kernel does not know",
+        },
+    }
+}
+
+/// Print every register `cx` saved, four to a line, then sepc and sstatus.
+pub fn dump_trap_context(cx: &TrapContext) {
+    for (i, row) in cx.x.chunks(4).enumerate() {
+        let mut line = [("", 0); 4];
+        for (j, value) in row.iter().enumerate() {
+            line[j] = (REG_NAMES[i * 4 + j], *value);
+        }
+        println!(
+            "  {:>4}={:#018x} {:>4}={:#018x} {:>4}={:#018x} {:>4}={:#018x}",
+            line[0].0, line[0].1, line[1].0, line[1].1, line[2].0, line[2].1, line[3].0, line[3].1
+        );
+    }
+    let from = match cx.sstatus.spp() {
+        SPP::User => "user",
+        SPP::Supervisor => "supervisor",
+    };
+    println!(
+        "  sepc={:#018x} sstatus: from {} mode, spie = {}",
+        cx.sepc,
+        from,
+        cx.sstatus.spie()
+    );
+}
+
+/// Print how the page table maps the faulting address `va`, `pte` being
+/// the leaf entry if there is one.
+pub fn dump_pte(va: usize, pte: Option<PageTableEntry>) {
+    match pte {
+        None => println!("  va {:#x}: no leaf page table", va),
+        Some(pte) if pte.is_valid() => println!(
+            "  va {:#x}: pte {:#x}, ppn {:#x}, flags {:?}",
+            va,
+            pte.bits,
+            pte.ppn().0,
+            pte.flags()
+        ),
+        Some(pte) => match pte.swap_slot() {
+            Some(slot) => println!("  va {:#x}: pte {:#x}, in swap slot {}", va, pte.bits, slot),
+            None => println!("  va {:#x}: pte {:#x}, not valid", va, pte.bits),
+        },
+    }
+}
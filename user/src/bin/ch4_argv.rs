@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{execve, exit, fork, getenv, waitpid};
+
+/*
+理想结果：启动时 argv 只有应用名，execve 后 main 拿到传入的参数和环境变量，
+输出 Test argv OK!
+*/
+
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if getenv("ARGV_TEST") == Some("child") {
+        // the second run, through execve
+        assert_eq!(argc, 3);
+        assert_eq!(argv, ["ch4_argv", "hello", "world"]);
+        assert_eq!(getenv("ARGV_TEST_EMPTY"), Some(""));
+        assert_eq!(getenv("ARGV"), None);
+        exit(0);
+    }
+    assert_eq!(argc, 1);
+    assert_eq!(argv, ["ch4_argv"]);
+    assert_eq!(getenv("ARGV_TEST"), None);
+    let pid = fork();
+    if pid == 0 {
+        let args = [
+            "ch4_argv\0".as_ptr(),
+            "hello\0".as_ptr(),
+            "world\0".as_ptr(),
+            core::ptr::null(),
+        ];
+        let envs = [
+            "ARGV_TEST=child\0".as_ptr(),
+            "ARGV_TEST_EMPTY=\0".as_ptr(),
+            core::ptr::null(),
+        ];
+        execve("ch4_argv\0", &args, &envs);
+        panic!("execve of ch4_argv failed");
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("Test argv OK!");
+    0
+}
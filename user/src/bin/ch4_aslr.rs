@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{close, execve, exit, fork, mmap, pipe, read, waitpid, write};
+
+/*
+理想结果：同一个程序重新 exec 后栈和 mmap 自动选择的地址会变化，输出 Test aslr OK!
+*/
+
+const TRIES: usize = 4;
+
+/// The address of a local variable and of an mmap the kernel placed, as bytes.
+fn layout() -> [u8; 16] {
+    let local = 0usize;
+    let stack = &local as *const usize as usize;
+    let mapped = mmap(0, 4096, 3);
+    assert!(mapped > 0);
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&stack.to_le_bytes());
+    bytes[8..].copy_from_slice(&(mapped as usize).to_le_bytes());
+    bytes
+}
+
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 {
+        // started again by execve, report our layout through the pipe
+        let write_fd = argv[1].parse::<usize>().unwrap();
+        assert_eq!(write(write_fd, &layout()), 16);
+        exit(0);
+    }
+    let ours = layout();
+    for _ in 0..TRIES {
+        let mut pipe_fd = [0usize; 2];
+        assert_eq!(pipe(&mut pipe_fd), 0);
+        let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+        let pid = fork();
+        if pid == 0 {
+            close(read_fd);
+            let fd_arg = format!("{}\0", write_fd);
+            let args = ["ch4_aslr\0".as_ptr(), fd_arg.as_ptr(), core::ptr::null()];
+            execve("ch4_aslr\0", &args, &[core::ptr::null()]);
+            panic!("execve of ch4_aslr failed");
+        }
+        close(write_fd);
+        let mut theirs = [0u8; 16];
+        let mut got = 0;
+        while got < theirs.len() {
+            let n = read(read_fd, &mut theirs[got..]);
+            assert!(n > 0);
+            got += n as usize;
+        }
+        close(read_fd);
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        if theirs != ours {
+            println!("Test aslr OK!");
+            return 0;
+        }
+    }
+    panic!("the layout stayed the same over {} execs", TRIES);
+}
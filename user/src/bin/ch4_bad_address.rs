@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+/*
+理想结果：该程序被内核杀死并输出 StorePageFault @ va=0x0 (page not present)，其余程序正常运行结束
+*/
+
+#[no_mangle]
+pub fn main() -> isize {
+    unsafe {
+        #[allow(clippy::zero_ptr)]
+        (0x0 as *mut u8).write_volatile(0);
+    }
+    panic!("FAIL: T.T\n");
+}
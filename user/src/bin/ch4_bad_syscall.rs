@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{syscall, task_info, TaskInfo, SYSCALL_TASK_INFO};
+
+/*
+理想结果：未知的系统调用号返回 -1 而不会使内核崩溃，输出 Test bad syscall OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(-1, syscall(9999, [0, 0, 0]));
+    assert_eq!(-1, syscall(usize::MAX, [0, 0, 0]));
+    assert_eq!(-1, syscall(499, [0, 0, 0]));
+    let info = TaskInfo::new();
+    assert_eq!(0, task_info(&info));
+    // ids that fit the legacy array are still counted there
+    assert_eq!(1, info.syscall_times[499]);
+    assert_eq!(1, info.syscall_times[SYSCALL_TASK_INFO]);
+    println!("Test bad syscall OK!");
+    0
+}
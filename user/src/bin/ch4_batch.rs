@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, set_batch, yield_};
+
+/*
+理想结果：批处理模式下连续运行不被时钟中断打断，输出 Test batch OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // let the other apps start first, so there is someone to be preempted for
+    yield_();
+    assert_eq!(set_batch(true), 0);
+    let start = get_time();
+    let mut last = start;
+    // spin across many time slices, another task running would leave a gap
+    while last - start < 200 {
+        let now = get_time();
+        assert!(now - last < 5, "preempted while in batch mode");
+        last = now;
+    }
+    assert_eq!(set_batch(false), 0);
+    println!("Test batch OK!");
+    0
+}
@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::set_batch;
+
+/*
+理想结果：内核输出 batch task ... killed: used more than 2000000us of cpu，不会输出 Test batch limit FAIL!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(set_batch(true), 0);
+    // never yields, only the cpu limit can take the cpu back
+    let mut i: usize = 0;
+    loop {
+        i = i.wrapping_add(1);
+        if i == usize::MAX {
+            break;
+        }
+    }
+    println!("Test batch limit FAIL!");
+    0
+}
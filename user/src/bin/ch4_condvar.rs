@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    condvar_broadcast, condvar_create, condvar_signal, condvar_wait, exit, fork, mmap,
+    mutex_blocking_create, mutex_lock, mutex_unlock, waitpid, EPERM, MAP_SHARED,
+};
+
+/*
+理想结果：生产者和两个消费者通过共享内存中的有界缓冲区传递 1..=ITEMS，消费者取到的总和正确；
+broadcast 唤醒所有等待者，不持有互斥锁时 wait 返回 -EPERM，输出 Test condvar OK!
+*/
+
+const ITEMS: usize = 50;
+const CONSUMERS: usize = 2;
+const SLOTS: usize = 4;
+
+/// A bounded buffer in a shared page, guarded by one mutex.
+#[repr(C)]
+struct Shared {
+    len: usize,
+    head: usize,
+    sum: usize,
+    go: usize,
+    slots: [usize; SLOTS],
+}
+
+fn shared() -> &'static mut Shared {
+    unsafe { &mut *(0x10000000 as *mut Shared) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(0, mmap(0x10000000, 4096, 3 | MAP_SHARED));
+    let mutex = mutex_blocking_create() as usize;
+    let not_full = condvar_create() as usize;
+    let not_empty = condvar_create() as usize;
+    assert_ne!(not_full, not_empty);
+    assert_eq!(condvar_wait(not_full, mutex), -EPERM);
+
+    let mut pids = [0isize; CONSUMERS + 1];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid != 0 {
+            continue;
+        }
+        let buf = shared();
+        if i == 0 {
+            // the producer, a 0 tells a consumer to stop
+            for item in (1..=ITEMS).chain([0; CONSUMERS]) {
+                mutex_lock(mutex);
+                while buf.len == SLOTS {
+                    assert_eq!(condvar_wait(not_full, mutex), 0);
+                }
+                buf.slots[(buf.head + buf.len) % SLOTS] = item;
+                buf.len += 1;
+                condvar_signal(not_empty);
+                mutex_unlock(mutex);
+            }
+        } else {
+            loop {
+                mutex_lock(mutex);
+                while buf.len == 0 {
+                    assert_eq!(condvar_wait(not_empty, mutex), 0);
+                }
+                let item = buf.slots[buf.head];
+                buf.head = (buf.head + 1) % SLOTS;
+                buf.len -= 1;
+                buf.sum += item;
+                condvar_signal(not_full);
+                mutex_unlock(mutex);
+                if item == 0 {
+                    break;
+                }
+            }
+        }
+        exit(0);
+    }
+    for pid in pids {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(shared().sum, ITEMS * (ITEMS + 1) / 2);
+
+    // every waiter gets out on one broadcast
+    let go = condvar_create() as usize;
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            mutex_lock(mutex);
+            while shared().go == 0 {
+                assert_eq!(condvar_wait(go, mutex), 0);
+            }
+            mutex_unlock(mutex);
+            exit(0);
+        }
+    }
+    mutex_lock(mutex);
+    shared().go = 1;
+    condvar_broadcast(go);
+    mutex_unlock(mutex);
+    for pid in pids {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("Test condvar OK!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, task_stats, TaskStats};
+
+/*
+理想结果：只读过的 mmap 页共享零页，不产生拷贝；写入三页后恰好拷贝三次，输出 Test cow copies OK!
+*/
+
+fn cow_copies() -> usize {
+    let mut stats = TaskStats::default();
+    assert_eq!(0, task_stats(&mut stats));
+    stats.cow_copies
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 3;
+    assert_eq!(0, mmap(start, len, 3));
+    let before = cow_copies();
+    for addr in (start..start + len).step_by(4096) {
+        let value = unsafe { (addr as *const u8).read_volatile() };
+        assert_eq!(value, 0);
+    }
+    assert_eq!(cow_copies(), before);
+    for addr in (start..start + len).step_by(4096) {
+        unsafe { (addr as *mut u8).write_volatile(1) };
+    }
+    assert_eq!(cow_copies(), before + 3);
+    // pages that are already private are written in place
+    unsafe { (start as *mut u8).write_volatile(2) };
+    assert_eq!(cow_copies(), before + 3);
+    assert_eq!(0, munmap(start, len));
+    println!("Test cow copies OK!");
+    0
+}
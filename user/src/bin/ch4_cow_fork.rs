@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, task_stats, waitpid, TaskStats};
+
+/*
+理想结果：fork 之后父子任务共享页面，子任务写入时各拷贝一次，父任务看到的数据不变；
+子任务退出后父任务写入不再拷贝，输出 Test cow fork OK!
+*/
+
+const PAGES: usize = 3;
+
+fn cow_copies() -> usize {
+    let mut stats = TaskStats::default();
+    assert_eq!(0, task_stats(&mut stats));
+    stats.cow_copies
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    assert_eq!(0, mmap(start, 4096 * PAGES, 3));
+    for i in 0..PAGES {
+        unsafe { ((start + i * 4096) as *mut usize).write_volatile(i) };
+    }
+    let pid = fork();
+    if pid == 0 {
+        let before = cow_copies();
+        for i in 0..PAGES {
+            unsafe { ((start + i * 4096) as *mut usize).write_volatile(100 + i) };
+        }
+        // the stack may take a copy of its own in between
+        assert!(cow_copies() >= before + PAGES);
+        return 0;
+    }
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    for i in 0..PAGES {
+        assert_eq!(unsafe { ((start + i * 4096) as *const usize).read_volatile() }, i);
+    }
+    // the child is gone, so the frames are ours alone again
+    let before = cow_copies();
+    for i in 0..PAGES {
+        unsafe { ((start + i * 4096) as *mut usize).write_volatile(i + 1) };
+    }
+    assert_eq!(cow_copies(), before);
+    println!("Test cow fork OK!");
+    0
+}
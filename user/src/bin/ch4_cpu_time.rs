@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, task_info, task_stats, yield_, TaskInfo, TaskStats};
+
+/*
+理想结果：让出 CPU 的时间不计入 cpu_time，cpu_time 不超过自首次调度以来的时间，输出 Test cpu time OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let info = TaskInfo::new();
+    let mut stats = TaskStats::default();
+    let start = get_time();
+    while get_time() < start + 200 {
+        yield_();
+    }
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(0, task_info(&info));
+    assert!(stats.cpu_time <= info.time + 1);
+    println!(
+        "cpu time = {}ms, time since first dispatch = {}ms",
+        stats.cpu_time, info.time
+    );
+    println!("Test cpu time OK!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    mmap, sys_get_time, task_info, TaskInfo, TaskStatus, TimeVal, SYSCALL_GETTIMEOFDAY,
+    SYSCALL_TASK_INFO,
+};
+
+/*
+理想结果：跨页的 TimeVal/TaskInfo 被完整写入，非法指针返回 -1，输出 Test cross page OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 2;
+    assert_eq!(0, mmap(start, len, 3));
+    let page_end = start + 4096;
+
+    // a TimeVal whose second field lies on the next page
+    let ts = unsafe { &*((page_end - 8) as *const TimeVal) };
+    assert_eq!(0, sys_get_time(ts, 0));
+    assert!(ts.sec > 0 || ts.usec > 0);
+
+    // the syscall_times array straddles the page boundary
+    let info = unsafe { &*((page_end - 64) as *const TaskInfo) };
+    assert_eq!(0, task_info(info));
+    assert!(info.status == TaskStatus::Running);
+    assert_eq!(1, info.syscall_times[SYSCALL_GETTIMEOFDAY]);
+    assert_eq!(1, info.syscall_times[SYSCALL_TASK_INFO]);
+
+    // unmapped memory is refused instead of crashing the kernel
+    let bad = unsafe { &*((start + len - 8) as *const TimeVal) };
+    assert_eq!(-1, sys_get_time(bad, 0));
+    println!("Test cross page OK!");
+    0
+}
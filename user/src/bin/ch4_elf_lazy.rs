@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{task_stats, TaskStats};
+
+/*
+理想结果：.data 里没有碰过的页不占物理页，第一次读到时内容和 elf 里的一致，
+输出 Test elf demand paging OK!
+*/
+
+const LEN: usize = 4096 * 32;
+
+/// Non-zero, so it is stored in the elf instead of bss.
+static DATA: [u8; LEN] = [0x5a; LEN];
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut stats = TaskStats::default();
+    assert_eq!(0, task_stats(&mut stats));
+    let before = stats.resident_pages;
+    let last = unsafe { (&DATA[LEN - 1] as *const u8).read_volatile() };
+    assert_eq!(last, 0x5a);
+    assert_eq!(0, task_stats(&mut stats));
+    assert!(stats.resident_pages > before);
+    assert!(DATA.iter().all(|byte| *byte == 0x5a));
+    println!("Test elf demand paging OK!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, mmap, munmap, read_eventlog, Event, EVENT_MMAP, EVENT_MUNMAP};
+
+/*
+理想结果：事件日志里按顺序出现本任务的 mmap 与 munmap 事件，输出 Test eventlog OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid() as usize;
+    let start: usize = 0x10000000;
+    let mut events = [Event::default(); 16];
+    // throw away what other tasks logged so far
+    while read_eventlog(&mut events) > 0 {}
+    assert_eq!(0, mmap(start, 4096, 3));
+    assert_eq!(0, munmap(start, 4096));
+    let mut seen = [0usize; 2];
+    let mut found = 0;
+    loop {
+        let count = read_eventlog(&mut events);
+        assert!(count >= 0);
+        if count == 0 {
+            break;
+        }
+        for event in events[..count as usize].iter() {
+            if event.task == pid && (event.kind == EVENT_MMAP || event.kind == EVENT_MUNMAP) {
+                assert_eq!(event.arg, start);
+                if found < 2 {
+                    seen[found] = event.kind;
+                }
+                found += 1;
+            }
+        }
+    }
+    assert_eq!(found, 2);
+    assert_eq!(seen, [EVENT_MMAP, EVENT_MUNMAP]);
+    println!("Test eventlog OK!");
+    0
+}
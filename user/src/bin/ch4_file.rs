@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, write, OpenFlags};
+
+/*
+理想结果：写入文件的内容关闭后重新打开能读回，错误的描述符和访问方式返回 -1，输出 Test file OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let content = "Hello, easy-fs!";
+    let name = "ch4_file_data\0";
+    let fd = open(name, OpenFlags::CREATE | OpenFlags::WRONLY);
+    // 0, 1 and 2 are the console
+    assert!(fd >= 3);
+    let fd = fd as usize;
+    assert_eq!(write(fd, content.as_bytes()), content.len() as isize);
+    let mut buffer = [0u8; 64];
+    assert_eq!(read(fd, &mut buffer), -1);
+    assert_eq!(close(fd), 0);
+    assert_eq!(close(fd), -1);
+
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd >= 3);
+    let fd = fd as usize;
+    assert_eq!(write(fd, content.as_bytes()), -1);
+    let len = read(fd, &mut buffer) as usize;
+    assert_eq!(&buffer[..len], content.as_bytes());
+    // at the end of the file
+    assert_eq!(read(fd, &mut buffer), 0);
+    assert_eq!(close(fd), 0);
+
+    assert_eq!(open("ch4_file_missing\0", OpenFlags::RDONLY), -1);
+    println!("Test file OK!");
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, getpid, wait, waitpid};
+
+/*
+理想结果：子任务看到的是父任务数据的副本，改动互不影响；exec 后的子任务正常退出，
+父任务用 waitpid/wait 收回所有子任务，输出 Test fork exec OK!
+*/
+
+static mut SHARED: usize = 1;
+
+#[no_mangle]
+fn main() -> i32 {
+    let parent = getpid();
+    let pid = fork();
+    if pid == 0 {
+        assert_ne!(getpid(), parent);
+        unsafe {
+            assert_eq!(SHARED, 1);
+            SHARED = 2;
+        }
+        return 7;
+    }
+    assert!(pid > 0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    unsafe {
+        assert_eq!(SHARED, 1);
+    }
+    let pid = fork();
+    if pid == 0 {
+        exec("ch2b_hello_world\0", &[core::ptr::null::<u8>()]);
+        panic!("exec returned");
+    }
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert!(wait(&mut exit_code) < 0, "no children should be left");
+    println!("Test fork exec OK!");
+    0
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, mmap, sys_futex, waitpid, yield_, EAGAIN, EINVAL,
+    FUTEX_WAKE, MAP_SHARED,
+};
+
+/*
+理想结果：用 futex 在共享内存中实现的互斥锁保证子任务累加共享计数不丢失更新，
+值不符时 wait 立即返回 -EAGAIN，地址未对齐返回 -EINVAL，输出 Test futex OK!
+*/
+
+const CHILDREN: usize = 3;
+const ROUNDS: usize = 20;
+const SHARED: usize = 0x10000000;
+
+/// 0 free, 1 held, 2 held and somebody may be waiting
+fn lock_word() -> &'static AtomicU32 {
+    unsafe { &*(SHARED as *const AtomicU32) }
+}
+
+fn counter() -> &'static AtomicUsize {
+    unsafe { &*((SHARED + 8) as *const AtomicUsize) }
+}
+
+fn lock(word: &AtomicU32) {
+    if word
+        .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+    {
+        return;
+    }
+    while word.swap(2, Ordering::Acquire) != 0 {
+        futex_wait(word, 2);
+    }
+}
+
+fn unlock(word: &AtomicU32) {
+    if word.swap(0, Ordering::Release) == 2 {
+        futex_wake(word, 1);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(0, mmap(SHARED, 4096, 3 | MAP_SHARED));
+    let word = lock_word();
+    assert_eq!(futex_wait(word, 1), -EAGAIN);
+    assert_eq!(futex_wake(word, 1), 0);
+    assert_eq!(
+        sys_futex((SHARED + 1) as *const u32, FUTEX_WAKE, 1),
+        -EINVAL
+    );
+
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            for _ in 0..ROUNDS {
+                lock(word);
+                let value = counter().load(Ordering::Relaxed);
+                // let the others run in the middle of the update
+                yield_();
+                counter().store(value + 1, Ordering::Relaxed);
+                unlock(word);
+            }
+            exit(0);
+        }
+    }
+    for pid in pids {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(counter().load(Ordering::Relaxed), CHILDREN * ROUNDS);
+    assert_eq!(word.load(Ordering::Relaxed), 0);
+    println!("Test futex OK!");
+    0
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, mmap, syscall, TimeVal, SYSCALL_GETTIMEOFDAY};
+
+/*
+理想结果：未对齐的 TimeVal 指针同样被完整写入，输出 Test get_time unaligned OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    assert_eq!(0, mmap(start, 4096, 3));
+    let before = get_time() as usize;
+    for offset in 1..8 {
+        // never build a `&TimeVal` from a misaligned address, go through the raw syscall
+        let ptr = (start + offset) as *mut TimeVal;
+        assert_eq!(0, syscall(SYSCALL_GETTIMEOFDAY, [ptr as usize, 0, 0]));
+        let ts = unsafe { ptr.read_unaligned() };
+        let ms = (ts.sec & 0xffff) * 1000 + ts.usec / 1000;
+        assert!(ms >= before);
+        assert!(ts.usec < 1_000_000);
+    }
+    println!("Test get_time unaligned OK!");
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpagesize, mmap, munmap};
+
+/*
+理想结果：页大小为 4096，按它对齐的 mmap 成功、错开半页的失败，输出 Test getpagesize OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let page_size = getpagesize();
+    assert_eq!(page_size, 4096);
+    assert!(page_size.is_power_of_two());
+    let start: usize = 0x10000000;
+    assert_eq!(mmap(start, page_size, 3), 0);
+    assert_eq!(munmap(start, page_size), 0);
+    assert_eq!(mmap(start + page_size / 2, page_size, 3), -1);
+    println!("Test getpagesize OK!");
+    0
+}
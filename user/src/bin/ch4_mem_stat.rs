@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mem_stat, mmap, munmap, MemStat};
+
+/*
+理想结果：访问 mmap 的页后任务占用的页帧增加，munmap 后页帧归还给分配器，输出 Test mem stat OK!
+*/
+
+const PAGES: usize = 4;
+
+fn stat() -> MemStat {
+    let mut stat = MemStat::default();
+    assert_eq!(0, mem_stat(&mut stat));
+    stat
+}
+
+fn touch(start: usize) {
+    for i in 0..PAGES {
+        unsafe { ((start + i * 4096) as *mut u8).write_volatile(1) };
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len = PAGES * 4096;
+    // warm up so the page table nodes for this region already exist
+    assert_eq!(0, mmap(start, len, 3));
+    touch(start);
+    assert_eq!(0, munmap(start, len));
+
+    let before = stat();
+    assert_eq!(0, mmap(start, len, 3));
+    touch(start);
+    let mapped = stat();
+    assert_eq!(before.map_areas + 1, mapped.map_areas);
+    assert_eq!(before.task_frames + PAGES, mapped.task_frames);
+
+    assert_eq!(0, munmap(start, len));
+    let after = stat();
+    assert_eq!(before.map_areas, after.map_areas);
+    assert_eq!(before.task_frames, after.task_frames);
+    // other tasks allocate concurrently, so free_frames is only reported
+    println!("free frames: {} -> {}", before.free_frames, after.free_frames);
+    println!("Test mem stat OK!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, mmap, munmap, MemInfo};
+
+/*
+理想结果：访问 N 个 mmap 页后已用页帧数增加 N 加上一个页表页，munmap 后恢复，输出 Test meminfo OK!
+*/
+
+const N: usize = 8;
+
+fn used_frames() -> usize {
+    let mut info = MemInfo::default();
+    assert_eq!(0, meminfo(&mut info));
+    assert!(info.used_frames <= info.total_frames);
+    info.used_frames
+}
+
+fn touch(start: usize, pages: usize) {
+    for i in 0..pages {
+        unsafe { ((start + i * 4096) as *mut u8).write_volatile(1) };
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len = N * 4096;
+    // warm up so the code below is already loaded and its pages do not
+    // show up halfway through
+    assert_eq!(0, mmap(start, len, 3));
+    touch(start, N);
+    assert_eq!(0, munmap(start, len));
+
+    let baseline = used_frames();
+    assert_eq!(0, mmap(start, len, 3));
+    // mmap is lazy, frames are only taken on first touch
+    assert_eq!(baseline, used_frames());
+    touch(start, N);
+    // munmap gave the leaf table of the region back, the first touch takes it
+    // again; the middle table above it also maps the program and stays
+    assert_eq!(baseline + N + 1, used_frames());
+    assert_eq!(0, munmap(start, len));
+    assert_eq!(baseline, used_frames());
+    println!("Test meminfo OK!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, task_stats, TaskStats};
+
+/*
+理想结果：频繁 mmap/munmap 的任务会被内核抢占，其他任务照常运行，输出 Test mmap churn OK!
+与 ch4_stride0 等计算型任务一起运行时，计算型任务应得到应有的 CPU 份额
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 4;
+    for _ in 0..2000 {
+        assert_eq!(0, mmap(start, len, 3));
+        assert_eq!(0, munmap(start, len));
+    }
+    let mut stats = TaskStats::default();
+    assert_eq!(0, task_stats(&mut stats));
+    println!("churn preemptions = {}", stats.churn_preemptions);
+    println!("Test mmap churn OK!");
+    0
+}
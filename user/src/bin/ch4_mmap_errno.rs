@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, mmap_file, munmap, EEXIST, EINVAL, ENOMEM};
+
+/*
+理想结果：mmap 出错一律返回 -1，mmap_file 对每类错误返回对应的错误码，输出 Test mmap errno OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let page: usize = 4096;
+    assert_eq!(0, mmap(start, page, 3));
+    // (start, len, port, expected)
+    let cases: [(usize, usize, usize, isize); 8] = [
+        (start + 1, page, 3, -EINVAL),
+        (start + page, page, 0, -EINVAL),
+        (start + page, page, 8 | 3, -EINVAL),
+        (usize::MAX - page + 1, page * 2, 3, -EINVAL),
+        (0x80_0000_0000, page, 3, -EINVAL),
+        (start + page, 1 << 36, 3, -ENOMEM),
+        (start, page, 3, -EEXIST),
+        (start - page, page + 1, 3, -EEXIST),
+    ];
+    for (i, (addr, len, port, expected)) in cases.iter().enumerate() {
+        // the grader's mmap only tells failure from success
+        assert_eq!(mmap(*addr, *len, *port), -1);
+        let ret = mmap_file(*addr, *len, *port, -1, 0);
+        if ret != *expected {
+            println!("case {}: mmap_file({:#x}, {:#x}, {}) = {}, expected {}", i, addr, len, port, ret, expected);
+            panic!("Test mmap errno failed!");
+        }
+    }
+    assert_eq!(0, munmap(start, page));
+    println!("Test mmap errno OK!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::slice;
+use user_lib::{close, mmap_file, munmap, open, read, write, OpenFlags};
+use user_lib::{EACCES, EBADF, ENODEV, MAP_PRIVATE, MAP_SHARED};
+
+/*
+理想结果：文件映射读到文件内容，MAP_PRIVATE 的写入不回写，MAP_SHARED 的写入在 munmap 时写回，
+文件长度不变；各种错误返回对应的错误码，输出 Test mmap file OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let name = "ch4_mmap_file_data\0";
+    let page: usize = 4096;
+    let size: usize = 6000;
+    let fd = open(name, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd >= 3);
+    let mut content = [b'a'; 6000];
+    content[page..].fill(b'b');
+    assert_eq!(write(fd as usize, &content), size as isize);
+
+    // a private mapping sees the file, past its end the page reads as zeros
+    let private: usize = 0x10000000;
+    assert_eq!(mmap_file(private, 2 * page, 3 | MAP_PRIVATE, fd, 0), 0);
+    let bytes = unsafe { slice::from_raw_parts_mut(private as *mut u8, 2 * page) };
+    assert!(bytes[..page].iter().all(|b| *b == b'a'));
+    assert!(bytes[page..size].iter().all(|b| *b == b'b'));
+    assert!(bytes[size..].iter().all(|b| *b == 0));
+    bytes[0] = b'x';
+    assert_eq!(munmap(private, 2 * page), 0);
+
+    // a shared one writes back when it is unmapped
+    let shared: usize = 0x10002000;
+    assert_eq!(mmap_file(shared, page, 3 | MAP_SHARED, fd, page), 0);
+    let bytes = unsafe { slice::from_raw_parts_mut(shared as *mut u8, page) };
+    assert_eq!(bytes[0], b'b');
+    bytes[..4].copy_from_slice(b"mmap");
+    assert_eq!(munmap(shared, page), 0);
+    assert_eq!(close(fd as usize), 0);
+
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd >= 3);
+    let mut buffer = [0u8; 6000];
+    assert_eq!(read(fd as usize, &mut buffer), size as isize);
+    assert_eq!(read(fd as usize, &mut buffer[..1]), 0);
+    assert_eq!(buffer[0], b'a');
+    assert_eq!(&buffer[page..page + 4], b"mmap");
+    assert!(buffer[page + 4..].iter().all(|b| *b == b'b'));
+
+    // a read-only file can only be shared read-only, a write-only one not at all
+    assert_eq!(mmap_file(shared, page, 3 | MAP_SHARED, fd, 0), -EACCES);
+    assert_eq!(mmap_file(shared, page, 1 | MAP_SHARED, fd, 0), 0);
+    assert_eq!(munmap(shared, page), 0);
+    let write_only = open(name, OpenFlags::WRONLY);
+    assert!(write_only >= 3);
+    assert_eq!(mmap_file(shared, page, 1, write_only, 0), -EACCES);
+    assert_eq!(mmap_file(shared, page, 1, 0, 0), -ENODEV);
+    assert_eq!(mmap_file(shared, page, 1, 42, 0), -EBADF);
+    assert_eq!(close(write_only as usize), 0);
+    assert_eq!(close(fd as usize), 0);
+    println!("Test mmap file OK!");
+    0
+}
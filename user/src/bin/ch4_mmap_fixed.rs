@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, MAP_FIXED};
+
+/*
+理想结果：带 MAP_FIXED 时映射到地址 0，不带时由内核另选地址并返回，输出 Test mmap fixed OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let len: usize = 4096;
+    assert_eq!(mmap(0, len, 3 | MAP_FIXED), 0);
+    assert_eq!(mmap(0, len, 3 | MAP_FIXED), -1);
+    // without MAP_FIXED, 0 means "pick an address for me"
+    let addr = mmap(0, len * 2, 3);
+    assert!(addr > 0);
+    let addr = addr as usize;
+    assert_eq!(addr % 4096, 0);
+    unsafe {
+        (addr as *mut usize).write_volatile(42);
+        assert_eq!((addr as *const usize).read_volatile(), 42);
+    }
+    // the next placement does not overlap the first one
+    let next = mmap(0, len, 3) as usize;
+    assert!(next >= addr + len * 2 || next + len <= addr);
+    assert_eq!(munmap(next, len), 0);
+    assert_eq!(munmap(addr, len * 2), 0);
+    assert_eq!(munmap(0, len), 0);
+    println!("Test mmap fixed OK!");
+    0
+}
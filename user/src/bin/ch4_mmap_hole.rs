@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/*
+理想结果：跨越 SV39 用户/内核地址空洞的 mmap 和 munmap 都被拒绝，输出 Test mmap hole OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // last page of the lower half, and the first page of the upper half
+    let last_user_page: usize = (1 << 38) - 4096;
+    let upper_half: usize = !((1 << 38) - 1);
+    let len = upper_half + 4096 - last_user_page;
+    assert_eq!(mmap(last_user_page, len, 3), -1);
+    assert_eq!(mmap(last_user_page, 4096 * 2, 3), -1);
+    assert_eq!(munmap(last_user_page, len), -1);
+    // the last page below the hole is still usable
+    assert_eq!(mmap(last_user_page, 4096, 3), 0);
+    assert_eq!(munmap(last_user_page, 4096), 0);
+    println!("Test mmap hole OK!");
+    0
+}
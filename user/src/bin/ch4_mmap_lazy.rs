@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/*
+理想结果：部分页从未被访问的区域可以正常 munmap，输出 Test mmap lazy OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 4;
+    assert_eq!(0, mmap(start, len, 3));
+    // only the second page is ever touched, and it is touched repeatedly
+    let addr = (start + 4096) as *mut usize;
+    for i in 0..16 {
+        unsafe {
+            addr.write_volatile(i);
+            assert_eq!(addr.read_volatile(), i);
+        }
+    }
+    assert_eq!(0, munmap(start, len));
+    assert_eq!(-1, munmap(start, len));
+    // the range is free again and comes back zeroed
+    assert_eq!(0, mmap(start, len, 3));
+    assert_eq!(unsafe { addr.read_volatile() }, 0);
+    println!("Test mmap lazy OK!");
+    0
+}
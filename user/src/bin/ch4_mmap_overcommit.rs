@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mem_stat, mmap, munmap, MemStat};
+
+/*
+理想结果：映射整个 mmap 配额 (64 MiB) 几乎不占用物理页帧，只有访问过的页才分配，输出 Test mmap overcommit OK!
+*/
+
+const MIB: usize = 1 << 20;
+
+fn free_frames() -> usize {
+    let mut stat = MemStat::default();
+    assert_eq!(mem_stat(&mut stat), 0);
+    stat.free_frames
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 64 * MIB;
+    let before = free_frames();
+    assert_eq!(0, mmap(start, len, 3));
+    // recording the range allocates nothing
+    assert_eq!(free_frames(), before);
+    for i in 0..4 {
+        let p = (start + i * 16 * MIB) as *mut usize;
+        unsafe {
+            p.write_volatile(i);
+        }
+    }
+    // four data pages plus at most a few page table pages
+    assert!(before - free_frames() <= 4 + 8);
+    assert_eq!(0, munmap(start, len));
+    println!("Test mmap overcommit OK!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, mmap_file, munmap, waitpid, EBADF, EINVAL, MAP_PRIVATE, MAP_SHARED};
+
+/*
+理想结果：MAP_SHARED 区域里子任务的写入父任务可见，MAP_PRIVATE 区域不可见；
+映射没有打开的描述符返回 -EBADF，输出 Test mmap shared OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let shared: usize = 0x10000000;
+    let private: usize = 0x10001000;
+    let page: usize = 4096;
+    assert_eq!(0, mmap(shared, page, 3 | MAP_SHARED));
+    assert_eq!(0, mmap(private, page, 3 | MAP_PRIVATE));
+    unsafe {
+        (shared as *mut usize).write_volatile(1);
+        (private as *mut usize).write_volatile(1);
+    }
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            (shared as *mut usize).write_volatile(2);
+            (private as *mut usize).write_volatile(2);
+        }
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { (shared as *const usize).read_volatile() }, 2);
+    assert_eq!(unsafe { (private as *const usize).read_volatile() }, 1);
+    assert_eq!(mmap(0x10002000, page, 3 | MAP_SHARED | MAP_PRIVATE), -1);
+    assert_eq!(mmap_file(0x10002000, page, 1, -1, 1), -EINVAL);
+    assert_eq!(mmap_file(0x10002000, page, 1, 3, 0), -EBADF);
+    assert_eq!(0, munmap(shared, page * 2));
+    println!("Test mmap shared OK!");
+    0
+}
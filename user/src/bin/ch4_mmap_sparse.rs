@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/*
+理想结果：映射 8 MiB 但只访问其中少量页面，读出全为 0，输出 Test mmap sparse OK!
+*/
+
+const MIB: usize = 1 << 20;
+const STRIDE: usize = 64 * 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 8 * MIB;
+    assert_eq!(0, mmap(start, len, 3));
+    // overlapping an untouched lazy page must still be refused
+    assert_eq!(-1, mmap(start + len - 4096, 4096, 3));
+    for addr in (start..start + len).step_by(STRIDE) {
+        let p = addr as *mut usize;
+        unsafe {
+            assert_eq!(p.read_volatile(), 0);
+            p.write_volatile(addr);
+        }
+    }
+    for addr in (start..start + len).step_by(STRIDE) {
+        assert_eq!(unsafe { (addr as *const usize).read_volatile() }, addr);
+    }
+    assert_eq!(0, munmap(start, len));
+    println!("Test mmap sparse OK!");
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, mprotect, munmap, waitpid, EINVAL, ENOMEM};
+
+/*
+理想结果：mprotect 改为只读的页仍可读，子任务写它被内核杀死（退出码 -2），
+恢复可写后父任务能正常写入，输出 Test mprotect OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let page: usize = 4096;
+    assert_eq!(0, mmap(start, page * 3, 3));
+    for i in 0..3 {
+        unsafe { ((start + i * page) as *mut usize).write_volatile(i + 1) };
+    }
+    // the middle page becomes a read-only guard between the other two
+    let guard = start + page;
+    assert_eq!(0, mprotect(guard, page, 1));
+    assert_eq!(unsafe { (guard as *const usize).read_volatile() }, 2);
+    let pid = fork();
+    if pid == 0 {
+        unsafe { (guard as *mut usize).write_volatile(0) };
+        panic!("should have been killed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+    // the pages around the guard are still writable
+    unsafe { ((start + page * 2) as *mut usize).write_volatile(30) };
+    assert_eq!(0, mprotect(guard, page, 3));
+    unsafe { (guard as *mut usize).write_volatile(20) };
+    assert_eq!(unsafe { (guard as *const usize).read_volatile() }, 20);
+    assert_eq!(mprotect(guard + 1, page, 1), -EINVAL);
+    assert_eq!(mprotect(guard, page, 0), -EINVAL);
+    assert_eq!(mprotect(start + page * 3, page, 1), -ENOMEM);
+    assert_eq!(0, munmap(start, page * 3));
+    println!("Test mprotect OK!");
+    0
+}
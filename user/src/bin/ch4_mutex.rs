@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock, waitpid,
+    yield_, EINVAL, EPERM, MAP_SHARED,
+};
+
+/*
+理想结果：两种互斥锁都能保证子任务累加共享计数不丢失更新，只有持有者能解锁，
+任务退出时仍持有的锁被内核释放（内核会打印一条警告），输出 Test mutex OK!
+*/
+
+const CHILDREN: usize = 3;
+const ROUNDS: usize = 10;
+
+/// Let `CHILDREN` children add to the counter at `counter` under `mutex`.
+fn count_under(mutex: usize, counter: usize) {
+    unsafe { (counter as *mut usize).write_volatile(0) };
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            for _ in 0..ROUNDS {
+                assert_eq!(mutex_lock(mutex), 0);
+                let value = unsafe { (counter as *const usize).read_volatile() };
+                // let the others run in the middle of the update
+                yield_();
+                unsafe { (counter as *mut usize).write_volatile(value + 1) };
+                assert_eq!(mutex_unlock(mutex), 0);
+            }
+            exit(0);
+        }
+    }
+    for pid in pids {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    let total = unsafe { (counter as *const usize).read_volatile() };
+    assert_eq!(total, CHILDREN * ROUNDS);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let counter: usize = 0x10000000;
+    assert_eq!(0, mmap(counter, 4096, 3 | MAP_SHARED));
+    let spin = mutex_create();
+    let blocking = mutex_blocking_create();
+    assert!(spin >= 0 && blocking >= 0 && spin != blocking);
+    let (spin, blocking) = (spin as usize, blocking as usize);
+    count_under(spin, counter);
+    count_under(blocking, counter);
+
+    // nobody holds it, and another task's lock cannot be unlocked
+    assert_eq!(mutex_unlock(blocking), -EPERM);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(mutex_lock(blocking), 0);
+        // exits holding the lock
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(mutex_lock(blocking), 0);
+    assert_eq!(mutex_unlock(blocking), 0);
+    assert_eq!(mutex_lock(blocking + 100), -EINVAL);
+    println!("Test mutex OK!");
+    0
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, getppid, waitpid};
+
+/*
+理想结果：子进程看到的 getpid 等于 fork 的返回值，getppid 等于父进程的 pid，
+同时存在的进程 pid 互不相同，输出 Test pid OK!
+*/
+
+const N: usize = 4;
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid();
+    assert!(pid >= 0);
+    let mut children = [0isize; N];
+    for i in 0..N {
+        let child = fork();
+        if child == 0 {
+            assert_eq!(getppid(), pid);
+            // the exit code tells the parent which pid the child saw
+            exit(getpid() as i32);
+        }
+        assert!(child > 0 && child != pid);
+        assert!(!children[..i].contains(&child));
+        children[i] = child;
+    }
+    for child in children {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(child as usize, &mut exit_code), child);
+        assert_eq!(exit_code as isize, child);
+    }
+    println!("Test pid OK!");
+    0
+}
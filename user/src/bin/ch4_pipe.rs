@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, pipe, read, waitpid, write};
+
+/*
+理想结果：子进程写入的 100 个字节（超过管道缓冲区）被父进程按顺序读完，
+写端全部关闭后读返回 0，读端关闭后写不进去，输出 Test pipe OK!
+*/
+
+const LEN: usize = 100;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_fd, write_fd) = (pipe_fd[0], pipe_fd[1]);
+    // the ends are one-way
+    assert_eq!(write(read_fd, b"x"), -1);
+    assert_eq!(read(write_fd, &mut [0u8; 1]), -1);
+
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd);
+        let mut data = [0u8; LEN];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        // blocks while the parent has not made room yet
+        assert_eq!(write(write_fd, &data), LEN as isize);
+        close(write_fd);
+        exit(0);
+    }
+    close(write_fd);
+    let mut data = [0u8; LEN];
+    let mut got = 0;
+    while got < LEN {
+        let n = read(read_fd, &mut data[got..]);
+        assert!(n > 0);
+        got += n as usize;
+    }
+    for (i, byte) in data.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // every write end is closed now
+    assert_eq!(read(read_fd, &mut data), 0);
+    close(read_fd);
+
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"nobody reads"), 0);
+    close(pipe_fd[1]);
+    println!("Test pipe OK!");
+    0
+}
@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{read, STDIN};
+
+/*
+理想结果：读 0 个字节立即返回 0，读入未映射的缓冲区返回 -1 且内核不崩溃，输出 Test read OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut empty = [0u8; 0];
+    assert_eq!(read(STDIN, &mut empty), 0);
+    // nothing is mapped there, checked before the kernel waits for input
+    let unmapped = unsafe { core::slice::from_raw_parts_mut(0x1000_0000 as *mut u8, 16) };
+    assert_eq!(read(STDIN, unmapped), -1);
+    println!("Test read OK!");
+    0
+}
@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, kill, sigprocmask, SignalFlags, SIGUSR1, SYSCALL_YIELD};
+
+/*
+理想结果：系统调用（包括其中发生的任务切换，以及有信号待处理时走完整陷入返回路径的快速系统调用）
+前后寄存器的值保持不变，输出 Test register integrity OK!
+*/
+
+/// Fill registers with patterns, yield, and count the registers that changed.
+fn yield_and_count_clobbered() -> usize {
+    let bad: usize;
+    unsafe {
+        core::arch::asm!(
+            "li s2, 0x5a5a5a5a00000000",
+            "li s3, 0x5a5a5a5a00000001",
+            "li s4, 0x5a5a5a5a00000002",
+            "li s5, 0x5a5a5a5a00000003",
+            "li s6, 0x5a5a5a5a00000004",
+            "li s7, 0x5a5a5a5a00000005",
+            "li s8, 0x5a5a5a5a00000006",
+            "li s9, 0x5a5a5a5a00000007",
+            "li s10, 0x5a5a5a5a00000008",
+            "li s11, 0x5a5a5a5a00000009",
+            "li t3, 0x5a5a5a5a0000000a",
+            "li t4, 0x5a5a5a5a0000000b",
+            "li t5, 0x5a5a5a5a0000000c",
+            "li t6, 0x5a5a5a5a0000000d",
+            "li a3, 0x5a5a5a5a0000000e",
+            "li a4, 0x5a5a5a5a0000000f",
+            "li a5, 0x5a5a5a5a00000010",
+            "li a6, 0x5a5a5a5a00000011",
+            "ecall",
+            "li {bad}, 0",
+            "li {tmp}, 0x5a5a5a5a00000000",
+            "beq s2, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000001",
+            "beq s3, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000002",
+            "beq s4, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000003",
+            "beq s5, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000004",
+            "beq s6, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000005",
+            "beq s7, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000006",
+            "beq s8, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000007",
+            "beq s9, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000008",
+            "beq s10, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000009",
+            "beq s11, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000a",
+            "beq t3, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000b",
+            "beq t4, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000c",
+            "beq t5, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000d",
+            "beq t6, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000e",
+            "beq a3, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a0000000f",
+            "beq a4, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000010",
+            "beq a5, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            "li {tmp}, 0x5a5a5a5a00000011",
+            "beq a6, {tmp}, 1f",
+            "addi {bad}, {bad}, 1",
+            "1:",
+            bad = out(reg) bad,
+            tmp = out(reg) _,
+            inlateout("a0") 0usize => _,
+            in("a7") SYSCALL_YIELD,
+            out("s2") _,
+            out("s3") _,
+            out("s4") _,
+            out("s5") _,
+            out("s6") _,
+            out("s7") _,
+            out("s8") _,
+            out("s9") _,
+            out("s10") _,
+            out("s11") _,
+            out("t3") _,
+            out("t4") _,
+            out("t5") _,
+            out("t6") _,
+            out("a3") _,
+            out("a4") _,
+            out("a5") _,
+            out("a6") _,
+        );
+    }
+    bad
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    for _ in 0..100 {
+        assert_eq!(0, yield_and_count_clobbered());
+    }
+    // a pending signal, even a blocked one, sends the yield back through
+    // trap_return, with s0~s11 saved only on the way out
+    assert_eq!(sigprocmask(SignalFlags::SIGUSR1), 0);
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    for _ in 0..100 {
+        assert_eq!(0, yield_and_count_clobbered());
+    }
+    println!("Test register integrity OK!");
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sbrk, task_stats, TaskStats};
+
+/*
+理想结果：堆可以增长、读写和收缩，增长时不立即分配物理页，低于堆底的收缩返回 -1，
+输出 Test sbrk OK!
+*/
+
+/// Write the `len` bytes at `start` and check they read back.
+fn fill(start: isize, len: usize) {
+    let heap = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) };
+    for (i, byte) in heap.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for (i, byte) in heap.iter().enumerate() {
+        assert_eq!(*byte, i as u8);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let bottom = sbrk(0);
+    assert!(bottom > 0);
+    // shrinking below the initial heap base is refused and changes nothing
+    assert_eq!(-1, sbrk(-1));
+    assert_eq!(bottom, sbrk(0));
+
+    let len: isize = 4096 * 2 + 100;
+    // warm up so the code below is already loaded and its pages do not
+    // show up as resident halfway through
+    let mut stats = TaskStats::default();
+    assert_eq!(bottom, sbrk(len));
+    fill(bottom, len as usize);
+    assert_eq!(bottom + len, sbrk(-len));
+
+    assert_eq!(0, task_stats(&mut stats));
+    let resident = stats.resident_pages;
+    assert_eq!(bottom, sbrk(len));
+    assert_eq!(bottom + len, sbrk(0));
+    // the new heap pages only get frames once touched
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(stats.resident_pages, resident);
+    fill(bottom, len as usize);
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(stats.resident_pages, resident + 3);
+
+    assert_eq!(bottom + len, sbrk(-len));
+    assert_eq!(bottom, sbrk(0));
+    println!("Test sbrk OK!");
+    0
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sched_setscheduler, yield_, EINVAL, SCHED_MLFQ, SCHED_RR, SCHED_STRIDE};
+
+/*
+理想结果：三种调度策略都能切换，未知策略返回 -EINVAL，输出 Test sched policy OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sched_setscheduler(SCHED_RR), 0);
+    for _ in 0..10 {
+        yield_();
+    }
+    assert_eq!(sched_setscheduler(SCHED_MLFQ), 0);
+    for _ in 0..10 {
+        yield_();
+    }
+    // back to stride, what the kernel boots with by default
+    assert_eq!(sched_setscheduler(SCHED_STRIDE), 0);
+    for _ in 0..10 {
+        yield_();
+    }
+    assert_eq!(sched_setscheduler(3), -EINVAL);
+    println!("Test sched policy OK!");
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, semaphore_create, semaphore_down, semaphore_up, waitpid, yield_, EINVAL,
+    MAP_SHARED,
+};
+
+/*
+理想结果：子任务在信号量保护下轮流累加共享计数且不丢失更新，父任务阻塞等到所有子任务完成，
+不存在的信号量返回 -EINVAL，输出 Test semaphore OK!
+*/
+
+const CHILDREN: usize = 3;
+const ROUNDS: usize = 20;
+
+#[no_mangle]
+fn main() -> i32 {
+    let counter: usize = 0x10000000;
+    assert_eq!(0, mmap(counter, 4096, 3 | MAP_SHARED));
+    let lock = semaphore_create(1);
+    let done = semaphore_create(0);
+    assert!(lock >= 0 && done >= 0 && lock != done);
+    let (lock, done) = (lock as usize, done as usize);
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            for _ in 0..ROUNDS {
+                assert_eq!(semaphore_down(lock), 0);
+                let value = unsafe { (counter as *const usize).read_volatile() };
+                // let the others run in the middle of the update
+                yield_();
+                unsafe { (counter as *mut usize).write_volatile(value + 1) };
+                semaphore_up(lock);
+            }
+            semaphore_up(done);
+            exit(0);
+        }
+    }
+    for _ in 0..CHILDREN {
+        assert_eq!(semaphore_down(done), 0);
+    }
+    let total = unsafe { (counter as *const usize).read_volatile() };
+    assert_eq!(total, CHILDREN * ROUNDS);
+    for pid in pids {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    assert_eq!(semaphore_down(done + 100), -EINVAL);
+    println!("Test semaphore OK!");
+    0
+}
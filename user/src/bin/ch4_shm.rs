@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, shmat, shmdt, shmget, waitpid, EEXIST, EINVAL, ENOENT, IPC_CREAT, IPC_EXCL, IPC_PRIVATE,
+};
+
+/*
+理想结果：子任务按 key 找到同一个共享内存段，挂到别的地址写入后父任务直接读到；
+最后一次卸下后段被销毁，输出 Test shm OK!
+*/
+
+const KEY: usize = 0x7368_6d31;
+
+#[no_mangle]
+fn main() -> i32 {
+    let page: usize = 4096;
+    assert_eq!(shmget(KEY, page, 0), -ENOENT);
+    assert_eq!(shmget(KEY, 0, IPC_CREAT), -EINVAL);
+    let id = shmget(KEY, page * 2, IPC_CREAT);
+    assert!(id > 0);
+    let id = id as usize;
+    assert_eq!(shmget(KEY, page, IPC_CREAT), id as isize);
+    assert_eq!(shmget(KEY, page, IPC_CREAT | IPC_EXCL), -EEXIST);
+    assert_eq!(shmget(KEY, page * 3, 0), -EINVAL);
+    // a private segment is new every time, gone once it was attached and detached
+    let private = shmget(IPC_PRIVATE, page, 0);
+    assert!(private > 0 && private as usize != id);
+    let addr = shmat(private as usize, 0, 0);
+    assert!(addr > 0);
+    assert_eq!(shmdt(addr as usize), 0);
+    assert_eq!(shmat(private as usize, 0, 0), -EINVAL);
+
+    let ours: usize = 0x10000000;
+    assert_eq!(shmat(id, ours, 0), ours as isize);
+    // the pages are taken now
+    assert_eq!(shmat(id, ours + page, 0), -EINVAL);
+    unsafe { (ours as *mut usize).write_volatile(1) };
+    let pid = fork();
+    if pid == 0 {
+        let id = shmget(KEY, page, 0) as usize;
+        let theirs = shmat(id, 0, 0);
+        assert!(theirs > 0 && theirs as usize != ours);
+        let theirs = theirs as usize;
+        unsafe {
+            assert_eq!((theirs as *const usize).read_volatile(), 1);
+            ((theirs + page) as *mut usize).write_volatile(2);
+        }
+        assert_eq!(shmdt(theirs), 0);
+        assert_eq!(shmdt(theirs), -EINVAL);
+        return 0;
+    }
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        unsafe { ((ours + page) as *const usize).read_volatile() },
+        2
+    );
+    assert_eq!(shmdt(ours + page), -EINVAL);
+    assert_eq!(shmdt(ours), 0);
+    // nobody has it attached any more
+    assert_eq!(shmget(KEY, page, 0), -ENOENT);
+    println!("Test shm OK!");
+    0
+}
@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, sigreturn, sleep_blocking, waitpid,
+    yield_, SignalAction, SignalFlags, EINVAL, ESRCH, SIGCONT, SIGKILL, SIGSTOP, SIGUSR1,
+};
+
+/*
+理想结果：处理函数在 kill 返回前运行并回到原处，屏蔽的信号在解除屏蔽后才送达，
+SIGKILL 不能被捕获并能杀死阻塞中的子进程，输出 Test signal OK!
+*/
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+fn handler(signum: usize) {
+    HANDLED.store(signum, Ordering::SeqCst);
+    sigreturn();
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let action = SignalAction {
+        handler: handler as usize,
+        mask: SignalFlags::empty(),
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    let pid = getpid() as usize;
+    // the handler runs on the way back from kill, kill still returns 0
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(HANDLED.swap(0, Ordering::SeqCst), SIGUSR1);
+
+    // a blocked signal waits until it is unblocked
+    sigprocmask(SignalFlags::SIGUSR1);
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 0);
+    assert_eq!(sigprocmask(SignalFlags::empty()), SignalFlags::SIGUSR1.bits() as isize);
+    assert_eq!(HANDLED.swap(0, Ordering::SeqCst), SIGUSR1);
+
+    // SIGKILL and SIGSTOP keep their fixed behavior
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), -EINVAL);
+    assert_eq!(sigaction(SIGSTOP, Some(&action), None), -EINVAL);
+    assert_eq!(kill(pid, 0), -EINVAL);
+    assert_eq!(kill(usize::MAX, SIGUSR1), -ESRCH);
+
+    // a child blocked in sleep dies of SIGKILL right away
+    let child = fork();
+    if child == 0 {
+        sleep_blocking(1_000_000);
+        exit(0);
+    }
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -(SIGKILL as i32));
+
+    // a stopped child comes back on SIGCONT and still handles its signals
+    let child = fork();
+    if child == 0 {
+        while HANDLED.load(Ordering::SeqCst) != SIGUSR1 {
+            yield_();
+        }
+        exit(7);
+    }
+    assert_eq!(kill(child as usize, SIGSTOP), 0);
+    yield_();
+    assert_eq!(kill(child as usize, SIGCONT), 0);
+    assert_eq!(kill(child as usize, SIGUSR1), 0);
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 7);
+    println!("Test signal OK!");
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, sleep_blocking};
+
+/*
+理想结果：睡眠 100ms 后经过的时间不少于 100ms，且最多多出几个时钟周期，输出 Test sleep OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start = get_time();
+    sleep_blocking(100);
+    let elapsed = get_time() - start;
+    println!("slept for {}ms", elapsed);
+    assert!(elapsed >= 100);
+    // one tick is 10ms, waking up may be late by about two ticks
+    assert!(elapsed <= 100 + 30);
+    println!("Test sleep OK!");
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, spawn, waitpid};
+
+/*
+理想结果：成功启动 ch2b_hello_world 并等到它正常退出，找不到的应用返回 -1，输出 Test spawn OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = spawn("ch2b_hello_world\0");
+    assert!(pid >= 0, "spawn failed");
+    assert_ne!(pid, getpid());
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(spawn("no_such_app\0"), -1);
+    println!("Test spawn OK!");
+    0
+}
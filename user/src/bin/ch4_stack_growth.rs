@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid};
+
+/*
+理想结果：使用超过初始 8 KiB 的栈时栈自动增长；子任务无限递归越过栈上限后被内核杀死（退出码 -2），
+内核不崩溃，输出 Test stack growth OK!
+*/
+
+/// Use about `depth` KiB of stack.
+#[inline(never)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    unsafe { (&mut frame as *mut [u8; 1024]).write_volatile([depth as u8; 1024]) };
+    if depth == 0 {
+        return frame[0] as usize;
+    }
+    recurse(depth - 1) + unsafe { (&frame as *const [u8; 1024]).read_volatile()[1023] } as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // well past the initial stack, well below the limit
+    let sum = recurse(32);
+    assert_eq!(sum, (0..=32).sum::<usize>());
+    let pid = fork();
+    if pid == 0 {
+        recurse(usize::MAX);
+        panic!("should have been killed");
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+    println!("Test stack growth OK!");
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpagesize, getpid, strace, ESRCH};
+
+/*
+理想结果：内核打印本任务 getpagesize 与 strace 的跟踪行，输出 Test strace OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid() as usize;
+    assert_eq!(strace(usize::MAX, true), -ESRCH);
+    assert_eq!(strace(pid, true), 0);
+    // logged as "getpagesize() = 4096"
+    assert_eq!(getpagesize(), 4096);
+    // logged as "strace(..., 0) = 0", the call turning it off still counts
+    assert_eq!(strace(pid, false), 0);
+    println!("Test strace OK!");
+    0
+}
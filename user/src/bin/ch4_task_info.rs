@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    get_time, task_info, task_stats, yield_, TaskInfo, TaskStats, TaskStatus, SYSCALL_GETTIMEOFDAY,
+    SYSCALL_TASK_INFO, SYSCALL_WRITE, SYSCALL_YIELD,
+};
+
+/*
+理想结果：task_info 返回的状态、系统调用次数与时间互相一致，输出 Test task info snapshot OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let t1 = get_time() as usize;
+    get_time();
+    get_time();
+    yield_();
+    yield_();
+    let info = TaskInfo::new();
+    let mut stats = TaskStats::default();
+    // cpu time read first can only be behind the time read after it
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(0, task_info(&info));
+    let t2 = get_time() as usize;
+    assert!(info.status == TaskStatus::Running);
+    assert_eq!(3, info.syscall_times[SYSCALL_GETTIMEOFDAY]);
+    assert_eq!(2, info.syscall_times[SYSCALL_YIELD]);
+    assert_eq!(1, info.syscall_times[SYSCALL_TASK_INFO]);
+    assert_eq!(0, info.syscall_times[SYSCALL_WRITE]);
+    assert!(info.time < t2 - t1 + 100);
+    assert!(stats.cpu_time <= info.time);
+    println!("Test task info snapshot OK!");
+    0
+}
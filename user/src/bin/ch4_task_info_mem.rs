@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap, task_stats, TaskStats};
+
+/*
+理想结果：mmap 只增加一个区域，写过的页才常驻，munmap 后常驻页减少而峰值不变，
+输出 Test task info memory OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 4;
+    // warm up so the code below is already loaded and its pages do not
+    // show up as resident halfway through
+    assert_eq!(0, mmap(start, len, 3));
+    unsafe { (start as *mut usize).write_volatile(0) };
+    assert_eq!(0, munmap(start, len));
+
+    // one buffer for every snapshot, so the stack does not grow in between
+    let mut stats = TaskStats::default();
+    assert_eq!(0, task_stats(&mut stats));
+    let (resident, areas) = (stats.resident_pages, stats.map_areas);
+    assert!(resident > 0);
+    assert!(stats.peak_resident_pages >= resident);
+
+    assert_eq!(0, mmap(start, len, 3));
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(stats.map_areas, areas + 1);
+    assert_eq!(stats.resident_pages, resident);
+
+    // two of the four pages get written
+    for page in 0..2 {
+        unsafe { ((start + page * 4096) as *mut usize).write_volatile(page) };
+    }
+    assert_eq!(0, task_stats(&mut stats));
+    let touched = stats.resident_pages;
+    assert!(touched >= resident + 2);
+    assert!(stats.peak_resident_pages >= touched);
+
+    assert_eq!(0, munmap(start, len));
+    assert_eq!(0, task_stats(&mut stats));
+    assert_eq!(stats.map_areas, areas);
+    assert_eq!(stats.resident_pages, touched - 2);
+    assert!(stats.peak_resident_pages >= touched);
+    println!("Test task info memory OK!");
+    0
+}
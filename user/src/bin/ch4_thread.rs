@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpid, gettid, mutex_blocking_create, mutex_lock, mutex_unlock, thread_create,
+    waitpid, waittid, yield_,
+};
+
+/*
+理想结果：线程共享地址空间，在互斥锁保护下累加同一个计数不丢失更新，getpid 相同而
+gettid 各不相同，waittid 拿到各线程的退出码；主线程退出时还在运行的线程被一并杀死，
+输出 Test thread OK!
+*/
+
+const THREADS: usize = 3;
+const ROUNDS: usize = 10;
+
+static mut COUNTER: usize = 0;
+static mut MUTEX: usize = 0;
+static mut MAIN_PID: isize = 0;
+
+fn add(tid: usize) -> ! {
+    unsafe {
+        assert_eq!(gettid() as usize, tid);
+        assert_eq!(getpid(), MAIN_PID);
+        for _ in 0..ROUNDS {
+            assert_eq!(mutex_lock(MUTEX), 0);
+            let value = (&COUNTER as *const usize).read_volatile();
+            // let the others run in the middle of the update
+            yield_();
+            (&mut COUNTER as *mut usize).write_volatile(value + 1);
+            assert_eq!(mutex_unlock(MUTEX), 0);
+        }
+    }
+    exit(100 + tid as i32)
+}
+
+fn spin(_arg: usize) -> ! {
+    loop {
+        yield_();
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(gettid(), 0);
+    unsafe {
+        MAIN_PID = getpid();
+        MUTEX = mutex_blocking_create() as usize;
+    }
+    let mut tids = [0isize; THREADS];
+    for (i, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(add as usize, i + 1);
+        assert_eq!(*tid, i as isize + 1);
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 100 + tid);
+    }
+    assert_eq!(
+        unsafe { (&COUNTER as *const usize).read_volatile() },
+        THREADS * ROUNDS
+    );
+    // joined already, never existed, or ourselves
+    assert_eq!(waittid(1), -1);
+    assert_eq!(waittid(THREADS + 1), -1);
+    assert_eq!(waittid(0), -1);
+
+    // a thread still running when its process exits dies with it
+    let pid = fork();
+    if pid == 0 {
+        assert!(thread_create(spin as usize, 0) > 0);
+        yield_();
+        exit(7);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+    println!("Test thread OK!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{exit, fork, get_time, getpid, task_stats, times, waitpid, TaskStats, Tms};
+
+/*
+理想结果：用户态计算计入 utime，系统调用计入 stime，两者之和不超过 cpu_time，
+回收子进程后它的时间计入 cutime/cstime，输出 Test times OK!
+*/
+
+static SPIN: AtomicUsize = AtomicUsize::new(0);
+
+/// Compute in user mode for about `ms` milliseconds.
+fn spin(ms: isize) {
+    let start = get_time();
+    while get_time() < start + ms {
+        for _ in 0..10_000 {
+            SPIN.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut tms = Tms::default();
+    spin(50);
+    for _ in 0..1000 {
+        getpid();
+    }
+    assert_eq!(times(&mut tms), 0);
+    assert!(tms.utime > 0);
+    assert!(tms.stime > 0);
+    assert_eq!((tms.cutime, tms.cstime), (0, 0));
+
+    let mut stats = TaskStats::default();
+    assert_eq!(task_stats(&mut stats), 0);
+    assert!(stats.user_time + stats.kernel_time <= stats.cpu_time);
+
+    let pid = fork();
+    if pid == 0 {
+        spin(20);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(times(&mut tms), 0);
+    assert!(tms.cutime > 0);
+    println!(
+        "utime = {}us, stime = {}us, cutime = {}us, cstime = {}us",
+        tms.utime, tms.stime, tms.cutime, tms.cstime
+    );
+    println!("Test times OK!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    getpagesize, getpid, read_trace, TraceRecord, TRACE_SYSCALL_ENTER, TRACE_SYSCALL_EXIT,
+};
+
+/*
+理想结果：跟踪记录里按顺序出现本任务 getpagesize 的进入与返回，输出 Test trace OK!
+*/
+
+const SYSCALL_GETPAGESIZE: usize = 413;
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid() as usize;
+    let mut records = [TraceRecord::default(); 32];
+    // throw away what was traced so far
+    while read_trace(&mut records) > 0 {}
+    let page_size = getpagesize();
+    let mut seen = [0usize; 2];
+    let mut found = 0;
+    loop {
+        let count = read_trace(&mut records);
+        assert!(count >= 0);
+        if count == 0 {
+            break;
+        }
+        for record in records[..count as usize].iter() {
+            let point = record.point;
+            if record.task == pid
+                && record.args[0] == SYSCALL_GETPAGESIZE
+                && (point == TRACE_SYSCALL_ENTER || point == TRACE_SYSCALL_EXIT)
+            {
+                if point == TRACE_SYSCALL_EXIT {
+                    assert_eq!(record.args[1], page_size);
+                }
+                if found < 2 {
+                    seen[found] = point;
+                }
+                found += 1;
+            }
+        }
+    }
+    assert_eq!(found, 2);
+    assert_eq!(seen, [TRACE_SYSCALL_ENTER, TRACE_SYSCALL_EXIT]);
+    println!("Test trace OK!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{getpid, spawn, wait4, Rusage, ECHILD, EINVAL, WNOHANG};
+
+/*
+理想结果：等待自己、不存在的任务或没有子任务时返回 -ECHILD；WNOHANG 对未退出的子任务返回 0，
+对已退出的子任务返回其编号并填好 rusage，回收之后再等待返回 -ECHILD，输出 Test wait4 OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid();
+    let mut exit_code = 0;
+    let mut rusage = Rusage::default();
+    assert_eq!(wait4(pid, &mut exit_code, 0, &mut rusage), -ECHILD);
+    assert_eq!(wait4(-1, &mut exit_code, 0, &mut rusage), -ECHILD);
+    assert_eq!(wait4(pid + 1, &mut exit_code, 2, &mut rusage), -EINVAL);
+    let child = spawn("ch2b_hello_world\0");
+    assert!(child > 0);
+    // the child has not been scheduled yet unless we got preempted
+    match wait4(child, &mut exit_code, WNOHANG, &mut rusage) {
+        0 => println!("task {} is still running", child),
+        ret => assert_eq!(ret, child),
+    }
+    assert_eq!(wait4(child, &mut exit_code, 0, &mut rusage), child);
+    println!(
+        "task {} exited with {}, cpu time {}.{:06}s, {} frames",
+        child, exit_code, rusage.cpu_time.sec, rusage.cpu_time.usec, rusage.frames
+    );
+    assert_eq!(exit_code, 0);
+    assert!(rusage.frames > 0);
+    // a reaped child is gone for good
+    assert_eq!(wait4(child, &mut exit_code, WNOHANG, &mut rusage), -ECHILD);
+    println!("Test wait4 OK!");
+    0
+}
@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+/*
+理想结果：该程序被内核杀死并输出 StorePageFault @ va=... (permission denied)，其余程序正常运行结束
+*/
+
+static READ_ONLY: u8 = 42;
+
+#[no_mangle]
+pub fn main() -> isize {
+    // the page is mapped, but only readable
+    unsafe {
+        (core::ptr::addr_of!(READ_ONLY) as *mut u8).write_volatile(0);
+    }
+    panic!("FAIL: T.T\n");
+}
@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::yield_;
+
+/*
+理想结果：内核日志中出现 possible livelock 警告，输出 Test yield spin OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // far more yields than YIELD_LIVELOCK_THRESHOLD without doing any work
+    for _ in 0..1000 {
+        yield_();
+    }
+    println!("Test yield spin OK!");
+    0
+}
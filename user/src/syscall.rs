@@ -1,4 +1,4 @@
-use crate::TaskInfo;
+use crate::{Event, MemInfo, MemStat, Rusage, SignalAction, TaskInfo, TaskStats, Tms, TraceRecord};
 
 use super::{Stat, TimeVal};
 
@@ -10,23 +10,45 @@ pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
 pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_EXIT: usize = 93;
+pub const SYSCALL_FUTEX: usize = 98;
 pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_TIMES: usize = 153;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
+pub const SYSCALL_GETPPID: usize = 173;
 pub const SYSCALL_GETTID: usize = 178;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
+pub const SYSCALL_SBRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_MEMINFO: usize = 411;
+pub const SYSCALL_MEM_STAT: usize = 412;
+pub const SYSCALL_GETPAGESIZE: usize = 413;
+pub const SYSCALL_READ_EVENTLOG: usize = 414;
+pub const SYSCALL_SET_BATCH: usize = 415;
+pub const SYSCALL_READ_TRACE: usize = 416;
+pub const SYSCALL_STRACE: usize = 417;
+pub const SYSCALL_TASK_STATS: usize = 418;
+pub const SYSCALL_MMAP_FILE: usize = 419;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -39,6 +61,7 @@ pub const SYSCALL_SEMAPHORE_DOWN: usize = 470;
 pub const SYSCALL_CONDVAR_CREATE: usize = 471;
 pub const SYSCALL_CONDVAR_SIGNAL: usize = 472;
 pub const SYSCALL_CONDVAR_WAIT: usize = 473;
+pub const SYSCALL_CONDVAR_BROADCAST: usize = 474;
 
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -146,6 +169,10 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_futex(addr: *const u32, op: usize, val: usize) -> isize {
+    syscall(SYSCALL_FUTEX, [addr as usize, op, val])
+}
+
 pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
@@ -162,6 +189,10 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -173,22 +204,68 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
+pub fn sys_execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envs.as_ptr() as usize,
+        ],
+    )
+}
+
 pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+    sys_wait4(pid, xstatus, 0, core::ptr::null_mut())
+}
+
+pub fn sys_wait4(pid: isize, xstatus: *mut i32, options: usize, rusage: *mut Rusage) -> isize {
+    syscall6(
+        SYSCALL_WAITPID,
+        [pid as usize, xstatus as usize, options, rusage as usize, 0, 0],
+    )
 }
 
 pub fn sys_set_priority(prio: isize) -> isize {
     syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
 }
 
+pub fn sys_sched_setscheduler(policy: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETSCHEDULER, [policy, 0, 0])
+}
+
 pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
     syscall(SYSCALL_MMAP, [start, len, prot])
 }
 
+pub fn sys_mmap_file(start: usize, len: usize, prot: usize, fd: isize, offset: usize) -> isize {
+    syscall6(SYSCALL_MMAP_FILE, [start, len, prot, fd as usize, offset, 0])
+}
+
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, addr, flags])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
+pub fn sys_sbrk(increment: isize) -> isize {
+    syscall(SYSCALL_SBRK, [increment as usize, 0, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -201,10 +278,75 @@ pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
 
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum, 0])
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [mask as usize, 0, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_times(tms: *mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as usize, 0, 0])
+}
+
 pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+pub fn sys_task_stats(stats: &mut TaskStats) -> isize {
+    syscall(SYSCALL_TASK_STATS, [stats as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_meminfo(info: &mut MemInfo) -> isize {
+    syscall(SYSCALL_MEMINFO, [info as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_mem_stat(stat: &mut MemStat) -> isize {
+    syscall(SYSCALL_MEM_STAT, [stat as *mut _ as usize, 0, 0])
+}
+
+pub fn sys_read_eventlog(buf: &mut [Event]) -> isize {
+    syscall(
+        SYSCALL_READ_EVENTLOG,
+        [buf.as_mut_ptr() as usize, buf.len(), 0],
+    )
+}
+
+pub fn sys_read_trace(buf: &mut [TraceRecord]) -> isize {
+    syscall(
+        SYSCALL_READ_TRACE,
+        [buf.as_mut_ptr() as usize, buf.len(), 0],
+    )
+}
+
+pub fn sys_strace(pid: usize, enable: bool) -> isize {
+    syscall(SYSCALL_STRACE, [pid, enable as usize, 0])
+}
+
+pub fn sys_set_batch(batch: bool) -> isize {
+    syscall(SYSCALL_SET_BATCH, [batch as usize, 0, 0])
+}
+
+pub fn sys_getpagesize() -> isize {
+    syscall(SYSCALL_GETPAGESIZE, [0, 0, 0])
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }
@@ -256,3 +398,7 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_BROADCAST, [condvar_id, 0, 0])
+}